@@ -11,15 +11,43 @@ pub(crate) const CORE_CHART_NAME: &str = "mayastor";
 /// This is the shared Pod label of the <helm-release>-io-engine DaemonSet.
 pub(crate) const IO_ENGINE_LABEL: &str = "app=io-engine";
 
+/// This is the name of the io-engine container in the <helm-release>-io-engine DaemonSet Pods.
+pub(crate) const IO_ENGINE_CONTAINER_NAME: &str = "io-engine";
+
 /// This is the shared Pod label of the <helm-release>-agent-core Deployment.
 pub(crate) const AGENT_CORE_LABEL: &str = "app=agent-core";
 
 /// This is the shared label across the helm chart components which carries the chart version.
 pub(crate) const CHART_VERSION_LABEL_KEY: &str = "openebs.io/version";
 
+/// This is the annotation set on an io-engine StatefulSet while its Pods are upgraded one Node at a
+/// time, with the update strategy which the StatefulSet had before the upgrade, as JSON.
+pub(crate) const UPDATE_STRATEGY_ANNOTATION_KEY: &str = "openebs.io/upgrade-update-strategy";
+
+/// This is the annotation set on the upgrade Job on completion, with the outcome of the upgrade,
+/// i.e. 'Succeeded' or 'Failed'.
+pub(crate) const UPGRADE_STATUS_ANNOTATION_KEY: &str = "openebs.io/upgrade-status";
+
+/// This is the annotation set on the upgrade Job on completion, with the version upgraded from.
+pub(crate) const UPGRADE_FROM_VERSION_ANNOTATION_KEY: &str = "openebs.io/upgrade-from-version";
+
+/// This is the annotation set on the upgrade Job on completion, with the version upgraded to.
+pub(crate) const UPGRADE_TO_VERSION_ANNOTATION_KEY: &str = "openebs.io/upgrade-to-version";
+
+/// This is the name of the CSI driver which provisions the PersistentVolumes of this project.
+pub(crate) const CSI_DRIVER_NAME: &str = "io.openebs.csi-mayastor";
+
 /// This is the label set on a storage API Node resource when a 'Node Drain' is issued.
 pub(crate) const DRAIN_FOR_UPGRADE: &str = "mayastor-upgrade";
 
+/// This is the label set on a storage API Node resource when a 'Node Cordon' is issued for the
+/// duration of the data-plane upgrade, with the batch cordon strategy.
+pub(crate) const CORDON_FOR_UPGRADE: &str = "mayastor-upgrade-cordon";
+
+/// This is the name which the Helm repository is added with, to pull the core Helm chart from, if
+/// no other name is given.
+pub(crate) const UPGRADE_HELM_REPO_NAME: &str = "mayastor-upgrade";
+
 /// This is the allowed upgrade to-version/to-version-range for the Umbrella chart.
 pub(crate) const TO_UMBRELLA_SEMVER: &str = "3.9.0";
 
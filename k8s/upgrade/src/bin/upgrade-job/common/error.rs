@@ -5,8 +5,8 @@ use crate::{
     },
     events::event_recorder::EventNote,
 };
-use snafu::Snafu;
-use std::path::PathBuf;
+use snafu::{IntoError, Snafu};
+use std::{path::PathBuf, time::Duration};
 use url::Url;
 
 /// For use with multiple fallible operations which may fail for different reasons, but are
@@ -16,6 +16,31 @@ use url::Url;
 #[snafu(visibility(pub(crate)))]
 #[snafu(context(suffix(false)))]
 pub(crate) enum Error {
+    /// Error for when the storage REST API URL is needed but was not set.
+    #[snafu(display(
+        "The {} REST API URL is required for this upgrade, but '--rest-endpoint' is not set",
+        PRODUCT
+    ))]
+    RestEndpointAbsent,
+
+    /// Error for when the storage REST API's Nodes are not the Nodes of the io-engine Pods of the
+    /// helm release.
+    #[snafu(display(
+        "The {} REST API at {} does not belong to the release in namespace {}: none of its Nodes \
+        {:?} run the io-engine Pods of the release, which are on Nodes {:?}",
+        PRODUCT,
+        rest_endpoint,
+        namespace,
+        storage_nodes,
+        io_engine_nodes
+    ))]
+    RestEndpointMismatch {
+        rest_endpoint: String,
+        namespace: String,
+        storage_nodes: Vec<String>,
+        io_engine_nodes: Vec<String>,
+    },
+
     /// Error for when the storage REST API URL is parsed.
     #[snafu(display(
         "Failed to parse {} REST API URL {}: {}",
@@ -28,6 +53,27 @@ pub(crate) enum Error {
         rest_endpoint: String,
     },
 
+    /// Error for when the storage REST API URL's scheme is neither 'http' nor 'https'.
+    #[snafu(display(
+        "Invalid scheme '{}' in {} REST API URL {}: only 'http' and 'https' are supported",
+        scheme,
+        PRODUCT,
+        rest_endpoint
+    ))]
+    RestUrlScheme {
+        rest_endpoint: String,
+        scheme: String,
+    },
+
+    /// Error for when the storage REST API URL is not a bare base URL.
+    #[snafu(display(
+        "{} REST API URL {} has the path '{}': only the scheme, host and port may be specified",
+        PRODUCT,
+        rest_endpoint,
+        path
+    ))]
+    RestUrlHasPath { rest_endpoint: String, path: String },
+
     /// Error for when Kubernetes API client generation fails.
     #[snafu(display("Failed to generate kubernetes client: {}", source))]
     K8sClientGeneration { source: kube_client::Error },
@@ -172,6 +218,10 @@ pub(crate) enum Error {
     #[snafu(display("{} is not a directory", path.display()))]
     NotADirectory { path: PathBuf },
 
+    /// Error for when the helm binary is not executable.
+    #[snafu(display("The helm binary {} is not executable", path.display()))]
+    HelmBinaryNotExecutable { path: PathBuf },
+
     /// Error for when the path is not that of a file.
     #[snafu(display("{} is not a file", path.display()))]
     NotAFile { path: PathBuf },
@@ -183,6 +233,21 @@ pub(crate) enum Error {
         filepath: PathBuf,
     },
 
+    /// Error when writing a file.
+    #[snafu(display("Failed to write to file {}: {}", filepath.display(), source))]
+    WritingFile {
+        source: std::io::Error,
+        filepath: PathBuf,
+    },
+
+    /// Error for when the upgrade's progress could not be serialized for the state file.
+    #[snafu(display("Failed to serialize the upgrade progress: {}", source))]
+    SerializeCheckpoint { source: serde_yaml::Error },
+
+    /// Error for when the upgrade's summary could not be serialized.
+    #[snafu(display("Failed to serialize the upgrade summary: {}", source))]
+    SerializeUpgradeSummary { source: serde_json::Error },
+
     /// Error for when the helm chart found in a path is not of the correct variant.
     #[snafu(display("Failed to find valid Helm chart in path {}", path.display()))]
     FindingHelmChart { path: PathBuf },
@@ -230,6 +295,54 @@ pub(crate) enum Error {
         namespace: String,
     },
 
+    /// Error for when a Kubernetes API request for GET-ing a list of PersistentVolumeClaims fails.
+    #[snafu(display("Failed to list PersistentVolumeClaims in {}: {}", scope, source))]
+    ListPersistentVolumeClaims { source: kube::Error, scope: String },
+
+    /// Error for when a Kubernetes API request for GET-ing a list of PodDisruptionBudgets fails.
+    #[snafu(display(
+        "Failed to list PodDisruptionBudgets in namespace {}: {}",
+        namespace,
+        source
+    ))]
+    ListPodDisruptionBudgets {
+        source: kube::Error,
+        namespace: String,
+    },
+
+    /// Error for when the PodDisruptionBudgets which select an io-engine Pod do not allow
+    /// disruptions within the PodDisruptionBudget wait timeout.
+    #[snafu(display(
+        "Timed out after {:?} waiting for the PodDisruptionBudgets {:?} to allow disruptions",
+        timeout,
+        pdbs
+    ))]
+    PdbWaitTimeout {
+        pdbs: Vec<String>,
+        timeout: Duration,
+    },
+
+    /// Error for when PersistentVolumeClaims which were Bound before the upgrade are not Bound
+    /// after it.
+    #[snafu(display(
+        "PersistentVolumeClaims are no longer Bound after the upgrade: {}",
+        pvcs.join(", ")
+    ))]
+    PvcBindingUnhealthy { pvcs: Vec<String> },
+
+    /// Error for when volumes are not Online or are rebuilding, or io-engine Pods are not Ready,
+    /// after the upgrade.
+    #[snafu(display(
+        "The cluster is unhealthy after the upgrade, unhealthy volumes: [{}], io-engine Pods \
+        which are not Ready: [{}]",
+        volumes.join(", "),
+        pods.join(", ")
+    ))]
+    PostUpgradeUnhealthy {
+        volumes: Vec<String>,
+        pods: Vec<String>,
+    },
+
     /// Error for when a Pod does not have a PodSpec struct member.
     #[snafu(display("Failed get .spec from Pod {} in Namespace {}", name, namespace))]
     EmptyPodSpec { name: String, namespace: String },
@@ -257,6 +370,38 @@ pub(crate) enum Error {
         node_id: String,
     },
 
+    /// Error for when a storage node is still cordoned or drained after it has been uncordoned.
+    #[snafu(display(
+        "{} Node {} is not schedulable after uncordon, it has the cordon or drain labels {:?}",
+        PRODUCT,
+        node_id,
+        labels
+    ))]
+    StorageNodeNotSchedulable {
+        node_id: String,
+        labels: Vec<String>,
+    },
+
+    /// Error for when an io-engine Pod is in CrashLoopBackOff before its Node is drained.
+    #[snafu(display(
+        "io-engine Pod '{}' in namespace '{}' on Node '{}' is in CrashLoopBackOff",
+        pod_name,
+        namespace,
+        node_name
+    ))]
+    IoEnginePodCrashLoopBackOff {
+        pod_name: String,
+        namespace: String,
+        node_name: String,
+    },
+
+    /// Error for when a cordon request for a storage node fails.
+    #[snafu(display("Failed to cordon {} Node {}: {}", PRODUCT, node_id, source))]
+    StorageNodeCordon {
+        source: openapi::tower::client::Error<openapi::models::RestJsonError>,
+        node_id: String,
+    },
+
     /// Error for when an Pod-delete Kubernetes API request fails.
     #[snafu(display("Failed get delete Pod {} from Node {}: {}", name, node, source))]
     PodDelete {
@@ -265,6 +410,76 @@ pub(crate) enum Error {
         node: String,
     },
 
+    /// Error for when the update strategy of a StatefulSet cannot be set to 'OnDelete'.
+    #[snafu(display(
+        "Failed to set the OnDelete update strategy on StatefulSet '{}' in namespace {}: {}",
+        name,
+        namespace,
+        source
+    ))]
+    PatchStatefulSetUpdateStrategy {
+        source: kube::Error,
+        name: String,
+        namespace: String,
+    },
+
+    /// Error for when the StatefulSets could not be listed.
+    #[snafu(display("Failed to list StatefulSets in namespace {}: {}", namespace, source))]
+    ListStatefulSets {
+        source: kube::Error,
+        namespace: String,
+    },
+
+    /// Error for when the update strategy of a StatefulSet could not be recorded.
+    #[snafu(display(
+        "Failed to serialize the update strategy of StatefulSet '{}': {}",
+        name,
+        source
+    ))]
+    SerializeUpdateStrategy {
+        source: serde_json::Error,
+        name: String,
+    },
+
+    /// Error for when the update strategy recorded on a StatefulSet could not be parsed.
+    #[snafu(display(
+        "Failed to parse the update strategy recorded on StatefulSet '{}': {}",
+        name,
+        source
+    ))]
+    ParseUpdateStrategy {
+        source: serde_json::Error,
+        name: String,
+    },
+
+    /// Error for when the update strategy of a StatefulSet could not be restored.
+    #[snafu(display(
+        "Failed to restore the update strategy of StatefulSet '{}' in namespace {}: {}",
+        name,
+        namespace,
+        source
+    ))]
+    RestoreStatefulSetUpdateStrategy {
+        source: kube::Error,
+        name: String,
+        namespace: String,
+    },
+
+    /// Error for when an io-engine Pod is not controlled by a DaemonSet or a StatefulSet, so it
+    /// is not known how to restart it with the upgraded Pod template.
+    #[snafu(display(
+        "Pod {} in namespace {} is controlled by {}, not by a DaemonSet or a StatefulSet, so it \
+        cannot be restarted with the upgraded Pod template",
+        pod_name,
+        namespace,
+        controller
+    ))]
+    UnknownIoEnginePodController {
+        pod_name: String,
+        namespace: String,
+        controller: String,
+    },
+
     /// Error for when listing storage nodes fails.
     #[snafu(display("Failed to list {} Nodes: {}", PRODUCT, source))]
     ListStorageNodes {
@@ -278,6 +493,21 @@ pub(crate) enum Error {
         node_id: String,
     },
 
+    /// Error for when the io-engine version reported by a storage node does not match the upgrade
+    /// target version.
+    #[snafu(display(
+        "{} Node {} reports io-engine version {}, expected version {}",
+        PRODUCT,
+        node_id,
+        reported_version,
+        expected_version
+    ))]
+    NodeVersionMismatch {
+        node_id: String,
+        reported_version: String,
+        expected_version: String,
+    },
+
     /// Error for when the storage node's Spec is empty.
     #[snafu(display("Failed to get {} Node {}", PRODUCT, node_id))]
     EmptyStorageNodeSpec { node_id: String },
@@ -288,6 +518,36 @@ pub(crate) enum Error {
         source: openapi::tower::client::Error<openapi::models::RestJsonError>,
     },
 
+    /// Error for when listing storage pools fails.
+    #[snafu(display("Failed to list {} Pools: {}", PRODUCT, source))]
+    ListStoragePools {
+        source: openapi::tower::client::Error<openapi::models::RestJsonError>,
+    },
+
+    /// Error for when listing storage replicas fails.
+    #[snafu(display("Failed to list {} Replicas: {}", PRODUCT, source))]
+    ListStorageReplicas {
+        source: openapi::tower::client::Error<openapi::models::RestJsonError>,
+    },
+
+    /// Error for when the pools on the other storage nodes do not have the free space to take on
+    /// the replicas of a storage node which is to be drained.
+    #[snafu(display(
+        "Insufficient spare capacity to drain {} Node {}: its replicas need {} bytes, the pools \
+        on the other Nodes have {} bytes free, a shortfall of {} bytes",
+        PRODUCT,
+        node_id,
+        required,
+        spare,
+        shortfall
+    ))]
+    InsufficientSpareCapacity {
+        node_id: String,
+        required: u64,
+        spare: u64,
+        shortfall: u64,
+    },
+
     /// Error for when a storage node drain request fails.
     #[snafu(display("Failed to drain {} Node {}: {}", PRODUCT, node_id, source))]
     DrainStorageNode {
@@ -295,6 +555,74 @@ pub(crate) enum Error {
         node_id: String,
     },
 
+    /// Error for when the volume targets on a storage Node don't move off of it within the
+    /// volume move timeout of the Node's drain.
+    #[snafu(display(
+        "Timed out after {:?} waiting for the drain of {} Node {}, the volumes {:?} did not move",
+        timeout,
+        PRODUCT,
+        node_id,
+        volumes
+    ))]
+    DrainTimedOut {
+        node_id: String,
+        volumes: Vec<String>,
+        timeout: Duration,
+    },
+
+    /// Error for when a storage Node is cordoned or drained other than by the upgrade, e.g. by an
+    /// operator for maintenance.
+    #[snafu(display(
+        "{} Node {} is {} with the labels {:?}, which were not added by the upgrade",
+        PRODUCT,
+        node_id,
+        state,
+        labels
+    ))]
+    StorageNodeInMaintenance {
+        node_id: String,
+        state: String,
+        labels: Vec<String>,
+    },
+
+    /// Error for when the upgrade is stopped, because the upgrade Job was asked to terminate or
+    /// lost the upgrade Lease.
+    #[snafu(display(
+        "The upgrade was stopped by a termination signal or the loss of the upgrade Lease"
+    ))]
+    UpgradeInterrupted,
+
+    /// Error for when a volume rebuild makes no progress for the rebuild stall timeout.
+    #[snafu(display(
+        "The rebuild of volume {} has been stuck at {}% for {:?}",
+        volume_uuid,
+        progress,
+        timeout
+    ))]
+    RebuildStalled {
+        volume_uuid: String,
+        progress: i64,
+        timeout: Duration,
+    },
+
+    /// Error for when the data-plane upgrade does not complete within the upgrade timeout.
+    #[snafu(display(
+        "Timed out after {:?} upgrading the data-plane, while upgrading the Nodes {:?}, the Nodes \
+        {:?} are already upgraded",
+        timeout,
+        upgrading_nodes,
+        upgraded_nodes
+    ))]
+    UpgradeTimeout {
+        timeout: Duration,
+        upgrading_nodes: Vec<String>,
+        upgraded_nodes: Vec<String>,
+    },
+
+    /// Error for when the metrics HTTP server cannot listen on the metrics port.
+    #[snafu(display("Failed to serve metrics on port {}: {}", port, source))]
+    MetricsServerBind { source: hyper::Error, port: u16 },
+
     /// Error for when the requested YAML key is invalid.
     #[snafu(display("Failed to parse YAML path {}", yaml_path))]
     YamlStructure { yaml_path: String },
@@ -307,17 +635,32 @@ pub(crate) enum Error {
     #[snafu(display("Failed to publish Event: {}", source))]
     EventPublish { source: kube_client::Error },
 
-    /// Error for when a Helm list command execution succeeds, but with an error.
+    /// Error for when the PATCH request for the annotations of the upgrade Job fails.
     #[snafu(display(
-        "`helm list` command return an error,\ncommand: {},\nargs: {:?},\nstd_err: {}",
+        "Failed to annotate Job '{}' in namespace {}: {}",
+        name,
+        namespace,
+        source
+    ))]
+    AnnotateJob {
+        source: kube::Error,
+        name: String,
+        namespace: String,
+    },
+
+    /// Error for when a Helm command runs, but exits with a non-zero exit code.
+    #[snafu(display(
+        "`helm` command failed with exit code {},\ncommand: {},\nargs: {:?},\nstderr: {}",
+        exit_code.map_or_else(|| "none (killed by a signal)".to_string(), |code| code.to_string()),
         command,
         args,
-        std_err,
+        stderr,
     ))]
-    HelmListCommand {
+    HelmCommandFailed {
         command: String,
         args: Vec<String>,
-        std_err: String,
+        stderr: String,
+        exit_code: Option<i32>,
     },
 
     /// Error for when a Helm version command execution succeeds, but with an error.
@@ -333,14 +676,14 @@ pub(crate) enum Error {
         std_err: String,
     },
 
-    /// Error for when a Helm upgrade command execution succeeds, but with an error.
+    /// Error for when a Helm rollback command execution succeeds, but with an error.
     #[snafu(display(
-        "`helm upgrade` command return an error,\ncommand: {},\nargs: {:?},\nstd_err: {}",
+        "`helm rollback` command return an error,\ncommand: {},\nargs: {:?},\nstd_err: {}",
         command,
         args,
         std_err,
     ))]
-    HelmUpgradeCommand {
+    HelmRollbackCommand {
         command: String,
         args: Vec<String>,
         std_err: String,
@@ -359,6 +702,32 @@ pub(crate) enum Error {
         std_err: String,
     },
 
+    /// Error for when a Helm repo add command execution succeeds, but with an error.
+    #[snafu(display(
+        "`helm repo add` command return an error,\ncommand: {},\nargs: {:?},\nstd_err: {}",
+        command,
+        args,
+        std_err,
+    ))]
+    HelmRepoAddCommand {
+        command: String,
+        args: Vec<String>,
+        std_err: String,
+    },
+
+    /// Error for when a Helm pull command execution succeeds, but with an error.
+    #[snafu(display(
+        "`helm pull` command return an error,\ncommand: {},\nargs: {:?},\nstd_err: {}",
+        command,
+        args,
+        std_err,
+    ))]
+    HelmPullCommand {
+        command: String,
+        args: Vec<String>,
+        std_err: String,
+    },
+
     /// Error for when detected helm chart name is not known helm chart.
     #[snafu(display(
         "'{}' is not a known {} helm chart, only helm charts '{}-<version-tag>' and '{}-<version-tag>' \
@@ -397,6 +766,31 @@ pub(crate) enum Error {
         version_string: String,
     },
 
+    /// Error for when the upgrade tries to move between phases in an order which is not allowed.
+    #[snafu(display("Invalid upgrade phase transition from '{}' to '{}'", from, to))]
+    InvalidPhaseTransition { from: String, to: String },
+
+    /// Error for when io-engine Pods are found to not be upgraded after the data-plane upgrade.
+    #[snafu(display(
+        "Found {} data-plane Pods which are not upgraded to version {}",
+        PRODUCT,
+        to_version
+    ))]
+    DataPlaneUpgradeIncomplete { to_version: String },
+
+    /// Error for when the upgrade-job's version does not match the target helm chart's version.
+    #[snafu(display(
+        "The upgrade-job version {} does not match the target helm chart version {}, use the \
+        upgrade-job image of version {}",
+        job_version,
+        chart_version,
+        chart_version
+    ))]
+    UpgradeJobVersionMismatch {
+        job_version: String,
+        chart_version: String,
+    },
+
     /// Error for when the detected upgrade path for PRODUCT is not supported.
     #[snafu(display("The upgrade path is invalid"))]
     InvalidUpgradePath,
@@ -408,10 +802,367 @@ pub(crate) enum Error {
         note: EventNote,
     },
 
+    /// Error in serializing the storage Node statuses to a JSON string.
+    #[snafu(display("Failed to serialize the storage Node statuses: {}", source))]
+    SerializeNodeStatus { source: serde_json::Error },
+
+    /// Error in serializing the resolved CLI options to a YAML string.
+    #[snafu(display("Failed to serialize the effective configuration: {}", source))]
+    SerializeEffectiveConfig { source: serde_yaml::Error },
+
     /// Error for when there are too many io-engine Pods in one single node;
     #[snafu(display("Too many io-engine Pods in Node '{}'", node_name))]
     TooManyIoEnginePods { node_name: String },
 
+    /// Error for when a restarted io-engine Pod cannot be scheduled, because none of the Nodes
+    /// have enough of the resources which it requests.
+    #[snafu(display("Replacement io-engine Pod '{}' cannot be scheduled: {}", pod, reason))]
+    ReplacementPodUnschedulable { pod: String, reason: String },
+
+    /// Error for when a restarted io-engine Pod is bound to a Node other than the one it was
+    /// restarted on.
+    #[snafu(display(
+        "Replacement io-engine Pod '{}' was expected on Node {}, but was scheduled on Node {}",
+        pod,
+        expected_node,
+        actual_node
+    ))]
+    PodRescheduledUnexpectedly {
+        pod: String,
+        expected_node: String,
+        actual_node: String,
+    },
+
+    /// Error for when some of the io-engine Pods of a batch of Nodes would not be schedulable
+    /// onto their Nodes, once restarted.
+    #[snafu(display(
+        "The io-engine Pods of the batch of Nodes would be left Pending: {:?}",
+        reasons
+    ))]
+    UnschedulableDataPlaneBatch { reasons: Vec<String> },
+
+    /// Error for when the dry-run server-side apply of a CRD fails.
+    #[snafu(display(
+        "Failed to dry-run server-side apply CustomResourceDefinition '{}': {}",
+        name,
+        source
+    ))]
+    DryRunApplyCrd { source: kube::Error, name: String },
+
+    /// Error for when a CRD spec cannot be serialized for comparison.
+    #[snafu(display(
+        "Failed to serialize CustomResourceDefinition '{}' spec: {}",
+        name,
+        source
+    ))]
+    SerializeCrd {
+        source: serde_json::Error,
+        name: String,
+    },
+
+    /// Error for when applying the helm chart's CRDs would make breaking changes.
+    #[snafu(display(
+        "Applying the helm chart's CustomResourceDefinitions would make breaking changes: {}",
+        changes.join("; ")
+    ))]
+    BreakingCrdChanges { changes: Vec<String> },
+
+    /// Error for when a Kubernetes API request for GET-ing a Node fails.
+    #[snafu(display("Failed to get Kubernetes Node '{}': {}", node_name, source))]
+    GetKubernetesNode {
+        source: kube::Error,
+        node_name: String,
+    },
+
+    /// Error for when a Kubernetes API request for listing Nodes with a label selector fails.
+    #[snafu(display(
+        "Failed to list Kubernetes Nodes with label selector '{}': {}",
+        label,
+        source
+    ))]
+    ListKubernetesNodesWithLabel { source: kube::Error, label: String },
+
+    /// Error for when a Kubernetes API request to acquire the upgrade Lease fails.
+    #[snafu(display(
+        "Failed to acquire the upgrade Lease '{}' in namespace {}: {}",
+        name,
+        namespace,
+        source
+    ))]
+    AcquireUpgradeLease {
+        source: kube::Error,
+        name: String,
+        namespace: String,
+    },
+
+    /// Error for when another upgrade Job holds the upgrade Lease.
+    #[snafu(display(
+        "Another upgrade is already in progress, the upgrade Lease '{}' is held by Pod '{}'",
+        lease,
+        holder
+    ))]
+    UpgradeAlreadyInProgress { holder: String, lease: String },
+
+    /// Error for when a preflight check runs, but the cluster does not meet its condition.
+    #[snafu(display("{}", reason))]
+    PreflightCheckUnmet { reason: String },
+
+    /// Error for when any of the preflight checks fails.
+    #[snafu(display("Preflight checks failed: {}", checks.join(", ")))]
+    PreflightChecksFailed { checks: Vec<String> },
+
+    /// Error for when a Kubernetes API request for a SelfSubjectAccessReview fails.
+    #[snafu(display(
+        "Failed to review access to '{}' {} in {}: {}",
+        verb,
+        resource,
+        scope,
+        source
+    ))]
+    SelfSubjectAccessReview {
+        source: kube::Error,
+        verb: String,
+        resource: String,
+        scope: String,
+    },
+
+    /// Error for when the upgrade Job's ServiceAccount lacks permissions which the upgrade needs.
+    #[snafu(display(
+        "The upgrade Job is missing the Kubernetes RBAC permissions: {}",
+        permissions.join(", ")
+    ))]
+    InsufficientRbac { permissions: Vec<String> },
+
+    /// Error for when a Kubernetes API request for GET-ing a DaemonSet fails.
+    #[snafu(display(
+        "Failed to get DaemonSet '{}' in namespace {}: {}",
+        name,
+        namespace,
+        source
+    ))]
+    GetDaemonSet {
+        source: kube::Error,
+        name: String,
+        namespace: String,
+    },
+
+    /// Error for when a Kubernetes API request for GET-ing a ConfigMap fails.
+    #[snafu(display(
+        "Failed to get ConfigMap '{}' in namespace {}: {}",
+        name,
+        namespace,
+        source
+    ))]
+    GetConfigMap {
+        source: kube::Error,
+        name: String,
+        namespace: String,
+    },
+
+    /// Error for when a Kubernetes API request for PATCH-ing a ConfigMap fails.
+    #[snafu(display(
+        "Failed to patch ConfigMap '{}' in namespace {}: {}",
+        name,
+        namespace,
+        source
+    ))]
+    PatchConfigMap {
+        source: kube::Error,
+        name: String,
+        namespace: String,
+    },
+
+    /// Error for when the handler for the canary resume signal cannot be set up.
+    #[snafu(display("Failed to listen for the canary resume signal: {}", source))]
+    CanaryResumeSignal { source: std::io::Error },
+
+    /// Error for when there are no Ready agent-core Pods to lead the control-plane.
+    #[snafu(display(
+        "No Ready agent-core Pod found in namespace {}, the control-plane has no leader",
+        namespace
+    ))]
+    NoAgentCoreLeader { namespace: String },
+
+    /// Error for when more than one agent-core Pod is Ready.
+    #[snafu(display(
+        "Found {} Ready agent-core Pods in namespace {}, expected exactly one leader: {:?}",
+        pods.len(),
+        namespace,
+        pods
+    ))]
+    MultipleAgentCoreLeaders {
+        namespace: String,
+        pods: Vec<String>,
+    },
+
+    /// Error for when the number of Degraded volumes stays above the maximum for too long.
+    #[snafu(display(
+        "Timed out after {:?} waiting for the number of Degraded volumes to go below {}, {} \
+        volumes are Degraded",
+        timeout,
+        max,
+        count
+    ))]
+    TooManyDegradedVolumes {
+        count: usize,
+        max: usize,
+        timeout: Duration,
+    },
+
+    /// Error for when the volumes with replicas on a storage Node would stay below the minimum
+    /// number of healthy replicas, were the Node drained.
+    #[snafu(display(
+        "Timed out after {:?} waiting to drain {} Node {}, the volumes {:?} would have fewer than \
+        {} healthy replicas",
+        timeout,
+        PRODUCT,
+        node_id,
+        volumes,
+        min
+    ))]
+    MinHealthyReplicas {
+        node_id: String,
+        volumes: Vec<String>,
+        min: usize,
+        timeout: Duration,
+    },
+
+    /// Error for when the control-plane Pods are not running within the timeout.
+    #[snafu(display(
+        "Timed out after {:?} waiting for the control-plane Pods in namespace {} to be running",
+        timeout,
+        namespace
+    ))]
+    ControlPlaneReadyTimeout {
+        namespace: String,
+        timeout: Duration,
+    },
+
+    /// Error for when the helm release was rolled back because the control-plane stopped running
+    /// during the data-plane upgrade.
+    #[snafu(display(
+        "The control-plane Pods in namespace {} stopped running during the data-plane upgrade, \
+        the helm release '{}' was rolled back",
+        namespace,
+        release_name
+    ))]
+    ControlPlaneRolledBack {
+        namespace: String,
+        release_name: String,
+    },
+
+    /// Error for when the restarted io-engine Pod is not Ready within the timeout.
+    #[snafu(display(
+        "Timed out after {:?} waiting for the data-plane Pod on Node '{}' to be Ready",
+        timeout,
+        node_name
+    ))]
+    DataPlanePodReadyTimeout {
+        node_name: String,
+        timeout: Duration,
+    },
+
+    /// Error for when the HTTP client for the notification backends cannot be built.
+    #[snafu(display("Failed to build HTTP client for upgrade notifications: {}", source))]
+    NotificationClient { source: reqwest::Error },
+
+    /// Error for when the report S3 URL is not of the form 's3://bucket/key'.
+    #[snafu(display("Invalid S3 URL '{}', expected 's3://bucket/key'", url))]
+    InvalidS3Url { url: String },
+
+    /// Error for when the S3-compatible store's endpoint is not a valid URL with a host.
+    #[snafu(display("Invalid S3 endpoint '{}': {}", endpoint, source))]
+    InvalidS3Endpoint {
+        source: url::ParseError,
+        endpoint: String,
+    },
+
+    /// Error for when there is no access key for the S3-compatible store.
+    #[snafu(display(
+        "No S3 credentials, set '--report-s3-access-key-id' and '--report-s3-secret-access-key'"
+    ))]
+    NoS3Credentials,
+
+    /// Error for when the HTTP client for the S3-compatible store cannot be built.
+    #[snafu(display("Failed to build HTTP client for the S3 upload: {}", source))]
+    S3Client { source: reqwest::Error },
+
+    /// Error for when the upload request to the S3-compatible store fails.
+    #[snafu(display("Failed to upload to {}: {}", url, source))]
+    S3UploadRequest { source: reqwest::Error, url: String },
+
+    /// Error for when the S3-compatible store rejects an upload.
+    #[snafu(display("S3 upload to {} was rejected with status {}: {}", url, status, body))]
+    S3UploadRejected {
+        url: String,
+        status: u16,
+        body: String,
+    },
+
+    /// Error for when a container image reference cannot be parsed.
+    #[snafu(display("Failed to parse container image reference '{}'", image))]
+    InvalidImageReference { image: String },
+
+    /// Error for when the HTTP client for the container image registry cannot be built.
+    #[snafu(display("Failed to build HTTP client for container image registry: {}", source))]
+    ImageRegistryClient { source: reqwest::Error },
+
+    /// Error for when a request to the container image registry fails.
+    #[snafu(display(
+        "Failed to look up container image '{}' in its registry: {}",
+        image,
+        source
+    ))]
+    ImageRegistryRequest {
+        source: reqwest::Error,
+        image: String,
+    },
+
+    /// Error for when the container image registry denies access to the image.
+    #[snafu(display(
+        "Failed to authenticate with the registry of container image '{}', HTTP status {}",
+        image,
+        status
+    ))]
+    ImageRegistryAuth { image: String, status: u16 },
+
+    /// Error for when the container image is not found in its registry.
+    #[snafu(display("Container image '{}' was not found in its registry", image))]
+    ImageNotFound { image: String },
+
+    /// Error for when the container image registry responds with an unexpected HTTP status.
+    #[snafu(display(
+        "Unexpected HTTP status {} from the registry of container image '{}'",
+        status,
+        image
+    ))]
+    ImageRegistryResponse { image: String, status: u16 },
+
+    /// Error for when the io-engine DaemonSet does not have an io-engine container image.
+    #[snafu(display(
+        "Failed to find the {} container image in DaemonSet '{}'",
+        container,
+        name
+    ))]
+    EmptyContainerImage { name: String, container: String },
+
+    /// Error for when a Node does not have enough allocatable hugepages for the io-engine Pod.
+    #[snafu(display(
+        "Node '{}' has {} bytes of allocatable {}, which is {} bytes short of the io-engine Pod's \
+        request of {} bytes",
+        node_name,
+        allocatable,
+        resource,
+        requested.saturating_sub(*allocatable),
+        requested
+    ))]
+    InsufficientHugepages {
+        node_name: String,
+        resource: String,
+        requested: u64,
+        allocatable: u64,
+    },
+
     /// Error for when the thin-provisioning options are absent, but still tried to fetch it.
     #[snafu(display("The agents.core.capacity yaml object is absent amongst the helm values"))]
     ThinProvisioningOptionsAbsent,
@@ -512,6 +1263,19 @@ pub(crate) enum Error {
         to_version: String,
     },
 
+    /// Error for when the helm upgrade's target version is more than a single minor version ahead
+    /// of the installed version.
+    #[snafu(display(
+        "Failed to upgrade from {} to {}: upgrades across more than a single minor version are \
+        unsupported",
+        from_version,
+        to_version
+    ))]
+    UnsupportedVersionSkew {
+        from_version: String,
+        to_version: String,
+    },
+
     /// Error for when yq command execution fails.
     #[snafu(display(
         "Failed to run yq command,\ncommand: {},\nargs: {:?},\ncommand_error: {}",
@@ -559,6 +1323,10 @@ pub(crate) enum Error {
     #[snafu(display("Failed to create temporary file: {}", source))]
     TempFileCreation { source: std::io::Error },
 
+    /// Error for when temporary directory creation fails.
+    #[snafu(display("Failed to create temporary directory: {}", source))]
+    TempDirCreation { source: std::io::Error },
+
     /// Error for when we fail to write to a temporary file.
     #[snafu(display("Failed to write to temporary file {}: {}", filepath.display(), source))]
     WriteToTempFile {
@@ -599,6 +1367,25 @@ pub(crate) enum Error {
     #[snafu(display("Failed to create CustomResourceDefinition '{}': {}", name, source))]
     CreateCrd { source: kube::Error, name: String },
 
+    /// Error for when a Kubernetes API request for GET-ing a CRD fails.
+    #[snafu(display("Failed to get CustomResourceDefinition '{}': {}", name, source))]
+    GetCrd { source: kube::Error, name: String },
+
+    /// Error for when the target helm chart's CRD no longer serves a version which the existing
+    /// custom resources are stored in.
+    #[snafu(display(
+        "CustomResourceDefinition '{}' in the helm chart with appVersion '{}' does not serve the \
+        version(s) {:?}, which existing custom resources are stored in",
+        name,
+        app_version,
+        versions
+    ))]
+    CrdStoredVersionsNotServed {
+        name: String,
+        app_version: String,
+        versions: Vec<String>,
+    },
+
     /// Error for when unwraping of Result<DirEntry, std::io::Error> fails.
     #[snafu(display(
         "Failed to collect DirEntry list from read_dir() into a Vec<_> for directory {}: {}",
@@ -609,6 +1396,70 @@ pub(crate) enum Error {
         source: std::io::Error,
         path: PathBuf,
     },
+
+    /// Error for when an upgrade step fails. This carries the upgrade phase and the storage Node
+    /// (if any) which the step was for, along with the error the step failed with.
+    #[snafu(display(
+        "Failed in the {} upgrade phase{}: {}",
+        phase,
+        node.as_ref()
+            .map(|node| format!(" for Node '{node}'"))
+            .unwrap_or_default(),
+        source
+    ))]
+    PhaseContext {
+        #[snafu(source(from(Error, Box::new)))]
+        source: Box<Error>,
+        phase: String,
+        node: Option<String>,
+    },
+}
+
+impl Error {
+    /// This returns the upgrade phase and the storage Node which the error occurred in, if the
+    /// error carries that context.
+    pub(crate) fn phase_context(&self) -> Option<(&str, Option<&str>)> {
+        match self {
+            Self::PhaseContext { phase, node, .. } => Some((phase.as_str(), node.as_deref())),
+            _ => None,
+        }
+    }
+
+    /// This is a predicate which is true if the error is for the helm release having been rolled
+    /// back, whether or not it carries the upgrade phase context.
+    pub(crate) fn is_control_plane_rolled_back(&self) -> bool {
+        match self {
+            Self::ControlPlaneRolledBack { .. } => true,
+            Self::PhaseContext { source, .. } => source.is_control_plane_rolled_back(),
+            _ => false,
+        }
+    }
+
+    /// This is a predicate which is true if the upgrade was stopped by a termination signal,
+    /// whether or not the error carries the upgrade phase context.
+    pub(crate) fn is_interrupted(&self) -> bool {
+        match self {
+            Self::UpgradeInterrupted => true,
+            Self::PhaseContext { source, .. } => source.is_interrupted(),
+            _ => false,
+        }
+    }
+
+    /// This wraps the error with the upgrade phase and the storage Node which it occurred in.
+    /// Errors which already carry this context are returned as they are.
+    pub(crate) fn in_phase<P>(self, phase: P, node: Option<&str>) -> Self
+    where
+        P: ToString,
+    {
+        match self {
+            Self::PhaseContext { .. } => self,
+            error => PhaseContext {
+                phase: phase.to_string(),
+                node: node.map(ToString::to_string),
+            }
+            .into_error(error),
+        }
+    }
 }
 
 /// A wrapper type to remove repeated Result<T, Error> returns.
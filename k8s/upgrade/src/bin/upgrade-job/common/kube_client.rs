@@ -1,13 +1,52 @@
 use crate::common::error::{K8sClientGeneration, KubeClientSetBuilderNs, Result};
 use k8s_openapi::{
     api::{
-        apps::v1::Deployment,
-        core::v1::{Namespace, Pod},
+        apps::v1::{DaemonSet, Deployment, StatefulSet},
+        batch::v1::Job,
+        coordination::v1::Lease,
+        core::v1::{ConfigMap, Namespace, Node, PersistentVolumeClaim, Pod},
     },
     apiextensions_apiserver::pkg::apis::apiextensions::v1::CustomResourceDefinition,
 };
-use kube::{api::Api, Client};
+use kube::{
+    api::{Api, ApiResource, DeleteParams, DynamicObject, ListParams, ObjectList},
+    core::{response::Status, Either},
+    Client,
+};
 use snafu::ResultExt;
+use std::sync::OnceLock;
+use tokio::sync::{Semaphore, SemaphorePermit};
+
+/// This limits the number of concurrent Kubernetes API requests made by the kube API clients.
+static KUBE_API_CONCURRENCY: OnceLock<Semaphore> = OnceLock::new();
+
+/// Sets the maximum number of concurrent Kubernetes API requests. This has to be called before
+/// any of the Kubernetes API requests are made, it has no effect otherwise.
+pub(crate) fn init_kube_api_concurrency(limit: usize) {
+    let _ = KUBE_API_CONCURRENCY.set(Semaphore::new(limit));
+}
+
+/// Waits for a permit to make a Kubernetes API request. The request should be made while the
+/// permit is held.
+async fn kube_api_permit() -> Option<SemaphorePermit<'static>> {
+    KUBE_API_CONCURRENCY
+        .get_or_init(|| Semaphore::new(Semaphore::MAX_PERMITS))
+        .acquire()
+        .await
+        .ok()
+}
+
+/// This is the policy/v1 PodDisruptionBudget resource. The k8s-openapi version feature in use
+/// predates policy/v1, so the PodDisruptionBudgets are accessed as dynamic objects.
+fn pod_disruption_budget_resource() -> ApiResource {
+    ApiResource {
+        group: "policy".to_string(),
+        version: "v1".to_string(),
+        api_version: "policy/v1".to_string(),
+        kind: "PodDisruptionBudget".to_string(),
+        plural: "poddisruptionbudgets".to_string(),
+    }
+}
 
 /// Builder for Kubernetes clients.
 #[derive(Default)]
@@ -39,6 +78,19 @@ impl KubeClientSetBuilder {
             pods_api: Api::namespaced(client.clone(), namespace.as_str()),
             namespaces_api: Api::all(client.clone()),
             deployments_api: Api::namespaced(client.clone(), namespace.as_str()),
+            daemonsets_api: Api::namespaced(client.clone(), namespace.as_str()),
+            statefulsets_api: Api::namespaced(client.clone(), namespace.as_str()),
+            nodes_api: Api::all(client.clone()),
+            configmaps_api: Api::namespaced(client.clone(), namespace.as_str()),
+            jobs_api: Api::namespaced(client.clone(), namespace.as_str()),
+            leases_api: Api::namespaced(client.clone(), namespace.as_str()),
+            pvcs_api: Api::namespaced(client.clone(), namespace.as_str()),
+            all_namespaces_pvcs_api: Api::all(client.clone()),
+            pdbs_api: Api::namespaced_with(
+                client.clone(),
+                namespace.as_str(),
+                &pod_disruption_budget_resource(),
+            ),
             crd_api: Api::all(client),
         });
     }
@@ -50,6 +102,15 @@ pub(crate) struct KubeClientSet {
     pods_api: Api<Pod>,
     namespaces_api: Api<Namespace>,
     deployments_api: Api<Deployment>,
+    daemonsets_api: Api<DaemonSet>,
+    statefulsets_api: Api<StatefulSet>,
+    nodes_api: Api<Node>,
+    configmaps_api: Api<ConfigMap>,
+    jobs_api: Api<Job>,
+    leases_api: Api<Lease>,
+    pvcs_api: Api<PersistentVolumeClaim>,
+    all_namespaces_pvcs_api: Api<PersistentVolumeClaim>,
+    pdbs_api: Api<DynamicObject>,
     crd_api: Api<CustomResourceDefinition>,
 }
 
@@ -63,6 +124,31 @@ impl KubeClientSet {
         &self.pods_api
     }
 
+    /// List Pods, within the limit for concurrent Kubernetes API requests.
+    pub(crate) async fn list_pods(
+        &self,
+        list_params: &ListParams,
+    ) -> kube::Result<ObjectList<Pod>> {
+        let _permit = kube_api_permit().await;
+        self.pods_api.list(list_params).await
+    }
+
+    /// Get a Pod if it exists, within the limit for concurrent Kubernetes API requests.
+    pub(crate) async fn get_pod_opt(&self, name: &str) -> kube::Result<Option<Pod>> {
+        let _permit = kube_api_permit().await;
+        self.pods_api.get_opt(name).await
+    }
+
+    /// Delete a Pod, within the limit for concurrent Kubernetes API requests.
+    pub(crate) async fn delete_pod(
+        &self,
+        name: &str,
+        delete_params: &DeleteParams,
+    ) -> kube::Result<Either<Pod, Status>> {
+        let _permit = kube_api_permit().await;
+        self.pods_api.delete(name, delete_params).await
+    }
+
     /// Generate the Namespace api client.
     pub(crate) fn namespaces_api(&self) -> &Api<Namespace> {
         &self.namespaces_api
@@ -73,6 +159,51 @@ impl KubeClientSet {
         &self.deployments_api
     }
 
+    /// Generate the DaemonSet api client.
+    pub(crate) fn daemonsets_api(&self) -> &Api<DaemonSet> {
+        &self.daemonsets_api
+    }
+
+    /// Generate the StatefulSet api client.
+    pub(crate) fn statefulsets_api(&self) -> &Api<StatefulSet> {
+        &self.statefulsets_api
+    }
+
+    /// Generate the Node api client.
+    pub(crate) fn nodes_api(&self) -> &Api<Node> {
+        &self.nodes_api
+    }
+
+    /// Generate the ConfigMap api client.
+    pub(crate) fn configmaps_api(&self) -> &Api<ConfigMap> {
+        &self.configmaps_api
+    }
+
+    /// Generate the Job api client.
+    pub(crate) fn jobs_api(&self) -> &Api<Job> {
+        &self.jobs_api
+    }
+
+    /// Generate the Lease api client.
+    pub(crate) fn leases_api(&self) -> &Api<Lease> {
+        &self.leases_api
+    }
+
+    /// Generate the PersistentVolumeClaim api client.
+    pub(crate) fn pvcs_api(&self) -> &Api<PersistentVolumeClaim> {
+        &self.pvcs_api
+    }
+
+    /// Generate the PersistentVolumeClaim api client for all namespaces.
+    pub(crate) fn all_namespaces_pvcs_api(&self) -> &Api<PersistentVolumeClaim> {
+        &self.all_namespaces_pvcs_api
+    }
+
+    /// Generate the PodDisruptionBudget api client.
+    pub(crate) fn pdbs_api(&self) -> &Api<DynamicObject> {
+        &self.pdbs_api
+    }
+
     /// Generate the CustomResourceDefinition api client.
     pub(crate) fn crd_api(&self) -> &Api<CustomResourceDefinition> {
         &self.crd_api
@@ -1,9 +1,27 @@
-use crate::common::error::{RestClientConfiguration, RestUrlParse, Result};
+use crate::common::error::{
+    RestClientConfiguration, RestUrlHasPath, RestUrlParse, RestUrlScheme, Result,
+};
 use openapi::tower::client::{ApiClient, Configuration as RestConfig};
-use snafu::ResultExt;
-use std::time::Duration;
+use snafu::{ensure, ResultExt};
+use std::{sync::OnceLock, time::Duration};
 use url::Url;
 
+/// This is the number of times a storage REST API request which fails with a retriable error is
+/// retried.
+static REST_MAX_RETRIES: OnceLock<u32> = OnceLock::new();
+
+/// Sets the number of times the retriable storage REST API requests are retried. This has to be
+/// called before any of the storage REST API requests are made, it has no effect otherwise.
+pub(crate) fn init_rest_max_retries(max_retries: u32) {
+    let _ = REST_MAX_RETRIES.set(max_retries);
+}
+
+/// This returns the number of times the retriable storage REST API requests are retried, none if
+/// it is not set.
+pub(crate) fn rest_max_retries() -> u32 {
+    REST_MAX_RETRIES.get().copied().unwrap_or_default()
+}
+
 /// This is a wrapper for the openapi::tower::client::ApiClient.
 pub(crate) struct RestClientSet {
     client: ApiClient,
@@ -12,8 +30,28 @@ pub(crate) struct RestClientSet {
 impl RestClientSet {
     /// Build the RestConfig, and the eventually the ApiClient. Fails if configuration is invalid.
     pub(crate) fn new_with_url(rest_endpoint: String) -> Result<Self> {
-        let rest_url =
-            Url::try_from(rest_endpoint.as_str()).context(RestUrlParse { rest_endpoint })?;
+        let rest_url = Url::try_from(rest_endpoint.as_str()).context(RestUrlParse {
+            rest_endpoint: rest_endpoint.clone(),
+        })?;
+
+        // Only plain and TLS-secured HTTP are spoken by the REST API client.
+        ensure!(
+            matches!(rest_url.scheme(), "http" | "https"),
+            RestUrlScheme {
+                rest_endpoint: rest_endpoint.clone(),
+                scheme: rest_url.scheme().to_string(),
+            }
+        );
+
+        // The REST API paths are appended to the endpoint by the client, so the endpoint has to
+        // be a bare base URL.
+        ensure!(
+            matches!(rest_url.path(), "" | "/"),
+            RestUrlHasPath {
+                rest_endpoint,
+                path: rest_url.path().to_string(),
+            }
+        );
 
         let config = RestConfig::builder()
             .with_timeout(Duration::from_secs(30))
@@ -35,6 +73,14 @@ impl RestClientSet {
         self.client.nodes_api()
     }
 
+    pub(crate) fn pools_api(&self) -> &dyn openapi::apis::pools_api::tower::client::Pools {
+        self.client.pools_api()
+    }
+
+    pub(crate) fn replicas_api(&self) -> &dyn openapi::apis::replicas_api::tower::client::Replicas {
+        self.client.replicas_api()
+    }
+
     pub(crate) fn volumes_api(&self) -> &dyn openapi::apis::volumes_api::tower::client::Volumes {
         self.client.volumes_api()
     }
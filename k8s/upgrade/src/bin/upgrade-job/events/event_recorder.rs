@@ -1,18 +1,28 @@
 use crate::common::{
-    constants::PRODUCT,
+    constants::{
+        PRODUCT, UPGRADE_FROM_VERSION_ANNOTATION_KEY, UPGRADE_STATUS_ANNOTATION_KEY,
+        UPGRADE_TO_VERSION_ANNOTATION_KEY,
+    },
     error::{
-        EventChannelSend, EventPublish, EventRecorderOptionsAbsent, GetPod, JobPodHasTooManyOwners,
-        JobPodOwnerIsNotJob, JobPodOwnerNotFound, Result, SerializeEventNote,
+        AnnotateJob, EventChannelSend, EventPublish, EventRecorderOptionsAbsent, GetPod,
+        JobPodHasTooManyOwners, JobPodOwnerIsNotJob, JobPodOwnerNotFound, Result,
+        SerializeEventNote,
     },
     kube_client::KubeClientSet,
 };
-use k8s_openapi::{api::core::v1::ObjectReference, serde_json};
-use kube::runtime::events::{Event, EventType, Recorder};
+use k8s_openapi::{
+    api::{batch::v1::Job, core::v1::ObjectReference},
+    serde_json,
+};
+use kube::{
+    api::{Api, Patch, PatchParams},
+    runtime::events::{Event, EventType, Recorder},
+};
 use serde::Serialize;
 use snafu::{ensure, ResultExt};
 use std::{fmt::Display, time::Duration};
 use tokio::{select, sync::mpsc, time::sleep};
-use tracing::error;
+use tracing::{error, info, warn};
 
 #[derive(Serialize, Debug)]
 #[serde(rename_all(serialize = "camelCase"))]
@@ -131,6 +141,10 @@ impl EventRecorderBuilder {
             .fail(),
         }?;
 
+        let job_name = pod_owner.name.clone();
+        let job_namespace = namespace.clone();
+        let jobs_api = k8s_client.jobs_api().clone();
+
         let job_obj_ref = ObjectReference {
             api_version: Some(pod_owner.api_version),
             kind: Some(pod_owner.kind),
@@ -175,6 +189,9 @@ impl EventRecorderBuilder {
             event_loop_handle,
             from_version,
             to_version,
+            job_name,
+            job_namespace,
+            jobs_api,
         })
     }
 }
@@ -185,6 +202,9 @@ pub(crate) struct EventRecorder {
     event_loop_handle: tokio::task::JoinHandle<()>,
     from_version: String,
     to_version: String,
+    job_name: String,
+    job_namespace: String,
+    jobs_api: Api<Job>,
 }
 
 impl EventRecorder {
@@ -221,6 +241,19 @@ impl EventRecorder {
         .await
     }
 
+    /// This publishes a Normal event for the progress of the upgrade. Failing to publish the
+    /// event does not fail the upgrade, it is only logged.
+    pub(crate) async fn publish_progress<J, K>(&self, note: J, action: K)
+    where
+        J: ToString,
+        K: ToString,
+    {
+        let action = action.to_string();
+        if let Err(error) = self.publish_normal(note, action.as_str()).await {
+            warn!(%error, %action, "Failed to publish upgrade progress event");
+        }
+    }
+
     /// This is a helper method with calls the publish method above and fills out the boilerplate
     /// Event fields. type is set to publish a Warning event.
     pub(crate) async fn publish_warning<J, K>(&self, note: J, action: K) -> Result<()>
@@ -256,6 +289,39 @@ impl EventRecorder {
             .map_err(|error| error!(%error, "Failed to upgrade {PRODUCT}"));
     }
 
+    /// Annotates the upgrade Job with the outcome of the upgrade, and the versions it upgraded from
+    /// and to. Failing to annotate the Job does not fail the upgrade, it is only logged.
+    pub(crate) async fn annotate_job_outcome(&self, succeeded: bool) {
+        let status = if succeeded { "Succeeded" } else { "Failed" };
+        let patch = serde_json::json!({
+            "metadata": {
+                "annotations": {
+                    UPGRADE_STATUS_ANNOTATION_KEY: status,
+                    UPGRADE_FROM_VERSION_ANNOTATION_KEY: self.from_version,
+                    UPGRADE_TO_VERSION_ANNOTATION_KEY: self.to_version,
+                }
+            }
+        });
+
+        match self
+            .jobs_api
+            .patch(
+                self.job_name.as_str(),
+                &PatchParams::default(),
+                &Patch::Merge(&patch),
+            )
+            .await
+            .context(AnnotateJob {
+                name: self.job_name.clone(),
+                namespace: self.job_namespace.clone(),
+            }) {
+            Ok(_) => {
+                info!(job.name = %self.job_name, %status, "Annotated Job with upgrade outcome")
+            }
+            Err(error) => error!(%error, "Failed to annotate Job with upgrade outcome"),
+        }
+    }
+
     /// Shuts down the event channel which makes the event loop worker exit its loop and return.
     pub(crate) async fn shutdown_worker(mut self) {
         // Dropping the sender, to signify no more channel messages.
@@ -283,6 +349,8 @@ pub enum EventAction {
     Failed,
     #[serde(rename = "Validation Failed")]
     ValidationFailed,
+    #[serde(rename = "Planned")]
+    Planned,
     #[serde(rename = "Upgrading control-plane")]
     UpgradingCP,
     #[serde(rename = "Upgraded control-plane")]
@@ -291,6 +359,16 @@ pub enum EventAction {
     UpgradingDP,
     #[serde(rename = "Upgraded data-plane")]
     UpgradedDP,
+    #[serde(rename = "Drained node")]
+    DrainedNode,
+    #[serde(rename = "Restarted io-engine pod")]
+    RestartedPod,
+    #[serde(rename = "Rolled back control-plane")]
+    RolledBackCP,
+    #[serde(rename = "Timed out")]
+    TimedOut,
+    #[serde(rename = "Unhealthy after upgrade")]
+    UnhealthyAfterUpgrade,
     #[serde(rename = "Successful")]
     Successful,
 }
@@ -300,10 +378,16 @@ impl ToString for EventAction {
         match self {
             Self::Failed => String::from("Failed"),
             Self::ValidationFailed => String::from("Validation Failed"),
+            Self::Planned => String::from("Planned"),
             Self::UpgradingCP => String::from("Upgrading control-plane"),
             Self::UpgradedCP => String::from("Upgraded control-plane"),
             Self::UpgradingDP => String::from("Upgrading data-plane"),
             Self::UpgradedDP => String::from("Upgraded data-plane"),
+            Self::DrainedNode => String::from("Drained node"),
+            Self::RestartedPod => String::from("Restarted io-engine pod"),
+            Self::RolledBackCP => String::from("Rolled back control-plane"),
+            Self::TimedOut => String::from("Timed out"),
+            Self::UnhealthyAfterUpgrade => String::from("Unhealthy after upgrade"),
             Self::Successful => String::from("Successful"),
         }
     }
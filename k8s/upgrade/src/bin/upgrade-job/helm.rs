@@ -1,6 +1,29 @@
+use std::sync::OnceLock;
+
+/// This is the helm binary which the helm commands are run with.
+static HELM_BINARY: OnceLock<String> = OnceLock::new();
+
+/// Sets the helm binary which the helm commands are run with. This has to be called before any
+/// of the helm commands are run, it has no effect otherwise.
+pub(crate) fn init_helm_binary(helm_binary: String) {
+    let _ = HELM_BINARY.set(helm_binary);
+}
+
+/// This returns the helm binary which the helm commands are run with, 'helm' from the $PATH
+/// unless it is set.
+pub(crate) fn helm_binary() -> &'static str {
+    HELM_BINARY.get().map_or("helm", String::as_str)
+}
+
 /// Contains the HelmReleaseClient. Used for interacting with installed helm chart releases.
 pub(crate) mod client;
 
+/// Contains the redaction of sensitive helm values from the helm command arguments.
+pub(crate) mod redact;
+
+/// Contains the logic to pull helm charts from classic helm repositories.
+pub(crate) mod repo;
+
 /// Contains helm chart upgrade logic.
 pub(crate) mod upgrade;
 
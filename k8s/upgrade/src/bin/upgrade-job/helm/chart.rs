@@ -8,6 +8,9 @@ pub(crate) struct Chart {
     name: String,
     /// This is the version of the helm chart.
     version: Version,
+    /// This is the version of the application which the helm chart installs.
+    #[serde(rename = "appVersion")]
+    app_version: Option<String>,
 }
 
 impl Chart {
@@ -20,6 +23,11 @@ impl Chart {
     pub(crate) fn version(&self) -> &Version {
         &self.version
     }
+
+    /// This is a getter for the helm chart appVersion, if set.
+    pub(crate) fn app_version(&self) -> Option<&str> {
+        self.app_version.as_deref()
+    }
 }
 
 /// This is used to deserialize the values.yaml of the Core chart.
@@ -1,13 +1,14 @@
 use crate::{
     common::{
         error::{
-            CollectDirEntries, CreateCrd, HelmClientNs, HelmCommand, HelmGetValuesCommand,
-            HelmListCommand, HelmRelease, HelmUpgradeCommand, InvalidHelmChartCrdDir,
+            CollectDirEntries, CreateCrd, HelmClientNs, HelmCommand, HelmCommandFailed,
+            HelmGetValuesCommand, HelmRelease, HelmRollbackCommand, InvalidHelmChartCrdDir,
             ReadingDirectoryContents, ReadingFile, Result, U8VectorToString, YamlParseFromFile,
             YamlParseFromSlice,
         },
         kube_client::KubeClientSet,
     },
+    helm::{helm_binary, redact::ValueRedactor},
     vec_to_strings,
 };
 use k8s_openapi::{
@@ -15,21 +16,24 @@ use k8s_openapi::{
 };
 use kube::ResourceExt;
 use kube_client::{api::PostParams, Api};
+use semver::Version;
 use serde::Deserialize;
 use snafu::{ensure, IntoError, ResultExt};
 use std::{
     fs,
     path::{Path, PathBuf},
-    process::Command,
+    process::{Command, Output, Stdio},
     str,
 };
-use tracing::{debug, info};
+use tokio::io::{AsyncBufReadExt, AsyncRead, BufReader};
+use tracing::{debug, info, warn};
 
 /// This struct is used to deserialize the output of `helm list -n <namespace> --deployed -o yaml`.
 #[derive(Clone, Deserialize)]
 pub(crate) struct HelmReleaseElement {
     name: String,
     chart: String,
+    revision: String,
 }
 
 impl HelmReleaseElement {
@@ -42,12 +46,26 @@ impl HelmReleaseElement {
     pub(crate) fn chart(&self) -> String {
         self.chart.clone()
     }
+    /// This is a getter function for the version of the release's chart, which is the part of the
+    /// chart after the chart name, e.g. '2.3.0' of 'mayastor-2.3.0'. This is None if the chart
+    /// has no valid semver version.
+    pub(crate) fn chart_version(&self) -> Option<Version> {
+        self.chart
+            .match_indices('-')
+            .find_map(|(index, _)| Version::parse(&self.chart[index + 1 ..]).ok())
+    }
+    /// This is a getter function for the revision of the release, if it is a valid revision
+    /// number.
+    pub(crate) fn revision(&self) -> Option<u32> {
+        self.revision.parse().ok()
+    }
 }
 
 /// This is a builder for HelmReleaseClient.
 #[derive(Default)]
 pub(crate) struct HelmReleaseClientBuilder {
     namespace: Option<String>,
+    redacted_value_keys: Vec<String>,
 }
 
 impl HelmReleaseClientBuilder {
@@ -62,10 +80,22 @@ impl HelmReleaseClientBuilder {
         self
     }
 
+    /// This is a builder option to add the patterns of the helm value keys whose values are
+    /// masked when the helm command arguments are logged or used in error messages.
+    #[must_use]
+    pub(crate) fn with_redacted_value_keys(mut self, key_patterns: Vec<String>) -> Self {
+        self.redacted_value_keys = key_patterns;
+        self
+    }
+
     /// Build the HelmReleaseClient.
     pub(crate) fn build(self) -> Result<HelmReleaseClient> {
         let ns = self.namespace.ok_or(HelmClientNs.build())?;
-        Ok(HelmReleaseClient { namespace: ns })
+        let redactor = ValueRedactor::new(self.redacted_value_keys.as_slice())?;
+        Ok(HelmReleaseClient {
+            namespace: ns,
+            redactor,
+        })
     }
 }
 
@@ -74,6 +104,7 @@ impl HelmReleaseClientBuilder {
 #[derive(Clone)]
 pub(crate) struct HelmReleaseClient {
     pub(crate) namespace: String,
+    redactor: ValueRedactor,
 }
 
 impl HelmReleaseClient {
@@ -92,15 +123,36 @@ impl HelmReleaseClient {
         A: ToString,
         B: ToString,
     {
-        let command: &str = "helm";
-        let mut args: Vec<String> = vec_to_strings![
-            "get",
-            "values",
-            release_name,
-            "-n",
-            self.namespace.as_str(),
-            "-a"
-        ];
+        self.get_values(release_name, true, maybe_extra_args)
+    }
+
+    /// Runs command `helm get values -n <namespace> <release_name> -o yaml`, which only outputs
+    /// the values which were supplied by the user.
+    pub(crate) fn get_user_values_as_yaml<A>(&self, release_name: A) -> Result<Vec<u8>>
+    where
+        A: ToString,
+    {
+        self.get_values::<A, String>(release_name, false, None)
+    }
+
+    /// Runs command `helm get values`, with the '--all' flag if all of the computed values are
+    /// to be output.
+    fn get_values<A, B>(
+        &self,
+        release_name: A,
+        all: bool,
+        maybe_extra_args: Option<Vec<B>>,
+    ) -> Result<Vec<u8>>
+    where
+        A: ToString,
+        B: ToString,
+    {
+        let command: &str = helm_binary();
+        let mut args: Vec<String> =
+            vec_to_strings!["get", "values", release_name, "-n", self.namespace.as_str()];
+        if all {
+            args.push("-a".to_string());
+        }
 
         // Extra args
         args.extend(
@@ -146,7 +198,7 @@ impl HelmReleaseClient {
     where
         A: ToString,
     {
-        let command: &str = "helm";
+        let command: &str = helm_binary();
         let mut args: Vec<String> =
             vec_to_strings!["list", "-n", self.namespace.as_str(), "--deployed"];
 
@@ -174,20 +226,9 @@ impl HelmReleaseClient {
 
         let stdout_str = str::from_utf8(output.stdout.as_slice()).context(U8VectorToString)?;
         debug!(stdout=%stdout_str, "Helm list command standard output");
-        ensure!(
-            output.status.success(),
-            HelmListCommand {
-                command: command.to_string(),
-                args,
-                std_err: str::from_utf8(output.stderr.as_slice())
-                    .context(U8VectorToString)?
-                    .to_string()
-            }
-        );
+        ensure_helm_success(command, args, &output)?;
 
-        serde_yaml::from_slice(output.stdout.as_slice()).context(YamlParseFromSlice {
-            input_yaml: stdout_str.to_string(),
-        })
+        parse_helm_list_output(stdout_str)
     }
 
     /// Runs command `helm upgrade -n <namespace> <release_name> <chart_dir>`.
@@ -213,7 +254,7 @@ impl HelmReleaseClient {
             install_missing_crds(k8s_client.crd_api(), chart_dir.join("crds")).await?;
         }
 
-        let command: &str = "helm";
+        let command: &str = helm_binary();
         let mut args: Vec<String> = vec_to_strings![
             "upgrade",
             release_name,
@@ -232,20 +273,56 @@ impl HelmReleaseClient {
                 .map(ToString::to_string),
         );
 
-        debug!(%command, ?args, "Helm upgrade command");
-        let output = Command::new(command)
-            .args(args.clone())
-            .output()
+        // The args with the sensitive helm values masked, for logs and error messages.
+        let redacted_args = self.redactor.redact_args(args.as_slice());
+
+        // 'helm upgrade --dry-run' prints the rendered manifests, Secrets and all, to its standard
+        // output. This is not logged.
+        let is_dry_run = args.iter().any(|arg| arg.eq("--dry-run"));
+
+        debug!(%command, args = ?redacted_args, "Helm upgrade command");
+        let output = run_streaming_output(command, args.as_slice(), !is_dry_run, &self.redactor)
+            .await
+            .context(HelmCommand {
+                command: command.to_string(),
+                args: redacted_args.clone(),
+            })?;
+
+        ensure_helm_success(command, redacted_args, &output)?;
+
+        Ok(())
+    }
+
+    /// Runs command `helm rollback -n <namespace> <release_name> [revision] --wait`, which rolls
+    /// the release back to the revision, or to its previous revision if none is given.
+    pub(crate) async fn rollback<A>(&self, release_name: A, revision: Option<u32>) -> Result<()>
+    where
+        A: ToString,
+    {
+        let command: &str = helm_binary();
+        let mut args: Vec<String> = vec_to_strings!["rollback", release_name];
+        if let Some(revision) = revision {
+            args.push(revision.to_string());
+        }
+        args.extend(vec_to_strings![
+            "-n",
+            self.namespace.as_str(),
+            "--wait",
+            "--timeout",
+            "15m"
+        ]);
+
+        debug!(%command, ?args, "Helm rollback command");
+        let output = run_streaming_output(command, args.as_slice(), true, &self.redactor)
+            .await
             .context(HelmCommand {
                 command: command.to_string(),
                 args: args.clone(),
             })?;
 
-        let stdout_str = str::from_utf8(output.stdout.as_slice()).context(U8VectorToString)?;
-        debug!(stdout=%stdout_str, "Helm upgrade command standard output");
         ensure!(
             output.status.success(),
-            HelmUpgradeCommand {
+            HelmRollbackCommand {
                 command: command.to_string(),
                 args,
                 std_err: str::from_utf8(output.stderr.as_slice())
@@ -282,9 +359,99 @@ impl HelmReleaseClient {
     }
 }
 
-/// Installs CRDs which are missing from the target helm chart cluster which are missing
-/// from the cluster.
-async fn install_missing_crds(crd_client: &Api<Crd>, crd_dir_path: PathBuf) -> Result<()> {
+/// Deserializes the standard output of a successful `helm list -o yaml` command.
+fn parse_helm_list_output(stdout: &str) -> Result<Vec<HelmReleaseElement>> {
+    // A successful command with no output lists no releases. Empty YAML does not deserialize
+    // into a list.
+    if stdout.trim().is_empty() {
+        return Ok(Vec::new());
+    }
+
+    serde_yaml::from_str(stdout).context(YamlParseFromSlice {
+        input_yaml: stdout.to_string(),
+    })
+}
+
+/// Checks that a helm command exited successfully. If it did not, this fails with the command's
+/// exit code and its standard error, so that the reason for the failure is not lost.
+pub(crate) fn ensure_helm_success(command: &str, args: Vec<String>, output: &Output) -> Result<()> {
+    ensure!(
+        output.status.success(),
+        HelmCommandFailed {
+            command: command.to_string(),
+            args,
+            stderr: str::from_utf8(output.stderr.as_slice())
+                .context(U8VectorToString)?
+                .trim_end()
+                .to_string(),
+            exit_code: output.status.code(),
+        }
+    );
+    Ok(())
+}
+
+/// Runs a command, and logs its standard error line by line while it runs, with the values of the
+/// sensitive helm keys masked. The standard output is logged at the debug level, and only if
+/// 'log_stdout' is set. The complete output is returned after the command exits.
+async fn run_streaming_output(
+    command: &str,
+    args: &[String],
+    log_stdout: bool,
+    redactor: &ValueRedactor,
+) -> std::io::Result<Output> {
+    let mut child = tokio::process::Command::new(command)
+        .args(args)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+
+    // Both of the pipes are read at once, so that the command does not block on a full pipe.
+    let stdout = child.stdout.take();
+    let stderr = child.stderr.take();
+    let log_stdout_line = |line: &str| {
+        if log_stdout {
+            debug!(%command, stdout = %redactor.redact_set_values(line));
+        }
+    };
+    let log_stderr_line = |line: &str| {
+        warn!(%command, stderr = %redactor.redact_set_values(line));
+    };
+    let (stdout, stderr) = tokio::try_join!(
+        stream_lines(stdout, log_stdout_line),
+        stream_lines(stderr, log_stderr_line)
+    )?;
+    let status = child.wait().await?;
+
+    Ok(Output {
+        status,
+        stdout,
+        stderr,
+    })
+}
+
+/// Reads lines from a child process's pipe until it is closed, passing each line to 'log'. This
+/// returns all of what was read.
+async fn stream_lines<R, F>(pipe: Option<R>, log: F) -> std::io::Result<Vec<u8>>
+where
+    R: AsyncRead + Unpin,
+    F: Fn(&str),
+{
+    let mut output: Vec<u8> = Vec::new();
+    let Some(pipe) = pipe else {
+        return Ok(output);
+    };
+
+    let mut reader = BufReader::new(pipe);
+    let mut line: Vec<u8> = Vec::new();
+    while reader.read_until(b'\n', &mut line).await? > 0 {
+        log(String::from_utf8_lossy(line.as_slice()).trim_end());
+        output.append(&mut line);
+    }
+    Ok(output)
+}
+
+/// Reads the CRDs in a helm chart's 'crds' directory.
+pub(crate) fn crds_from_dir(crd_dir_path: PathBuf) -> Result<Vec<Crd>> {
     ensure!(
         crd_dir_path.is_dir(),
         InvalidHelmChartCrdDir { path: crd_dir_path }
@@ -298,9 +465,10 @@ async fn install_missing_crds(crd_client: &Api<Crd>, crd_dir_path: PathBuf) -> R
         .collect::<Result<Vec<_>, std::io::Error>>()
         .context(CollectDirEntries { path: crd_dir_path })?;
 
-    // Walk through the entries in the directory and create the CRDs.
+    // Walk through the entries in the directory and parse the CRDs.
     // This errors out if a file is not a CRD, but that is okay because the 'crds' directory
     // is meant for use with CRDs only.
+    let mut crds = Vec::with_capacity(entries.len());
     for entry in entries {
         if entry.is_file() {
             let crd_yaml = fs::read(entry.as_path()).context(ReadingFile {
@@ -309,36 +477,89 @@ async fn install_missing_crds(crd_client: &Api<Crd>, crd_dir_path: PathBuf) -> R
 
             let crd: Crd = serde_yaml::from_slice(crd_yaml.as_slice())
                 .context(YamlParseFromFile { filepath: entry })?;
+            crds.push(crd);
+        }
+    }
+    Ok(crds)
+}
 
-            // Create CRDs, and ignore creation failures due to the CRD already
-            // existing in the cluster.
-            let pp = PostParams::default();
-            let creation_result = crd_client.create(&pp, &crd).await;
-            if let Err(err) = creation_result {
-                match err {
-                    // Return early if creation has failed due to the CRD already existing in the
-                    // cluster.
-                    // Ref: https://github.com/kubernetes/apimachinery/blob/v0.27.3/pkg/apis/meta/v1/types.go#L846
-                    // TODO: It could be that the existing CRD registers a CR of a different version
-                    //       than the one bundled with the target helm chart. This needs to be
-                    // handled.
-                    kube::Error::Api(response) if response.reason.eq("AlreadyExists") => {
-                        info!(
-                            "CustomResourceDefinition '{}' already exists",
-                            crd.name_any()
-                        );
-                        continue;
-                    }
-                    _ => {
-                        return Err(CreateCrd {
-                            name: crd.name_any(),
-                        }
-                        .into_error(err))
+/// Installs CRDs which are missing from the target helm chart cluster which are missing
+/// from the cluster.
+async fn install_missing_crds(crd_client: &Api<Crd>, crd_dir_path: PathBuf) -> Result<()> {
+    for crd in crds_from_dir(crd_dir_path)? {
+        // Create CRDs, and ignore creation failures due to the CRD already
+        // existing in the cluster.
+        let pp = PostParams::default();
+        let creation_result = crd_client.create(&pp, &crd).await;
+        if let Err(err) = creation_result {
+            match err {
+                // Return early if creation has failed due to the CRD already existing in the
+                // cluster.
+                // Ref: https://github.com/kubernetes/apimachinery/blob/v0.27.3/pkg/apis/meta/v1/types.go#L846
+                // TODO: It could be that the existing CRD registers a CR of a different version
+                //       than the one bundled with the target helm chart. This needs to be
+                // handled.
+                kube::Error::Api(response) if response.reason.eq("AlreadyExists") => {
+                    info!(
+                        "CustomResourceDefinition '{}' already exists",
+                        crd.name_any()
+                    );
+                    continue;
+                }
+                _ => {
+                    return Err(CreateCrd {
+                        name: crd.name_any(),
                     }
+                    .into_error(err))
                 }
             }
-            info!("Created CustomResourceDefinition '{}'", crd.name_any());
         }
+        info!("Created CustomResourceDefinition '{}'", crd.name_any());
     }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::process::ExitStatusExt;
+
+    #[test]
+    fn failed_helm_command_error_is_redacted() {
+        let redactor = ValueRedactor::new(&["password".to_string()]).unwrap();
+        let args = vec_to_strings![
+            "upgrade",
+            "mayastor",
+            "--set",
+            "etcd.auth.rootPassword=hunter2,image.tag=v2.4.0"
+        ];
+        let output = Output {
+            // This is the raw wait status of a process which exited with the exit code 1.
+            status: std::process::ExitStatus::from_raw(1 << 8),
+            stdout: Vec::new(),
+            stderr: b"Error: UPGRADE FAILED".to_vec(),
+        };
+
+        let error = ensure_helm_success("helm", redactor.redact_args(args.as_slice()), &output)
+            .unwrap_err()
+            .to_string();
+        assert!(!error.contains("hunter2"));
+        assert!(error.contains("etcd.auth.rootPassword=*****"));
+        assert!(error.contains("exit code 1"));
+    }
+
+    #[test]
+    fn empty_helm_list_output_lists_no_releases() {
+        assert!(parse_helm_list_output("").unwrap().is_empty());
+        assert!(parse_helm_list_output("\n").unwrap().is_empty());
+    }
+
+    #[test]
+    fn helm_list_output_lists_releases() {
+        let stdout = "- name: mayastor\n  chart: mayastor-2.4.0\n  revision: \"3\"\n";
+        let releases = parse_helm_list_output(stdout).unwrap();
+        assert_eq!(releases.len(), 1);
+        assert_eq!(releases[0].name(), "mayastor");
+        assert_eq!(releases[0].chart(), "mayastor-2.4.0");
+    }
+}
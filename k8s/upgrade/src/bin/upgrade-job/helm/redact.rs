@@ -0,0 +1,96 @@
+use crate::common::error::{RegexCompile, Result};
+use regex::Regex;
+use snafu::ResultExt;
+
+/// This is the mask which the redacted helm values are replaced with.
+const REDACTED_VALUE: &str = "*****";
+
+/// These are the helm flags whose arguments are comma-separated 'key=value' pairs.
+const SET_FLAGS: [&str; 4] = ["--set", "--set-string", "--set-json", "--set-literal"];
+
+/// This masks the values of the sensitive keys in the '--set' style arguments of a helm command,
+/// so that the helm arguments may be logged and used in error messages.
+#[derive(Clone, Default)]
+pub(crate) struct ValueRedactor {
+    key_patterns: Vec<Regex>,
+}
+
+impl ValueRedactor {
+    /// This creates a ValueRedactor which masks the values of the keys which match any of the
+    /// key patterns. The patterns are case-insensitive regular expressions, which may match any
+    /// part of the key, e.g. 'password' matches the key 'etcd.auth.rootPassword'.
+    pub(crate) fn new(key_patterns: &[String]) -> Result<Self> {
+        let key_patterns = key_patterns
+            .iter()
+            .map(|pattern| {
+                let expression = format!("(?i){pattern}");
+                Regex::new(expression.as_str()).context(RegexCompile { expression })
+            })
+            .collect::<Result<Vec<Regex>>>()?;
+
+        Ok(Self { key_patterns })
+    }
+
+    /// Returns a copy of the helm arguments with the values of the sensitive keys masked. Both
+    /// the '--set k=v' and the '--set=k=v' forms are redacted.
+    pub(crate) fn redact_args(&self, args: &[String]) -> Vec<String> {
+        if self.key_patterns.is_empty() {
+            return args.to_vec();
+        }
+
+        let mut redacted_args: Vec<String> = Vec::with_capacity(args.len());
+        let mut follows_set_flag = false;
+        for arg in args {
+            if follows_set_flag {
+                redacted_args.push(self.redact_set_values(arg));
+                follows_set_flag = false;
+                continue;
+            }
+
+            match arg.split_once('=') {
+                Some((flag, value)) if SET_FLAGS.contains(&flag) => {
+                    redacted_args.push(format!("{flag}={}", self.redact_set_values(value)));
+                }
+                _ => {
+                    follows_set_flag = SET_FLAGS.contains(&arg.as_str());
+                    redacted_args.push(arg.clone());
+                }
+            }
+        }
+
+        redacted_args
+    }
+
+    /// Masks the values of the sensitive keys in a comma-separated list of 'key=value' pairs.
+    /// Commas escaped with a '\' are a part of the value, as they are for helm.
+    pub(crate) fn redact_set_values(&self, set_values: &str) -> String {
+        let mut pairs: Vec<String> = Vec::new();
+        let mut pair = String::new();
+        let mut escaped = false;
+        for c in set_values.chars() {
+            if c.eq(&',') && !escaped {
+                pairs.push(std::mem::take(&mut pair));
+                continue;
+            }
+            escaped = c.eq(&'\\') && !escaped;
+            pair.push(c);
+        }
+        pairs.push(pair);
+
+        pairs
+            .into_iter()
+            .map(|pair| match pair.split_once('=') {
+                Some((key, _)) if self.is_sensitive(key) => format!("{key}={REDACTED_VALUE}"),
+                _ => pair,
+            })
+            .collect::<Vec<String>>()
+            .join(",")
+    }
+
+    /// This is a predicate which is true if the helm value key matches any of the key patterns.
+    fn is_sensitive(&self, key: &str) -> bool {
+        self.key_patterns
+            .iter()
+            .any(|pattern| pattern.is_match(key))
+    }
+}
@@ -0,0 +1,134 @@
+use crate::{
+    common::{
+        constants::CORE_CHART_NAME,
+        error::{
+            HelmCommand, HelmPullCommand, HelmRepoAddCommand, Result, TempDirCreation,
+            U8VectorToString,
+        },
+    },
+    helm::helm_binary,
+    opts::masked_url,
+    vec_to_strings,
+};
+use snafu::{ensure, ResultExt};
+use std::{path::PathBuf, process::Command, str};
+use tempfile::TempDir;
+use tracing::{debug, error, info};
+
+/// This is a Core helm chart which has been pulled from a classic helm repository. The helm
+/// repository which was added to pull the chart is removed, and the pulled chart is deleted,
+/// when this is dropped.
+#[derive(Debug)]
+pub(crate) struct HelmRepoChart {
+    repo_name: String,
+    pull_dir: TempDir,
+}
+
+impl HelmRepoChart {
+    /// Runs commands `helm repo add <repo_name> <repo_url>` and
+    /// `helm pull <repo_name>/<chart> --version <chart_version> --untar` to obtain the Core helm
+    /// chart and its values.yaml file.
+    pub(crate) fn pull(repo_url: &str, repo_name: &str, chart_version: &str) -> Result<Self> {
+        let command: &str = helm_binary();
+
+        let args: Vec<String> =
+            vec_to_strings!["repo", "add", repo_name, repo_url, "--force-update"];
+        // The repository URL may carry basic-auth credentials, so its password is masked wherever
+        // the command or its output is logged or reported.
+        let masked_repo_url = masked_url(repo_url);
+        let masked_args: Vec<String> = vec_to_strings![
+            "repo",
+            "add",
+            repo_name,
+            masked_repo_url.as_str(),
+            "--force-update"
+        ];
+        debug!(%command, args = ?masked_args, "Helm repo add command");
+        let output = Command::new(command)
+            .args(args)
+            .output()
+            .context(HelmCommand {
+                command: command.to_string(),
+                args: masked_args.clone(),
+            })?;
+        ensure!(
+            output.status.success(),
+            HelmRepoAddCommand {
+                command: command.to_string(),
+                args: masked_args,
+                // helm echoes the repository URL in some of its errors.
+                std_err: str::from_utf8(output.stderr.as_slice())
+                    .context(U8VectorToString)?
+                    .replace(repo_url, masked_repo_url.as_str())
+            }
+        );
+
+        // The repository is removed on drop, from here on.
+        let chart = Self {
+            repo_name: repo_name.to_string(),
+            pull_dir: TempDir::new().context(TempDirCreation)?,
+        };
+
+        let args: Vec<String> = vec_to_strings![
+            "pull",
+            format!("{repo_name}/{CORE_CHART_NAME}"),
+            "--version",
+            chart_version,
+            "--untar",
+            "--untardir",
+            chart.pull_dir.path().to_string_lossy()
+        ];
+        debug!(%command, ?args, "Helm pull command");
+        let output = Command::new(command)
+            .args(args.clone())
+            .output()
+            .context(HelmCommand {
+                command: command.to_string(),
+                args: args.clone(),
+            })?;
+        ensure!(
+            output.status.success(),
+            HelmPullCommand {
+                command: command.to_string(),
+                args,
+                std_err: str::from_utf8(output.stderr.as_slice())
+                    .context(U8VectorToString)?
+                    .to_string()
+            }
+        );
+
+        info!(
+            repo_url = %masked_repo_url,
+            chart_version, "Pulled {CORE_CHART_NAME} helm chart from helm repository"
+        );
+        Ok(chart)
+    }
+
+    /// This returns the directory which the Core helm chart was pulled into.
+    pub(crate) fn chart_dir(&self) -> PathBuf {
+        self.pull_dir.path().join(CORE_CHART_NAME)
+    }
+}
+
+impl Drop for HelmRepoChart {
+    fn drop(&mut self) {
+        let command: &str = helm_binary();
+        let args: Vec<String> = vec_to_strings!["repo", "remove", self.repo_name.as_str()];
+        debug!(%command, ?args, "Helm repo remove command");
+
+        // The pulled chart is deleted when the TempDir is dropped, after this.
+        match Command::new(command).args(args).output() {
+            Ok(output) if output.status.success() => {}
+            Ok(output) => error!(
+                repo_name = %self.repo_name,
+                std_err = %String::from_utf8_lossy(output.stderr.as_slice()),
+                "Failed to remove helm repository"
+            ),
+            Err(error) => error!(
+                %error,
+                repo_name = %self.repo_name,
+                "Failed to remove helm repository"
+            ),
+        }
+    }
+}
@@ -4,18 +4,51 @@ use crate::{
         error::{
             CoreChartUpgradeNoneChartDir, HelmUpgradeOptionsAbsent, InvalidHelmUpgrade,
             InvalidUpgradePath, NoInputHelmChartDir, NotAKnownHelmChart, RegexCompile, Result,
-            RollbackForbidden, UmbrellaChartNotUpgraded,
+            RollbackForbidden, SemverParse, UmbrellaChartNotUpgraded, UnsupportedVersionSkew,
         },
     },
-    helm::{client::HelmReleaseClient, values::generate_values_yaml_file},
+    helm::{
+        client::HelmReleaseClient,
+        values::{core_chart_image_tag, generate_values_yaml_file, warn_about_removed_values},
+    },
+    metrics::metrics,
+    opts::ValuesMode,
     upgrade, vec_to_strings,
 };
 use regex::Regex;
 use semver::Version;
+use serde::Serialize;
 use snafu::{ensure, ResultExt};
-use std::{future::Future, path::PathBuf, pin::Pin};
+use std::{
+    fmt::{Display, Formatter},
+    future::Future,
+    path::PathBuf,
+    pin::Pin,
+    time::{Duration, Instant},
+};
 use tempfile::NamedTempFile as TempFile;
-use tracing::info;
+use tracing::{info, warn};
+
+/// This matches a 'major.minor.patch' version, without a pre-release or build metadata.
+const STRICT_SEMVER_REGEX: &str = r"[0-9]+\.[0-9]+\.[0-9]+";
+
+/// This matches a semver 2.0.0 version, with an optional pre-release and build metadata.
+const SEMVER_REGEX: &str = concat!(
+    r"[0-9]+\.[0-9]+\.[0-9]+",
+    r"(-[0-9A-Za-z-]+(\.[0-9A-Za-z-]+)*)?",
+    r"(\+[0-9A-Za-z-]+(\.[0-9A-Za-z-]+)*)?"
+);
+
+/// This returns the regular expression which matches the chart name and version of the named
+/// helm chart, e.g. 'mayastor-2.3.0'. The version must be a strict semver if strict_semver is set.
+fn chart_name_regex(chart_name: &str, strict_semver: bool) -> String {
+    let chart_version_regex = if strict_semver {
+        STRICT_SEMVER_REGEX
+    } else {
+        SEMVER_REGEX
+    };
+    format!(r"^({chart_name}-{chart_version_regex})$")
+}
 
 /// This is the helm chart variant of the helm chart installed in the cluster.
 /// The PRODUCT may be installed using either of these options, but never both.
@@ -26,14 +59,30 @@ pub(crate) enum HelmChart {
     Core,
 }
 
+impl Display for HelmChart {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let chart_variant = match self {
+            Self::Umbrella => "umbrella",
+            Self::Core => "core",
+        };
+        write!(f, "{chart_variant}")
+    }
+}
+
 /// This is a builder for the Helm chart upgrade.
 #[derive(Default)]
 pub(crate) struct HelmUpgradeBuilder {
     release_name: Option<String>,
     namespace: Option<String>,
     core_chart_dir: Option<PathBuf>,
+    image_tag: Option<String>,
     skip_upgrade_path_validation: bool,
+    allow_unsupported_skew: bool,
     values: Option<String>,
+    values_mode: ValuesMode,
+    strict_semver: bool,
+    redacted_value_keys: Vec<String>,
+    set_values: Vec<String>,
 }
 
 impl HelmUpgradeBuilder {
@@ -59,8 +108,17 @@ impl HelmUpgradeBuilder {
 
     /// This is a builder option to set the directory path of the Umbrella helm chart CLI option.
     #[must_use]
-    pub(crate) fn with_core_chart_dir(mut self, dir: PathBuf) -> Self {
-        self.core_chart_dir = Some(dir);
+    pub(crate) fn with_core_chart_dir(mut self, dir: Option<PathBuf>) -> Self {
+        self.core_chart_dir = dir;
+        self
+    }
+
+    /// This is a builder option to set the container image tag to upgrade to. If set, the image
+    /// tag is set directly instead of the chart's values.yaml being read, and the chart is only
+    /// required if the helm release is not already on the image tag's version.
+    #[must_use]
+    pub(crate) fn with_image_tag(mut self, image_tag: Option<String>) -> Self {
+        self.image_tag = image_tag;
         self
     }
 
@@ -74,6 +132,13 @@ impl HelmUpgradeBuilder {
         self
     }
 
+    /// This sets the flag to allow upgrades across more than a single minor version.
+    #[must_use]
+    pub(crate) fn with_allow_unsupported_skew(mut self, allow_unsupported_skew: bool) -> Self {
+        self.allow_unsupported_skew = allow_unsupported_skew;
+        self
+    }
+
     /// This is a builder option to add set flags set during upgrade.
     #[must_use]
     pub(crate) fn with_values<J>(mut self, values: J) -> Self
@@ -84,6 +149,37 @@ impl HelmUpgradeBuilder {
         self
     }
 
+    /// This is a builder option to set the mode which decides the helm values used for the
+    /// upgrade.
+    #[must_use]
+    pub(crate) fn with_values_mode(mut self, values_mode: ValuesMode) -> Self {
+        self.values_mode = values_mode;
+        self
+    }
+
+    /// This sets the flag to only accept helm chart versions of the form 'major.minor.patch'.
+    #[must_use]
+    pub(crate) fn with_strict_semver(mut self, strict_semver: bool) -> Self {
+        self.strict_semver = strict_semver;
+        self
+    }
+
+    /// This is a builder option to add the patterns of the helm value keys whose values are
+    /// masked in the logs and error messages of the helm commands.
+    #[must_use]
+    pub(crate) fn with_redacted_value_keys(mut self, key_patterns: Vec<String>) -> Self {
+        self.redacted_value_keys = key_patterns;
+        self
+    }
+
+    /// This is a builder option to add the helm values, in the 'key=value' form, which are set
+    /// after all of the other values of the upgrade, so that these take precedence.
+    #[must_use]
+    pub(crate) fn with_set_values(mut self, set_values: Vec<String>) -> Self {
+        self.set_values = set_values;
+        self
+    }
+
     /// This builds the HelmUpgrade object.
     pub(crate) async fn build(self) -> Result<HelmUpgrade> {
         ensure!(
@@ -93,54 +189,83 @@ impl HelmUpgradeBuilder {
         let release_name = self.release_name.clone().unwrap();
         let namespace = self.namespace.clone().unwrap();
         let values = self.values.clone().unwrap_or_default();
+        // An explicit image tag is set directly, instead of being read from the chart's values.
+        let values = match self.image_tag.as_deref() {
+            Some(tag) if values.is_empty() => format!("image.tag={tag}"),
+            Some(tag) => format!("{values},image.tag={tag}"),
+            None => values,
+        };
 
         // Generate HelmReleaseClient.
         let client = HelmReleaseClient::builder()
             .with_namespace(namespace.clone())
+            .with_redacted_value_keys(self.redacted_value_keys.clone())
             .build()?;
 
         // Get HelmReleaseElement object for the release specified in CLI options.
-        let chart = client.release_info(release_name.clone())?.chart();
+        let release = client.release_info(release_name.clone())?;
+        let chart = release.chart();
+        let from_revision = release.revision();
 
         // The version of the Core helm chart (installed as a the parent chart or as a dependent
         // chart) which is installed in the cluster.
         let from_version: Version =
             upgrade::path::version_from_rest_deployment_label(namespace.as_str()).await?;
 
-        // The version of the Core chart which we are (maybe) going to.
-        let chart_dir: PathBuf = self.core_chart_dir.ok_or(
-            NoInputHelmChartDir {
-                chart_name: CORE_CHART_NAME.to_string(),
+        // The version of the Core chart which we are (maybe) going to. Without a chart, this is
+        // the version of the image tag.
+        let chart_dir: Option<PathBuf> = self.core_chart_dir.clone();
+        let to_version: Version = match (chart_dir.as_ref(), self.image_tag.as_deref()) {
+            (Some(chart_dir), _) => {
+                upgrade::path::version_from_chart_yaml_file(chart_dir.join("Chart.yaml"))?
+            }
+            (None, Some(tag)) => {
+                Version::parse(tag.trim_start_matches('v')).context(SemverParse {
+                    version_string: tag.to_string(),
+                })?
             }
-            .build(),
-        )?;
-        let chart_yaml_path = chart_dir.join("Chart.yaml");
-        let to_version: Version = upgrade::path::version_from_chart_yaml_file(chart_yaml_path)?;
+            (None, None) => {
+                return NoInputHelmChartDir {
+                    chart_name: CORE_CHART_NAME.to_string(),
+                }
+                .fail()
+            }
+        };
 
         // Check if already upgraded.
         let already_upgraded = to_version.eq(&from_version);
 
+        // The container image tag which the io-engine Pods are upgraded to, if it is known.
+        let to_image_tag = match (self.image_tag.clone(), chart_dir.as_ref()) {
+            (Some(image_tag), _) => Some(image_tag),
+            (None, Some(chart_dir)) => core_chart_image_tag(chart_dir.as_path())
+                .map_err(|error| {
+                    warn!(%error, "Failed to read the image tag of the target helm chart");
+                })
+                .ok(),
+            (None, None) => None,
+        };
+
         // Define regular expression to pick out the chart name from the
         // <chart-name>-<chart-version> string.
-        let umbrella_chart_regex = format!(r"^({UMBRELLA_CHART_NAME}-[0-9]+\.[0-9]+\.[0-9]+)$");
-        // Accepts pre-release and release, both.
-        // Q: How do I read this regex?
-        // A: This regular expressions is bounded by the '^' and '$' characters, which means
+        // Q: How do I read these regexes?
+        // A: These regular expressions are bounded by the '^' and '$' characters, which means
         //    that the input string has to match all of the expression exactly. It is not enough
         //    if a substring within the input string matches the regular expression. The pattern
         //    requires the following conditions to be met:
-        //    1. The string must start with the literal contained in the literal CORE_CHART_NAME.
+        //    1. The string must start with the value of UMBRELLA_CHART_NAME or CORE_CHART_NAME.
         //       e.g.: mayastor-2.2.0 starts with 'mayastor'
         //    2. A '-' followed by three sets of numbers (each with one or more) separated by '.',
-        //       must sit after the value of CORE_CHART_NAME. e.g. mayastor-4.56.789 is a valid
-        //       chart-name.
-        //    3. A '-' followed by one or many alphanumeric characters may optionally sit after a
-        //       chart-name like 'mayastor-1.2.3'. e.g.: mayastor-1.2.3-testing,
-        //       mayastor-1.2.3-testing-upgrade-23-35-25-05-2023, mayastor-2.3.0-rc-3
-        //    4. The optional group of character(s) mentioned in (3) above, may optionally contain a
-        //       '.' followed by a set of numbers. e.g.: mayastor-2.3.4-rc.1, mayastor-2.3.4-alpha.2
-        let core_chart_regex =
-            format!(r"^({CORE_CHART_NAME}-[0-9]+\.[0-9]+\.[0-9]+(-[a-zA-Z0-9]+(\.[0-9]+)?)*)$");
+        //       must sit after the chart name. e.g. mayastor-4.56.789 is a valid chart-name.
+        //    3. Unless strict semver is required, a semver pre-release and build metadata may
+        //       optionally sit after the version. The pre-release is a '-' followed by one or more
+        //       '.' separated identifiers, and the build metadata is a '+' followed by the same.
+        //       Each identifier is made up of alphanumeric characters and '-'. e.g.:
+        //       mayastor-2.3.0-rc1, mayastor-2.3.4-rc.1, mayastor-2.3.0-rc-3,
+        //       mayastor-1.2.3-testing-upgrade-23-35-25-05-2023, mayastor-2.3.0+build.5,
+        //       mayastor-2.3.0-alpha.2+20230525
+        let umbrella_chart_regex = chart_name_regex(UMBRELLA_CHART_NAME, self.strict_semver);
+        let core_chart_regex = chart_name_regex(CORE_CHART_NAME, self.strict_semver);
 
         // Validate if already upgraded for Umbrella chart, and prepare for upgrade for Core chart.
         let chart_variant: HelmChart;
@@ -180,25 +305,106 @@ impl HelmUpgradeBuilder {
                 ensure!(upgrade_path_is_valid, InvalidUpgradePath);
             }
 
-            // Generate values yaml file for upgrade
-            let _upgrade_values_file = generate_values_yaml_file(
-                &from_version,
-                chart_dir.as_path(),
-                &client,
-                release_name.clone(),
-            )?;
+            // The installed chart's version is compared against the target chart's version, as
+            // upgrades across more than a single minor version are unsupported.
+            if chart_dir.is_some() {
+                let deployed_version = release
+                    .chart_version()
+                    .unwrap_or_else(|| from_version.clone());
+                ensure!(
+                    self.allow_unsupported_skew
+                        || upgrade::path::version_skew_is_supported(&deployed_version, &to_version),
+                    UnsupportedVersionSkew {
+                        from_version: deployed_version.to_string(),
+                        to_version: to_version.to_string()
+                    }
+                );
+            }
 
-            core_chart_dir = Some(chart_dir);
+            // Without a chart, only the data-plane of an already upgraded release is restarted.
+            let Some(chart_dir) = chart_dir else {
+                ensure!(
+                    already_upgraded,
+                    NoInputHelmChartDir {
+                        chart_name: CORE_CHART_NAME.to_string(),
+                    }
+                );
+                return Ok(HelmUpgrade {
+                    chart_variant,
+                    already_upgraded,
+                    core_chart_dir,
+                    release_name,
+                    client,
+                    core_chart_extra_args,
+                    from_version,
+                    from_revision,
+                    to_version,
+                    to_image_tag,
+                    upgrade_values_file,
+                });
+            };
+
+            // The user's values are carried over to the target chart, unless they're reset. This
+            // check only warns, so failing to run it does not fail the upgrade.
+            if self.values_mode.ne(&ValuesMode::Reset) {
+                if let Err(error) =
+                    warn_about_removed_values(chart_dir.as_path(), &client, release_name.clone())
+                {
+                    warn!(%error, "Failed to check for user-set values removed from the chart");
+                }
+            }
+
+            // An explicit '--image-tag' is only set as the 'image.tag' override, the values file
+            // is generated for the chart upgrade all the same.
+            match self.values_mode {
+                ValuesMode::Reuse => {
+                    // Generate values yaml file for upgrade
+                    let _upgrade_values_file = generate_values_yaml_file(
+                        &from_version,
+                        chart_dir.as_path(),
+                        &client,
+                        release_name.clone(),
+                    )?;
+
+                    // helm upgrade .. -f <values-yaml> --atomic
+                    core_chart_extra_args = Some(vec_to_strings![
+                        "-f",
+                        _upgrade_values_file.path().to_string_lossy(),
+                        "--set",
+                        values,
+                        "--atomic"
+                    ]);
+                    upgrade_values_file = Some(_upgrade_values_file)
+                }
+                ValuesMode::Reset => {
+                    // helm upgrade .. --reset-values --atomic
+                    core_chart_extra_args = Some(vec_to_strings![
+                        "--reset-values",
+                        "--set",
+                        values,
+                        "--atomic"
+                    ]);
+                }
+                ValuesMode::ResetThenReuse => {
+                    // helm upgrade .. --reset-then-reuse-values --atomic
+                    core_chart_extra_args = Some(vec_to_strings![
+                        "--reset-then-reuse-values",
+                        "--set",
+                        values,
+                        "--atomic"
+                    ]);
+                }
+            }
 
-            // helm upgrade .. -f <values-yaml> --atomic
-            core_chart_extra_args = Some(vec_to_strings![
-                "-f",
-                _upgrade_values_file.path().to_string_lossy(),
-                "--set",
-                values,
-                "--atomic"
-            ]);
-            upgrade_values_file = Some(_upgrade_values_file)
+            // The user's '--set' values are merged last, e.g. after '--reuse-values', so that
+            // they override the carried over values.
+            if let Some(extra_args) = core_chart_extra_args.as_mut() {
+                for set_value in self.set_values.iter() {
+                    extra_args.extend(vec_to_strings!["--set", set_value]);
+                }
+            }
+
+            core_chart_dir = Some(chart_dir);
         } else {
             // Case: Helm chart release is not a known helm chart installation.
             return NotAKnownHelmChart { chart_name: chart }.fail();
@@ -212,7 +418,9 @@ impl HelmUpgradeBuilder {
             client,
             core_chart_extra_args,
             from_version,
+            from_revision,
             to_version,
+            to_image_tag,
             upgrade_values_file,
         })
     }
@@ -227,7 +435,9 @@ pub(crate) struct HelmUpgrade {
     client: HelmReleaseClient,
     core_chart_extra_args: Option<Vec<String>>,
     from_version: Version,
+    from_revision: Option<u32>,
     to_version: Version,
+    to_image_tag: Option<String>,
     #[allow(dead_code)]
     upgrade_values_file: Option<TempFile>,
 }
@@ -244,6 +454,7 @@ impl HelmUpgrade {
     pub(crate) async fn dry_run(self) -> Result<HelmUpgradeRunner> {
         match self.chart_variant {
             HelmChart::Umbrella if self.already_upgraded => {
+                let report = self.report();
                 // Returned HelmUpgradeRunner logs and exits.
                 Ok(Box::pin(async move {
                     info!(
@@ -252,7 +463,7 @@ impl HelmUpgrade {
                         self.release_name.as_str()
                     );
 
-                    Ok(())
+                    Ok(report)
                 }))
             }
             HelmChart::Umbrella if !self.already_upgraded => {
@@ -260,6 +471,7 @@ impl HelmUpgrade {
                 UmbrellaChartNotUpgraded.fail()
             }
             HelmChart::Core if self.already_upgraded => {
+                let report = self.report();
                 // Returned HelmUpgradeRunner logs and exits.
                 Ok(Box::pin(async move {
                     info!(
@@ -267,7 +479,7 @@ impl HelmUpgrade {
                         is the same as that of this upgrade-job's helm chart"
                     );
 
-                    Ok(())
+                    Ok(report)
                 }))
             }
             HelmChart::Core if !self.already_upgraded => {
@@ -291,6 +503,7 @@ impl HelmUpgrade {
                     .await?;
                 info!("Helm upgrade dry-run succeeded!");
 
+                let report = self.report();
                 // Returning HelmUpgradeRunner.
                 Ok(Box::pin(async move {
                     // Pinning the helm values file handle to this closure so that it is not
@@ -300,32 +513,143 @@ impl HelmUpgrade {
                     let _values_file = self.upgrade_values_file;
 
                     info!("Starting helm upgrade...");
-                    self.client
+                    let started_at = Instant::now();
+                    let result = self
+                        .client
                         .upgrade(
                             self.release_name,
                             chart_dir.as_path(),
                             self.core_chart_extra_args,
                             true,
                         )
-                        .await?;
+                        .await;
+                    metrics().observe_helm_upgrade(
+                        self.chart_variant.to_string().as_str(),
+                        started_at.elapsed(),
+                        result.is_err(),
+                    );
+                    result?;
                     info!("Helm upgrade successful!");
 
-                    Ok(())
+                    Ok(report)
                 }))
             }
             _ => InvalidHelmUpgrade.fail(),
         }
     }
 
+    /// This is a predicate which is true if the helm chart release (and with it, the
+    /// control-plane) is already on the upgrade target version.
+    pub(crate) fn control_plane_is_upgraded(&self) -> bool {
+        self.already_upgraded
+    }
+
     pub(crate) fn upgrade_from_version(&self) -> String {
         self.from_version.to_string()
     }
 
+    /// This is the revision of the helm release before the upgrade, if known. A failed upgrade
+    /// may be rolled back to it.
+    pub(crate) fn upgrade_from_revision(&self) -> Option<u32> {
+        self.from_revision
+    }
+
     pub(crate) fn upgrade_to_version(&self) -> String {
         self.to_version.to_string()
     }
+
+    /// This is the container image tag which the upgrade is to, if it is known.
+    pub(crate) fn upgrade_to_image_tag(&self) -> Option<String> {
+        self.to_image_tag.clone()
+    }
+
+    /// This is the report of the helm upgrade, which the rest of the upgrade adds to.
+    fn report(&self) -> UpgradeReport {
+        UpgradeReport::new(
+            self.upgrade_from_version(),
+            self.upgrade_to_version(),
+            self.upgrade_to_image_tag(),
+        )
+    }
+}
+
+/// This is the machine-readable summary of an upgrade. The helm upgrade reports the versions and
+/// the image tag, and the data-plane upgrade adds the Nodes it upgraded and the upgrade's
+/// duration.
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct UpgradeReport {
+    /// This is the version of the Core helm chart upgraded from.
+    from_version: String,
+    /// This is the version of the Core helm chart upgraded to.
+    to_version: String,
+    /// This is the io-engine container image tag upgraded to, if it is known.
+    image_tag: Option<String>,
+    /// These are the names of the Nodes whose io-engine Pods were upgraded.
+    upgraded_nodes: Vec<String>,
+    /// This is the time the upgrade took, in seconds.
+    duration_seconds: u64,
+}
+
+impl UpgradeReport {
+    /// This creates a report of an upgrade with no Nodes upgraded yet.
+    pub(crate) fn new(from_version: String, to_version: String, image_tag: Option<String>) -> Self {
+        Self {
+            from_version,
+            to_version,
+            image_tag,
+            upgraded_nodes: Vec::new(),
+            duration_seconds: 0,
+        }
+    }
+
+    /// This records the Nodes whose io-engine Pods were upgraded. Each Node is recorded once, in
+    /// the order it was first upgraded in.
+    pub(crate) fn set_upgraded_nodes(&mut self, nodes: &[String]) {
+        self.upgraded_nodes.clear();
+        for node in nodes {
+            if !self.upgraded_nodes.contains(node) {
+                self.upgraded_nodes.push(node.clone());
+            }
+        }
+    }
+
+    /// This records the time the upgrade took.
+    pub(crate) fn set_duration(&mut self, duration: Duration) {
+        self.duration_seconds = duration.as_secs();
+    }
 }
 
 /// HelmUpgradeRunner is returned after an upgrade is validated and dry-run-ed. Running
 /// it carries out helm upgrade.
-pub(crate) type HelmUpgradeRunner = Pin<Box<dyn Future<Output = Result<()>>>>;
+pub(crate) type HelmUpgradeRunner = Pin<Box<dyn Future<Output = Result<UpgradeReport>>>>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chart_name_matches(chart: &str, strict_semver: bool) -> bool {
+        Regex::new(chart_name_regex(CORE_CHART_NAME, strict_semver).as_str())
+            .unwrap()
+            .is_match(chart)
+    }
+
+    #[test]
+    fn pre_release_chart_version() {
+        assert!(chart_name_matches("mayastor-2.3.0-rc1", false));
+        assert!(!chart_name_matches("mayastor-2.3.0-rc1", true));
+    }
+
+    #[test]
+    fn build_metadata_chart_version() {
+        assert!(chart_name_matches("mayastor-2.3.0+build.5", false));
+        assert!(!chart_name_matches("mayastor-2.3.0+build.5", true));
+    }
+
+    #[test]
+    fn release_chart_version() {
+        assert!(chart_name_matches("mayastor-2.3.0", false));
+        assert!(chart_name_matches("mayastor-2.3.0", true));
+        assert!(!chart_name_matches("openebs-2.3.0", false));
+    }
+}
@@ -13,9 +13,117 @@ use crate::{
     },
 };
 use semver::{Version, VersionReq};
+use serde_yaml::Value;
 use snafu::ResultExt;
 use std::{fs, io::Write, path::Path, str};
 use tempfile::NamedTempFile as TempFile;
+use tracing::warn;
+
+/// Reads the container image tag from the values.yaml of the Core chart.
+pub(crate) fn core_chart_image_tag(chart_dir: &Path) -> Result<String> {
+    let values_filepath = chart_dir.join("values.yaml");
+    let values_yaml = fs::read(values_filepath.as_path()).context(ReadingFile {
+        filepath: values_filepath.clone(),
+    })?;
+    let values: CoreValues =
+        serde_yaml::from_slice(values_yaml.as_slice()).context(YamlParseFromFile {
+            filepath: values_filepath,
+        })?;
+    Ok(values.image_tag().to_string())
+}
+
+/// Warns about the values which the user had set on the release, but which are not in the default
+/// values of the target chart. Helm does not complain about unknown values, so these are likely to
+/// have been removed or renamed in the target chart, and would be silently ignored by it. The
+/// values of the dependency charts are not checked.
+pub(crate) fn warn_about_removed_values(
+    chart_dir: &Path,
+    client: &HelmReleaseClient,
+    release_name: String,
+) -> Result<()> {
+    let defaults_filepath = chart_dir.join("values.yaml");
+    let defaults_yaml = fs::read(defaults_filepath.as_path()).context(ReadingFile {
+        filepath: defaults_filepath.clone(),
+    })?;
+    let defaults: Value =
+        serde_yaml::from_slice(defaults_yaml.as_slice()).context(YamlParseFromFile {
+            filepath: defaults_filepath,
+        })?;
+
+    let user_values_yaml = client.get_user_values_as_yaml(release_name)?;
+    let user_values: Value =
+        serde_yaml::from_slice(user_values_yaml.as_slice()).context(YamlParseFromSlice {
+            input_yaml: str::from_utf8(user_values_yaml.as_slice())
+                .context(U8VectorToString)?
+                .to_string(),
+        })?;
+
+    let dependency_charts: Vec<String> = fs::read_dir(chart_dir.join("charts"))
+        .map(|entries| {
+            entries
+                .filter_map(|entry| entry.ok())
+                .map(|entry| entry.file_name().to_string_lossy().to_string())
+                .collect()
+        })
+        .unwrap_or_default();
+
+    let mut removed_keys: Vec<String> = Vec::new();
+    if let (Value::Mapping(user_values), Value::Mapping(defaults)) = (&user_values, &defaults) {
+        for (key, value) in user_values {
+            let key = key_string(key);
+            // Values under a dependency chart's key are defaulted by the dependency chart.
+            let is_dependency_chart = dependency_charts
+                .iter()
+                .any(|chart| chart.eq(&key) || chart.starts_with(format!("{key}-").as_str()));
+            if is_dependency_chart {
+                continue;
+            }
+            match defaults.get(key.as_str()) {
+                Some(default) => collect_removed_keys(value, default, key, &mut removed_keys),
+                None => removed_keys.push(key),
+            }
+        }
+    }
+
+    if !removed_keys.is_empty() {
+        warn!(
+            keys = ?removed_keys,
+            "Found values set on the release which are not in the target chart's default values, \
+            these may have been removed or renamed and are likely to be ignored after the upgrade"
+        );
+    }
+
+    Ok(())
+}
+
+/// Collects the paths of the keys in the user's value which are not in the chart's default value.
+/// Default values which are empty maps or are not maps at all, accept any keys.
+fn collect_removed_keys(user: &Value, default: &Value, path: String, removed: &mut Vec<String>) {
+    let (Value::Mapping(user), Value::Mapping(default)) = (user, default) else {
+        return;
+    };
+    if default.is_empty() {
+        return;
+    }
+
+    for (key, value) in user {
+        let key_path = format!("{path}.{}", key_string(key));
+        match default.get(key) {
+            Some(default) => collect_removed_keys(value, default, key_path, removed),
+            None => removed.push(key_path),
+        }
+    }
+}
+
+/// Returns the YAML map key as a string.
+fn key_string(key: &Value) -> String {
+    match key {
+        Value::String(key) => key.clone(),
+        key => serde_yaml::to_string(key)
+            .map(|key| key.trim().to_string())
+            .unwrap_or_default(),
+    }
+}
 
 /// This compiles all of the helm values options to be passed during the helm chart upgrade.
 pub(crate) fn generate_values_yaml_file(
@@ -1,14 +1,30 @@
 use crate::{
-    common::{constants::PRODUCT, error::Result},
+    common::{
+        constants::PRODUCT,
+        error::{RestEndpointAbsent, Result},
+        kube_client::init_kube_api_concurrency,
+        rest_client::init_rest_max_retries,
+    },
+    helm::init_helm_binary,
+    metrics::serve_metrics,
     opts::validators::{
-        validate_helm_chart_dir, validate_helm_release, validate_helmv3_in_path,
-        validate_namespace, validate_rest_endpoint,
+        validate_helm_binary, validate_helm_chart_dir, validate_helm_release,
+        validate_helmv3_in_path, validate_namespace, validate_rbac, validate_rest_endpoint,
+    },
+    progress::init_progress,
+    shutdown::{init_shutdown_handler, INTERRUPTED_EXIT_CODE},
+    upgrade::{
+        checks::run_preflight_checks,
+        data_plane::{init_canary_resume_handler, init_drain_poll_max},
+        nodes::list_nodes,
+        plan::plan_data_plane,
+        upgrade,
     },
-    upgrade::upgrade,
 };
 use clap::Parser;
-use opts::CliArgs;
+use opts::{CanaryResume, CliArgs, Command, LogFormat};
 use tracing::{error, info};
+use tracing_subscriber::EnvFilter;
 use utils::{
     print_package_info, raw_version_str,
     tracing_telemetry::{default_tracing_tags, flush_traces, init_tracing},
@@ -17,44 +33,155 @@ use utils::{
 mod common;
 mod events;
 mod helm;
+mod metrics;
+mod notify;
 mod opts;
+mod progress;
+mod s3;
+mod shutdown;
 mod upgrade;
 
 #[tokio::main]
 async fn main() -> Result<()> {
     print_package_info!();
-    init_logging();
 
-    let opts = parse_cli_args().await.map_err(|error| {
-        error!(%error, "Failed to upgrade {PRODUCT}");
+    let opts = CliArgs::parse();
+    let action = command_action(opts.command());
+    let mut opts = validate_cli_args(opts).await.map_err(|error| {
+        error!(%error, "Failed to validate the inputs to {action}");
         error
     })?;
 
-    upgrade(&opts).await.map_err(|error| {
-        error!(%error, "Failed to upgrade {PRODUCT}");
+    let result = match opts.command() {
+        Some(Command::PlanDataPlane { image_tag }) => {
+            plan_data_plane(&opts, image_tag.clone()).await
+        }
+        Some(Command::ListNodes { image_tag, output }) => {
+            list_nodes(&opts, image_tag.clone(), *output).await
+        }
+        Some(Command::Preflight) => run_preflight_checks(&mut opts).await,
+        None => upgrade_serving_metrics(&opts).await,
+    };
+
+    result.map_err(|error| {
+        error!(%error, "Failed to {action}");
         flush_traces();
+        if error.is_interrupted() {
+            std::process::exit(INTERRUPTED_EXIT_CODE);
+        }
         error
     })
 }
 
-/// Initialize logging components -- tracing.
-fn init_logging() {
-    let tags = default_tracing_tags(raw_version_str(), env!("CARGO_PKG_VERSION"));
+/// Returns what the command does, e.g. 'upgrade Mayastor', for the log line of its failure.
+fn command_action(command: Option<&Command>) -> String {
+    match command {
+        Some(Command::PlanDataPlane { .. }) => "plan the data-plane upgrade".to_string(),
+        Some(Command::ListNodes { .. }) => format!("list the {PRODUCT} Nodes"),
+        Some(Command::Preflight) => "run the upgrade preflight checks".to_string(),
+        None => format!("upgrade {PRODUCT}"),
+    }
+}
+
+/// Runs the upgrade, serving the upgrade metrics while it runs if a metrics port is given.
+async fn upgrade_serving_metrics(opts: &CliArgs) -> Result<()> {
+    let metrics_server = match opts.metrics_port() {
+        Some(port) => Some(serve_metrics(port)?),
+        None => None,
+    };
 
-    init_tracing("upgrade-job", tags, None);
+    let result = upgrade(opts).await;
+
+    if let Some(metrics_server) = metrics_server {
+        metrics_server.shutdown().await;
+    }
+    result
 }
 
-/// This function handles the following tasks -- 1. Argument parsing, 2. Validating arguments whose
-/// validation depends on other arguments.
-pub(crate) async fn parse_cli_args() -> Result<CliArgs> {
-    let opts = CliArgs::parse();
+/// Initialize logging components -- tracing. With the 'json' log format, each log line is a JSON
+/// object, and the fields of the log line are keys of the object.
+fn init_logging(log_format: LogFormat) {
+    match log_format {
+        LogFormat::Pretty => {
+            let tags = default_tracing_tags(raw_version_str(), env!("CARGO_PKG_VERSION"));
+
+            init_tracing("upgrade-job", tags, None);
+        }
+        LogFormat::Json => {
+            tracing_subscriber::fmt()
+                .json()
+                .flatten_event(true)
+                .with_env_filter(
+                    EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info")),
+                )
+                .init();
+        }
+    }
+}
+
+/// This function handles the following tasks -- 1. Initializing the components which the parsed
+/// arguments configure, 2. Validating arguments whose validation depends on other arguments.
+pub(crate) async fn validate_cli_args(mut opts: CliArgs) -> Result<CliArgs> {
+    if opts.dump_effective_config() {
+        print!("{}", opts.effective_config_yaml()?);
+        std::process::exit(0);
+    }
+
+    init_logging(opts.log_format());
+
+    init_kube_api_concurrency(opts.kube_api_concurrency());
+    init_rest_max_retries(opts.max_retries());
+    init_drain_poll_max(opts.drain_poll_max());
+    init_helm_binary(opts.helm_binary());
+    init_progress(opts.progress());
+    init_shutdown_handler();
+    if opts.canary_first() && opts.canary_resume().eq(&CanaryResume::Signal) {
+        init_canary_resume_handler()?;
+    }
+
+    // The preflight checks run these validations themselves, and report on each of them.
+    if matches!(opts.command(), Some(Command::Preflight)) {
+        return Ok(opts);
+    }
+
+    validate_helm_binary(opts.helm_binary().as_str())?;
 
     validate_namespace(opts.namespace()).await?;
-    validate_rest_endpoint(opts.rest_endpoint()).await?;
+    if opts.control_plane_namespace().ne(&opts.namespace()) {
+        validate_namespace(opts.control_plane_namespace()).await?;
+    }
+    validate_rbac(&opts).await?;
+    // The REST API is not used when the io-engine Pods are not restarted.
+    if let Some(rest_endpoint) = opts.rest_endpoint_opt() {
+        validate_rest_endpoint(rest_endpoint, opts.namespace()).await?;
+    } else if !opts.skip_data_plane_restart() {
+        return RestEndpointAbsent.fail();
+    }
 
     validate_helmv3_in_path()?;
     validate_helm_release(opts.release_name(), opts.namespace())?;
-    validate_helm_chart_dir(opts.core_chart_dir())?;
+
+    // The chart is not required to plan the data-plane upgrade to, to list the storage Nodes
+    // against, or to upgrade to, a known image tag.
+    let chart_is_required = opts.image_tag().is_none()
+        && !matches!(
+            opts.command(),
+            Some(Command::PlanDataPlane { image_tag: Some(_) })
+                | Some(Command::ListNodes {
+                    image_tag: Some(_),
+                    ..
+                })
+        );
+    if chart_is_required {
+        opts.pull_core_chart()?;
+        validate_helm_chart_dir(opts.core_chart_dir())?;
+    } else if opts.image_tag().is_some() {
+        // The chart is still used for the helm upgrade, if one is given.
+        opts.pull_core_chart()?;
+        if opts.core_chart_dir().is_some() {
+            validate_helm_chart_dir(opts.core_chart_dir())?;
+        }
+    }
 
     info!("Validated all inputs");
 
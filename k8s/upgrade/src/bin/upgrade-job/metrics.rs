@@ -0,0 +1,222 @@
+use crate::{
+    common::error::{MetricsServerBind, Result},
+    upgrade::phase::UpgradePhase,
+};
+use hyper::{
+    header::{HeaderValue, CONTENT_TYPE},
+    service::{make_service_fn, service_fn},
+    Body, Request, Response, Server,
+};
+use prometheus::{
+    histogram_opts, opts, Encoder, HistogramVec, IntCounter, IntCounterVec, IntGauge, TextEncoder,
+    TEXT_FORMAT,
+};
+use snafu::ResultExt;
+use std::{convert::Infallible, net::SocketAddr, sync::OnceLock, time::Duration};
+use tokio::{sync::oneshot, task::JoinHandle};
+use tracing::{info, warn};
+
+/// This is the label for the helm chart variant which is upgraded.
+const CHART_VARIANT: &str = "chart_variant";
+
+/// This is the label for the Node whose io-engine Pod is restarted.
+const NODE: &str = "node";
+
+/// This is the label for the step of the io-engine Pod restart.
+const STEP: &str = "step";
+
+/// These are the metrics recorded by the upgrade-job.
+pub(crate) struct UpgradeMetrics {
+    helm_upgrade_duration_seconds: HistogramVec,
+    helm_upgrade_failures_total: IntCounterVec,
+    data_plane_node_step_duration_seconds: HistogramVec,
+    upgrade_nodes_total: IntGauge,
+    upgrade_nodes_completed: IntCounter,
+    upgrade_phase: IntGauge,
+}
+
+impl UpgradeMetrics {
+    /// Initialize all of the metrics and register them with the default prometheus registry.
+    fn new() -> Self {
+        let helm_upgrade_duration_seconds = HistogramVec::new(
+            histogram_opts!(
+                "helm_upgrade_duration_seconds",
+                "Time taken by the helm upgrade of the control-plane",
+                vec![15.0, 30.0, 60.0, 120.0, 300.0, 600.0, 900.0]
+            ),
+            &[CHART_VARIANT],
+        )
+        .expect("Unable to create histogram metric type for helm upgrade duration");
+        let helm_upgrade_failures_total = IntCounterVec::new(
+            opts!(
+                "helm_upgrade_failures_total",
+                "Number of failed helm upgrades of the control-plane"
+            ),
+            &[CHART_VARIANT],
+        )
+        .expect("Unable to create counter metric type for helm upgrade failures");
+        let data_plane_node_step_duration_seconds = HistogramVec::new(
+            histogram_opts!(
+                "data_plane_node_step_duration_seconds",
+                "Time taken by each step of the io-engine Pod restart on a Node",
+                vec![10.0, 30.0, 60.0, 120.0, 300.0, 600.0, 1200.0, 1800.0, 3600.0]
+            ),
+            &[NODE, STEP],
+        )
+        .expect("Unable to create histogram metric type for data-plane node step duration");
+        let upgrade_nodes_total = IntGauge::with_opts(opts!(
+            "upgrade_nodes_total",
+            "Number of Nodes whose io-engine Pods are to be restarted"
+        ))
+        .expect("Unable to create gauge metric type for upgrade nodes total");
+        let upgrade_nodes_completed = IntCounter::with_opts(opts!(
+            "upgrade_nodes_completed",
+            "Number of Nodes whose io-engine Pods have been restarted"
+        ))
+        .expect("Unable to create counter metric type for upgrade nodes completed");
+        let upgrade_phase = IntGauge::with_opts(opts!(
+            "upgrade_phase",
+            "Phase of the upgrade, 0 for helm, 1 for data-plane and 2 for done"
+        ))
+        .expect("Unable to create gauge metric type for upgrade phase");
+
+        let registry = prometheus::default_registry();
+        registry
+            .register(Box::new(helm_upgrade_duration_seconds.clone()))
+            .expect("Unable to register helm upgrade duration metric");
+        registry
+            .register(Box::new(helm_upgrade_failures_total.clone()))
+            .expect("Unable to register helm upgrade failures metric");
+        registry
+            .register(Box::new(data_plane_node_step_duration_seconds.clone()))
+            .expect("Unable to register data-plane node step duration metric");
+        registry
+            .register(Box::new(upgrade_nodes_total.clone()))
+            .expect("Unable to register upgrade nodes total metric");
+        registry
+            .register(Box::new(upgrade_nodes_completed.clone()))
+            .expect("Unable to register upgrade nodes completed metric");
+        registry
+            .register(Box::new(upgrade_phase.clone()))
+            .expect("Unable to register upgrade phase metric");
+
+        Self {
+            helm_upgrade_duration_seconds,
+            helm_upgrade_failures_total,
+            data_plane_node_step_duration_seconds,
+            upgrade_nodes_total,
+            upgrade_nodes_completed,
+            upgrade_phase,
+        }
+    }
+
+    /// Records the duration and the outcome of a helm upgrade.
+    pub(crate) fn observe_helm_upgrade(
+        &self,
+        chart_variant: &str,
+        duration: Duration,
+        failed: bool,
+    ) {
+        self.helm_upgrade_duration_seconds
+            .with_label_values(&[chart_variant])
+            .observe(duration.as_secs_f64());
+        if failed {
+            self.helm_upgrade_failures_total
+                .with_label_values(&[chart_variant])
+                .inc();
+        }
+    }
+
+    /// Records the duration of a step of the io-engine Pod restart on a Node.
+    pub(crate) fn observe_data_plane_node_step(&self, node: &str, step: &str, duration: Duration) {
+        self.data_plane_node_step_duration_seconds
+            .with_label_values(&[node, step])
+            .observe(duration.as_secs_f64());
+    }
+
+    /// Records the number of Nodes whose io-engine Pods are to be restarted.
+    pub(crate) fn set_upgrade_nodes_total(&self, nodes_total: usize) {
+        self.upgrade_nodes_total.set(nodes_total as i64);
+    }
+
+    /// Records the restart of the io-engine Pod on a Node.
+    pub(crate) fn inc_upgrade_nodes_completed(&self) {
+        self.upgrade_nodes_completed.inc();
+    }
+
+    /// Records the phase of the upgrade. The preflight checks and the helm upgrade are recorded as
+    /// 0, the data-plane upgrade and its verification as 1, and the completed upgrade as 2. A
+    /// failed upgrade is left at the phase it failed in.
+    pub(crate) fn set_upgrade_phase(&self, phase: UpgradePhase) {
+        let value = match phase {
+            UpgradePhase::Preflight | UpgradePhase::ControlPlane => 0,
+            UpgradePhase::DataPlane | UpgradePhase::Verify => 1,
+            UpgradePhase::Done => 2,
+            UpgradePhase::Failed => return,
+        };
+        self.upgrade_phase.set(value);
+    }
+}
+
+/// This returns the upgrade-job's metrics, initializing them on first use.
+pub(crate) fn metrics() -> &'static UpgradeMetrics {
+    static METRICS: OnceLock<UpgradeMetrics> = OnceLock::new();
+    METRICS.get_or_init(UpgradeMetrics::new)
+}
+
+/// This is the HTTP server which serves the metrics from the default prometheus registry.
+pub(crate) struct MetricsServer {
+    shutdown: oneshot::Sender<()>,
+    server: JoinHandle<std::result::Result<(), hyper::Error>>,
+}
+
+impl MetricsServer {
+    /// Stops the server, after the scrapes which are being served are done.
+    pub(crate) async fn shutdown(self) {
+        let _ = self.shutdown.send(());
+        match self.server.await {
+            Ok(Ok(())) => info!("Stopped serving metrics"),
+            Ok(Err(error)) => warn!(%error, "Metrics server failed"),
+            Err(error) => warn!(%error, "Metrics server task failed"),
+        }
+    }
+}
+
+/// Serves the metrics on the port, on all interfaces, until the server is shut down.
+pub(crate) fn serve_metrics(port: u16) -> Result<MetricsServer> {
+    // Register the metrics, so that all of them are served from the first scrape.
+    metrics();
+
+    let address = SocketAddr::from(([0, 0, 0, 0], port));
+    let (shutdown, shutdown_signal) = oneshot::channel::<()>();
+    let server = Server::try_bind(&address)
+        .context(MetricsServerBind { port })?
+        .serve(make_service_fn(|_| async {
+            Ok::<_, Infallible>(service_fn(metrics_response))
+        }))
+        .with_graceful_shutdown(async {
+            let _ = shutdown_signal.await;
+        });
+
+    info!(%address, "Serving metrics");
+    Ok(MetricsServer {
+        shutdown,
+        server: tokio::spawn(server),
+    })
+}
+
+/// Responds with the metrics of the default prometheus registry, in the text format.
+async fn metrics_response(
+    _request: Request<Body>,
+) -> std::result::Result<Response<Body>, Infallible> {
+    let mut buffer: Vec<u8> = Vec::new();
+    if let Err(error) = TextEncoder::new().encode(&prometheus::gather(), &mut buffer) {
+        warn!(%error, "Failed to encode metrics");
+    }
+
+    let mut response = Response::new(Body::from(buffer));
+    response
+        .headers_mut()
+        .insert(CONTENT_TYPE, HeaderValue::from_static(TEXT_FORMAT));
+    Ok(response)
+}
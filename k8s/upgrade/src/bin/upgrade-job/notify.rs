@@ -0,0 +1,127 @@
+use crate::{
+    common::{
+        constants::PRODUCT,
+        error::{Error, NotificationClient, Result},
+    },
+    opts::CliArgs,
+};
+use serde_json::{json, Value};
+use snafu::ResultExt;
+use std::time::Duration;
+use tracing::{info, warn};
+
+/// This is the PagerDuty Events API v2 endpoint which incidents are triggered at.
+const PAGERDUTY_EVENTS_URL: &str = "https://events.pagerduty.com/v2/enqueue";
+
+/// This is the time a notification backend is given to accept a notification.
+const NOTIFICATION_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// This sends notifications about the outcome of the upgrade to Slack and PagerDuty, if they are
+/// configured. Delivery is best-effort: failures are logged, and never fail the upgrade.
+pub(crate) struct Notifier {
+    release_name: String,
+    namespace: String,
+    slack_webhook_url: Option<String>,
+    pagerduty_routing_key: Option<String>,
+    notify_on_success: bool,
+    client: reqwest::Client,
+}
+
+impl Notifier {
+    /// This creates a Notifier for the notification backends in the options.
+    pub(crate) fn new(opts: &CliArgs) -> Result<Self> {
+        let client = reqwest::Client::builder()
+            .timeout(NOTIFICATION_TIMEOUT)
+            .build()
+            .context(NotificationClient)?;
+
+        Ok(Self {
+            release_name: opts.release_name(),
+            namespace: opts.namespace(),
+            slack_webhook_url: opts.notify_slack(),
+            pagerduty_routing_key: opts.notify_pagerduty(),
+            notify_on_success: opts.notify_on_success(),
+            client,
+        })
+    }
+
+    /// Notifies Slack and PagerDuty of a failed upgrade. The message includes the phase and the
+    /// storage Node which the upgrade failed in, if known.
+    pub(crate) async fn notify_failure(&self, error: &Error) {
+        let (phase, node) = error.phase_context().unwrap_or(("unknown", None));
+        let summary = format!(
+            "{PRODUCT} upgrade of release '{}' in namespace '{}' failed",
+            self.release_name, self.namespace
+        );
+
+        let slack = async {
+            let Some(url) = self.slack_webhook_url.as_deref() else {
+                return;
+            };
+            let mut text = format!("{summary}\n*Phase:* {phase}");
+            if let Some(node) = node {
+                text.push_str(format!("\n*Node:* {node}").as_str());
+            }
+            text.push_str(format!("\n*Error:* {error}").as_str());
+            self.post("Slack", url, json!({ "text": text })).await;
+        };
+
+        let pagerduty = async {
+            let Some(routing_key) = self.pagerduty_routing_key.as_deref() else {
+                return;
+            };
+            let body = json!({
+                "routing_key": routing_key,
+                "event_action": "trigger",
+                "payload": {
+                    "summary": summary,
+                    "source": format!("{}/{}", self.namespace, self.release_name),
+                    "severity": "critical",
+                    "component": "upgrade-job",
+                    "custom_details": {
+                        "release": self.release_name,
+                        "namespace": self.namespace,
+                        "phase": phase,
+                        "node": node,
+                        "error": error.to_string(),
+                    },
+                },
+            });
+            self.post("PagerDuty", PAGERDUTY_EVENTS_URL, body).await;
+        };
+
+        tokio::join!(slack, pagerduty);
+    }
+
+    /// Notifies Slack of a successful upgrade, if successful upgrades are to be notified.
+    /// PagerDuty is only ever notified of failures.
+    pub(crate) async fn notify_success(&self) {
+        if !self.notify_on_success {
+            return;
+        }
+        let Some(url) = self.slack_webhook_url.as_deref() else {
+            return;
+        };
+
+        let text = format!(
+            "{PRODUCT} upgrade of release '{}' in namespace '{}' succeeded",
+            self.release_name, self.namespace
+        );
+        self.post("Slack", url, json!({ "text": text })).await;
+    }
+
+    /// POSTs the JSON body to the notification backend, logging any failure to deliver it.
+    async fn post(&self, backend: &str, url: &str, body: Value) {
+        match self.client.post(url).json(&body).send().await {
+            Ok(response) if response.status().is_success() => {
+                info!(%backend, "Sent upgrade notification");
+            }
+            Ok(response) => warn!(
+                %backend,
+                status = %response.status(),
+                "Notification backend rejected upgrade notification"
+            ),
+            Err(error) => warn!(%backend, %error, "Failed to send upgrade notification"),
+        }
+    }
+}
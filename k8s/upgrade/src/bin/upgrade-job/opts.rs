@@ -1,31 +1,81 @@
-use crate::common::constants::PRODUCT;
-use clap::Parser;
-use std::path::PathBuf;
+use crate::{
+    common::{
+        constants::{PRODUCT, UPGRADE_HELM_REPO_NAME},
+        error::{RestEndpointAbsent, Result, SerializeEffectiveConfig},
+    },
+    helm::{redact::ValueRedactor, repo::HelmRepoChart},
+};
+use clap::{Parser, Subcommand, ValueEnum};
+use serde::{Serialize, Serializer};
+use snafu::ResultExt;
+use std::{path::PathBuf, time::Duration};
+use url::Url;
 use utils::{package_description, version_info_str};
 
 /// Validate input whose validation depends on other inputs.
 pub(crate) mod validators;
 
 /// These are the supported cli configuration options for upgrade.
-#[derive(Parser)]
+#[derive(Parser, Serialize)]
+#[serde(rename_all = "camelCase")]
 #[command(name = package_description!(), version = version_info_str!())]
 #[command(about = format!("Upgrades {}", PRODUCT), long_about = None)]
 pub(crate) struct CliArgs {
-    /// This is the URL for the storage REST API server.
+    /// This is the URL for the storage REST API server. This is only required if the io-engine
+    /// Pods are to be restarted, or if a check which uses the REST API is enabled.
     #[arg(short = 'e', long)]
-    rest_endpoint: String,
+    #[serde(serialize_with = "serialize_masked_optional_url")]
+    rest_endpoint: Option<String>,
 
     /// This is the Kubernetes Namespace for the Helm release.
     #[arg(short, long)]
     namespace: String,
 
+    /// This is the Kubernetes Namespace of the control-plane components, if they are not in the
+    /// Namespace of the Helm release. The control-plane readiness checks look up Pods in this
+    /// Namespace.
+    #[arg(long)]
+    control_plane_namespace: Option<String>,
+
     /// This is the release name of the installed Helm chart.
     #[arg(long)]
     release_name: String,
 
-    /// This is the Helm chart directory filepath for the core Helm chart variant.
+    /// This is the Helm chart directory filepath for the core Helm chart variant. This is required
+    /// unless the chart is pulled from a Helm repository, an '--image-tag' is set, or the
+    /// 'plan-data-plane' command is run with an '--image-tag'.
     #[arg(long, env = "CORE_CHART_DIR", value_name = "DIR_PATH")]
-    core_chart_dir: PathBuf,
+    core_chart_dir: Option<PathBuf>,
+
+    /// This is the container image tag to upgrade to. If set, the image tag is set directly with
+    /// the helm upgrade's '--set' values, instead of the chart's values.yaml being read. Without a
+    /// chart, the helm release must already be on this version, and only the data-plane is
+    /// restarted.
+    #[arg(long)]
+    image_tag: Option<String>,
+
+    /// This is the URL of a classic Helm repository to pull the core Helm chart from. If set, the
+    /// chart is pulled from this repository instead of being read from the '--core-chart-dir'.
+    /// The chart's values.yaml, e.g. for the upgrade target's image tag, is read from the pulled
+    /// chart too.
+    #[arg(long, visible_alias = "chart-repo", requires = "chart_version")]
+    #[serde(serialize_with = "serialize_masked_optional_url")]
+    repo_url: Option<String>,
+
+    /// This is the name which the Helm repository is added with. This is 'mayastor-upgrade' if
+    /// not set.
+    #[arg(long, requires = "repo_url")]
+    repo_name: Option<String>,
+
+    /// This is the version of the core Helm chart to pull from the Helm repository.
+    #[arg(long, requires = "repo_url")]
+    chart_version: Option<String>,
+
+    /// This is the core Helm chart pulled from the Helm repository, if any. This is not a CLI
+    /// option.
+    #[arg(skip)]
+    #[serde(skip)]
+    repo_chart: Option<HelmRepoChart>,
 
     /// If not set, this skips the Kubernetes Pod restarts for the io-engine DaemonSet.
     #[arg(long, default_value_t = false)]
@@ -35,6 +85,438 @@ pub(crate) struct CliArgs {
     #[arg(long, default_value_t = false)]
     skip_upgrade_path_validation: bool,
 
+    /// If set then upgrades to a helm chart which is more than a single minor version ahead of the
+    /// installed one are allowed. Such upgrades are unsupported.
+    #[arg(long, default_value_t = false)]
+    allow_unsupported_skew: bool,
+
+    /// If set then the upgrade drain and cordon labels, which a previous upgrade attempt may have
+    /// left on storage Nodes, are removed before the upgrade starts. Such Nodes are only reported
+    /// otherwise.
+    #[arg(long, default_value_t = false)]
+    auto_uncordon_stale: bool,
+
+    /// If set then the io-engine version reported by each storage Node is verified to match the
+    /// upgrade target version, after its data-plane Pod has been restarted.
+    #[arg(long, default_value_t = false)]
+    verify_io_engine_version: bool,
+
+    /// If set then each storage Node is verified to have enough allocatable hugepages for the
+    /// upgraded io-engine Pod's request, before the Node is drained.
+    #[arg(long, default_value_t = false)]
+    verify_hugepages: bool,
+
+    /// If set then the pools on the rest of the storage Nodes are not verified to have the free
+    /// space for the replicas on each Node, before the Node is drained.
+    #[arg(long, default_value_t = false)]
+    skip_capacity_check: bool,
+
+    /// If set then the upgraded io-engine container image is verified to exist in its registry,
+    /// before any Node is drained.
+    #[arg(long, default_value_t = false)]
+    verify_image_exists: bool,
+
+    /// If set then each of the CRDs in the helm chart is dry-run server-side applied, and the
+    /// kind of change it would make is reported. Breaking changes fail the upgrade with
+    /// '--strict'.
+    #[arg(long, default_value_t = false)]
+    dry_run_crds: bool,
+
+    /// If set then the upgrade is only previewed. The upgrade is validated and the helm upgrade is
+    /// dry-run, and the io-engine Pods which would be drained, restarted and uncordoned are
+    /// printed. No changes are made to the cluster.
+    #[arg(long, default_value_t = false)]
+    dry_run: bool,
+
+    /// If set then the upgrade does not start unless exactly one agent-core Pod is Ready. The
+    /// agent-core holds a lease on the control-plane's persistent store, so none or many Ready
+    /// agent-core Pods point at a leadership problem.
+    #[arg(long, default_value_t = false)]
+    verify_control_plane_leader: bool,
+
+    /// If set then this upgrade-job's own version is compared with the version of the target helm
+    /// chart. An upgrade-job of a different major or minor version may not understand the target
+    /// chart, so a mismatch is logged as a warning, or fails the upgrade if '--strict' is set.
+    #[arg(long, default_value_t = false)]
+    verify_job_version: bool,
+
+    /// If set then post-upgrade verification mismatches fail the upgrade, instead of only logging
+    /// a warning.
+    #[arg(long, default_value_t = false)]
+    strict: bool,
+
+    /// If set then the PersistentVolumeClaims provisioned by the storage CSI driver are verified
+    /// to remain Bound after the upgrade.
+    #[arg(long, default_value_t = false)]
+    verify_pvc_binding: bool,
+
+    /// If set then all of the volumes are verified to be Online with no rebuilds in progress, and
+    /// all of the io-engine Pods are verified to be Ready, after the upgrade. This is set by
+    /// default, and may be unset with '--verify-health=false'.
+    #[arg(long, default_value_t = true, action = clap::ArgAction::Set)]
+    verify_health: bool,
+
+    /// This is the scope of the PersistentVolumeClaims to verify the binding health of.
+    #[arg(long, value_enum, default_value_t = PvcScope::Namespace)]
+    pvc_binding_scope: PvcScope,
+
+    /// This is the strategy used to cordon the storage Nodes during the data-plane upgrade.
+    /// 'per-node' drains and uncordons each Node in turn, so volume targets may move to Nodes
+    /// which have already been upgraded. 'batch' cordons all of the Nodes which are to be
+    /// upgraded before the first Pod restart and uncordons them after the last one, which keeps
+    /// new replicas and targets off of the Nodes still to be restarted. With 'batch', draining a
+    /// Node requires uncordoned Nodes to move its volume targets to, and the Nodes stay
+    /// unschedulable for the whole of the data-plane upgrade.
+    #[arg(long, value_enum, default_value_t = CordonStrategy::PerNode)]
+    cordon_strategy: CordonStrategy,
+
+    /// If set then, with the 'batch' cordon strategy, the scheduling of the restarted io-engine
+    /// Pods of the batch is simulated before the Nodes are cordoned. The batch is rejected if any
+    /// of the Pods would be left Pending, because of its Node's taints or labels.
+    #[arg(long, default_value_t = false)]
+    verify_batch_scheduling: bool,
+
+    /// This is the maximum number of Nodes whose io-engine Pods are upgraded concurrently. Nodes
+    /// which host replicas or targets of the same volume are never drained at the same time.
+    /// The default upgrades one Node at a time.
+    #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u16).range(1..))]
+    max_parallel_nodes: u16,
+
+    /// This is a Kubernetes Node label selector, e.g. 'upgrade-wave=canary'. If set then only the
+    /// io-engine Pods on the Nodes which match it are upgraded. The io-engine Pods on the rest of
+    /// the Nodes are left as is, and may be upgraded with a later run of the job.
+    #[arg(long, value_name = "KEY=VALUE")]
+    node_selector: Option<String>,
+
+    /// If set then the data-plane upgrade pauses after the io-engine Pod on the first Node is
+    /// restarted and verified, and waits for approval before restarting the rest of them.
+    #[arg(long, default_value_t = false)]
+    canary_first: bool,
+
+    /// This decides how the data-plane upgrade is approved to continue after the canary Node.
+    /// 'config-map' waits for the key 'resume' to be set to "true" in the '--canary-configmap'
+    /// ConfigMap, in the release's namespace. A 'resume' key left set to "true" is reset before
+    /// the pause. 'signal' waits for the upgrade-job process to receive SIGUSR1. A SIGUSR1 which
+    /// is received before the pause is ignored.
+    #[arg(long, value_enum, default_value_t = CanaryResume::ConfigMap)]
+    canary_resume: CanaryResume,
+
+    /// This is the name of the ConfigMap which approves the data-plane upgrade to continue after
+    /// the canary Node, with the 'config-map' canary resume trigger.
+    #[arg(long, default_value = "upgrade-canary")]
+    canary_configmap: String,
+
+    /// This is the action taken when the control-plane is not running before an io-engine Pod
+    /// restart. 'abort' fails the upgrade. 'pause' uncordons the Node and waits for the
+    /// control-plane to recover, before resuming the upgrade. 'rollback' uncordons the Node, rolls
+    /// the helm release back to its previous revision, restarts the io-engine Pods which are
+    /// already restarted back to the previous version in the reverse order, and fails the
+    /// upgrade.
+    #[arg(long, value_enum, default_value_t = ControlPlaneUnhealthyAction::Abort)]
+    on_control_plane_unhealthy: ControlPlaneUnhealthyAction,
+
+    /// If set then the helm release is rolled back to the revision it was on before the upgrade,
+    /// if the data-plane upgrade fails after the helm upgrade. The io-engine Pods which are
+    /// already restarted are then restarted back to the previous version, in the reverse order.
+    /// This is set by default, so that a failed upgrade does not leave the control-plane and the
+    /// data-plane on different versions, and may be unset with '--rollback-on-failure=false'.
+    #[arg(long, default_value_t = true, action = clap::ArgAction::Set)]
+    rollback_on_failure: bool,
+
+    /// This is the minimum fraction of the Pods of each of the control-plane components which have
+    /// to be Ready for the control-plane to be considered running. The default requires all of
+    /// the Pods to be Ready.
+    #[arg(long, default_value_t = 1.0, value_parser = parse_fraction)]
+    min_ready_fraction: f64,
+
+    /// These are the control-plane components which are health-checked before each of the
+    /// io-engine Pod restarts. Clusters which use an external etcd may leave out 'etcd'.
+    #[arg(
+        long,
+        value_enum,
+        value_delimiter = ',',
+        default_values_t = [
+            ControlPlaneComponent::AgentCore,
+            ControlPlaneComponent::Rest,
+            ControlPlaneComponent::Etcd
+        ]
+    )]
+    control_plane_components: Vec<ControlPlaneComponent>,
+
+    /// This is the number of times the drain, restart and verification of the io-engine Pod on a
+    /// Node is retried, if it fails, including when its drain times out. The Node is uncordoned
+    /// between attempts.
+    #[arg(long, default_value_t = 0)]
+    node_retries: u32,
+
+    /// If set then the next io-engine Pod restart is held back while more than this many volumes
+    /// are Degraded, until enough of them recover.
+    #[arg(long)]
+    max_degraded_volumes: Option<u32>,
+
+    /// This is the number of seconds to hold back an io-engine Pod restart for, waiting on the
+    /// Degraded volumes to go below '--max-degraded-volumes', before failing the upgrade.
+    #[arg(long, default_value_t = 3600, value_parser = clap::value_parser!(u64).range(1..))]
+    degraded_volumes_timeout_seconds: u64,
+
+    /// This is the number of seconds for which no volume may be rebuilding, before an io-engine
+    /// Pod is restarted. A rebuild which starts during this quiet period restarts it. The
+    /// default of 0 proceeds as soon as no volume is rebuilding.
+    #[arg(long, default_value_t = 0)]
+    rebuild_quiet_period_seconds: u64,
+
+    /// This is the time to wait for before checking for volume rebuilds, before each io-engine
+    /// Pod restart, so that any rebuilds have time to start.
+    #[arg(long, default_value = "60s", value_parser = humantime::parse_duration)]
+    #[serde(serialize_with = "serialize_duration")]
+    rebuild_grace_period: Duration,
+
+    /// This is the time between the checks for volume rebuilds, for the drain of a Node to
+    /// complete, and for a restarted io-engine Pod to be Ready, e.g. '10s'. If not set, then the
+    /// volume rebuilds are checked every 10 seconds, the drains every 2 seconds at first, and the
+    /// restarted Pods every 5 seconds. The time between the checks of a drain doubles after each
+    /// check, up to '--drain-poll-max'.
+    #[arg(long, value_parser = humantime::parse_duration)]
+    #[serde(serialize_with = "serialize_optional_duration")]
+    poll_interval: Option<Duration>,
+
+    /// This is the maximum time between the checks for the drain of a Node to complete, e.g.
+    /// '1m'. The time between the checks goes back to '--poll-interval' when the drain state of
+    /// the Node changes.
+    #[arg(long, default_value = "30s", value_parser = humantime::parse_duration)]
+    #[serde(serialize_with = "serialize_duration")]
+    drain_poll_max: Duration,
+
+    /// If set then a volume rebuild which makes no progress for this many seconds is logged as a
+    /// warning, naming the stalled volume, or fails the upgrade if '--strict' is set.
+    #[arg(long, value_parser = clap::value_parser!(u64).range(1..))]
+    rebuild_stall_timeout_seconds: Option<u64>,
+
+    /// This is the number of seconds to wait for after the helm upgrade, before the data-plane
+    /// upgrade starts. After the wait, the control-plane is verified to be running and no volume
+    /// may be rebuilding, before the first io-engine Pod restart. The default of 0 skips this.
+    #[arg(long, default_value_t = 0)]
+    post_helm_settle_seconds: u64,
+
+    /// If set then a storage Node is only drained when each of the volumes with replicas on it
+    /// would still have at least this many healthy replicas on the other Nodes. The drain is held
+    /// back until then, for up to '--min-healthy-replicas-timeout-seconds'.
+    #[arg(long)]
+    min_healthy_replicas: Option<u32>,
+
+    /// This is the number of seconds to hold back the drain of a storage Node for, waiting on its
+    /// volumes to have '--min-healthy-replicas' healthy replicas elsewhere, before failing the
+    /// upgrade.
+    #[arg(long, default_value_t = 3600, value_parser = clap::value_parser!(u64).range(1..))]
+    min_healthy_replicas_timeout_seconds: u64,
+
+    /// If set then the drain of a storage Node may take up to this many seconds. A Node whose
+    /// volumes don't move off of it in time is uncordoned and skipped, or fails the upgrade if
+    /// '--strict' is set.
+    #[arg(long, value_parser = clap::value_parser!(u64).range(1..))]
+    volume_move_timeout_seconds: Option<u64>,
+
+    /// If set then the read-only preflight diagnostics, i.e. the CRD validation, the
+    /// PersistentVolumeClaim binding snapshot, the control-plane leader check and the search for
+    /// stale cordons, run concurrently with the helm upgrade dry-run. Both are joined before the
+    /// upgrade makes any changes, and a failure in either fails the upgrade.
+    #[arg(long, default_value_t = false)]
+    concurrent_preflight: bool,
+
+    /// If set then the time taken by each step of the io-engine Pod restart on each Node is also
+    /// recorded as a metric, labelled with the Node and the step. The timings are always logged.
+    #[arg(long, default_value_t = false)]
+    node_timing_metrics: bool,
+
+    /// If set then a progress bar with the upgrade phase and the data-plane progress across the
+    /// Nodes is drawn on stderr, when it is a terminal. The log lines are printed either way.
+    #[arg(long, default_value_t = false)]
+    progress: bool,
+
+    /// This is the format of the log lines. 'json' prints each log line as a JSON object, with
+    /// each of the logged fields as a key of the object.
+    #[arg(long, value_enum, default_value_t = LogFormat::Pretty)]
+    log_format: LogFormat,
+
+    /// This is the port to serve the upgrade metrics on, in the Prometheus text format, at any
+    /// HTTP path. The metrics are served until the upgrade ends, and are not served by default.
+    #[arg(long)]
+    metrics_port: Option<u16>,
+
+    /// This is the number of seconds to wait for the control-plane to be running, before each of
+    /// the io-engine Pod restarts.
+    #[arg(long, default_value_t = 600, value_parser = clap::value_parser!(u64).range(1..))]
+    control_plane_ready_timeout_seconds: u64,
+
+    /// This is the number of seconds to wait for each restarted io-engine Pod to be Ready.
+    #[arg(long, default_value_t = 900, value_parser = clap::value_parser!(u64).range(1..))]
+    data_plane_ready_timeout_seconds: u64,
+
+    /// This is the time the data-plane upgrade may take, e.g. '2h'. The upgrade is aborted when it
+    /// runs out, and the Nodes which were already upgraded are reported. The data-plane upgrade
+    /// is not timed out by default.
+    #[arg(long, value_parser = humantime::parse_duration)]
+    #[serde(serialize_with = "serialize_optional_duration")]
+    timeout: Option<Duration>,
+
+    /// If set then the io-engine Pods are deleted only while their PodDisruptionBudgets allow
+    /// disruptions, waiting until they do before each Pod delete.
+    #[arg(long, default_value_t = false)]
+    respect_pdb: bool,
+
+    /// This is the time to wait for the PodDisruptionBudgets to allow disruptions, before an
+    /// io-engine Pod delete, when the PodDisruptionBudgets are respected.
+    #[arg(long, default_value = "10m", value_parser = humantime::parse_duration)]
+    #[serde(serialize_with = "serialize_duration")]
+    pdb_wait_timeout: Duration,
+
+    /// This is the time to wait for after an io-engine Pod is deleted, before looking for its
+    /// replacement Pod. The replacement is then waited on until it is a Pod other than the deleted
+    /// one.
+    #[arg(long, default_value = "5s", value_parser = humantime::parse_duration)]
+    #[serde(serialize_with = "serialize_duration")]
+    pod_settle_delay: Duration,
+
+    /// This is the time for which a replacement io-engine Pod may stay unschedulable because the
+    /// Node does not have enough of the resources it requests, before the upgrade fails.
+    #[arg(long, default_value = "30s", value_parser = humantime::parse_duration)]
+    #[serde(serialize_with = "serialize_duration")]
+    unschedulable_grace_period: Duration,
+
+    /// This is the path to a YAML file which maps volume UUIDs to 'wait' or 'ignore'. The rebuilds
+    /// of the volumes set to 'ignore' are not waited on before an io-engine Pod restart. The
+    /// rebuilds of all other volumes are waited on.
+    #[arg(long, value_name = "FILE_PATH")]
+    rebuild_wait_policy_file: Option<PathBuf>,
+
+    /// This is the path to a file which the progress of the upgrade is recorded in, i.e. whether
+    /// the helm upgrade has completed and which Nodes have been upgraded. If the upgrade is
+    /// interrupted, e.g. by the eviction of this Pod, then the next run continues from the
+    /// recorded progress. A corrupt file, or one for another upgrade target version, is ignored.
+    #[arg(long, value_name = "FILE_PATH")]
+    state_file: Option<PathBuf>,
+
+    /// This is the path to a file which a JSON summary of the upgrade is written to, once the
+    /// upgrade succeeds. The summary has the chart versions upgraded from and to, the io-engine
+    /// image tag upgraded to, the Nodes whose io-engine Pods were upgraded, and the duration of
+    /// the upgrade.
+    #[arg(long, value_name = "FILE_PATH")]
+    output_summary: Option<PathBuf>,
+
+    /// This is an 's3://bucket/key' URL. If set then the JSON summary of the upgrade is uploaded
+    /// to it, once the upgrade succeeds. The upload is best-effort, a failed upload is logged and
+    /// does not fail the upgrade.
+    #[arg(long, value_name = "S3_URL")]
+    report_s3_url: Option<String>,
+
+    /// This is the endpoint of the S3-compatible store which the upgrade summary is uploaded to.
+    /// The objects are addressed path-style, i.e. as '<endpoint>/<bucket>/<key>'.
+    #[arg(long, default_value = "https://s3.amazonaws.com")]
+    report_s3_endpoint: String,
+
+    /// This is the region of the S3-compatible store, which the upload requests are signed for.
+    #[arg(long, env = "AWS_REGION", default_value = "us-east-1")]
+    report_s3_region: String,
+
+    /// This is the access key id of the S3-compatible store.
+    #[arg(long, env = "AWS_ACCESS_KEY_ID", hide_env_values = true)]
+    #[serde(serialize_with = "serialize_masked_secret")]
+    report_s3_access_key_id: Option<String>,
+
+    /// This is the secret access key of the S3-compatible store.
+    #[arg(long, env = "AWS_SECRET_ACCESS_KEY", hide_env_values = true)]
+    #[serde(serialize_with = "serialize_masked_secret")]
+    report_s3_secret_access_key: Option<String>,
+
+    /// This is the session token which goes with temporary credentials for the S3-compatible
+    /// store, if any.
+    #[arg(long, env = "AWS_SESSION_TOKEN", hide_env_values = true)]
+    #[serde(serialize_with = "serialize_masked_secret")]
+    report_s3_session_token: Option<String>,
+
+    /// This is the number of pages of volumes which are fetched concurrently from the storage REST
+    /// API, when checking for volume rebuilds. Each page holds up to 200 volumes, and the pages
+    /// of a batch are held in memory together, so memory use grows with this value.
+    #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u16).range(1..))]
+    volume_list_parallelism: u16,
+
+    /// This is the maximum number of concurrent Kubernetes API requests made by this job.
+    #[arg(long, default_value_t = 10, value_parser = clap::value_parser!(u16).range(1..))]
+    kube_api_concurrency: u16,
+
+    /// This is the number of times a read-only storage REST API request is retried, if it fails
+    /// with a transport error or with the API being unavailable for now, e.g. while the
+    /// control-plane restarts. The retries back off exponentially, from 1 second. Other errors
+    /// are not retried.
+    #[arg(long, default_value_t = 5)]
+    max_retries: u32,
+
+    /// This is the helm binary which all of the helm commands are run with. A bare name is looked
+    /// up in the $PATH.
+    #[arg(long, default_value = "helm")]
+    helm_binary: String,
+
+    /// This is a Slack incoming webhook URL. If set then a message is posted to it if the upgrade
+    /// fails, and also if it succeeds when '--notify-on-success' is set.
+    #[arg(long)]
+    #[serde(serialize_with = "serialize_masked_secret")]
+    notify_slack: Option<String>,
+
+    /// This is a PagerDuty Events API v2 routing key. If set then an incident is triggered if the
+    /// upgrade fails.
+    #[arg(long)]
+    #[serde(serialize_with = "serialize_masked_secret")]
+    notify_pagerduty: Option<String>,
+
+    /// If set then the notification backends which support it are notified of a successful
+    /// upgrade too.
+    #[arg(long, default_value_t = false)]
+    notify_on_success: bool,
+
+    /// If set then the fully-resolved options are printed as YAML, and the job exits without
+    /// doing anything else. Passwords in URLs are masked.
+    #[arg(long, default_value_t = false)]
+    #[serde(skip)]
+    dump_effective_config: bool,
+
+    /// This decides which helm values the Core chart is upgraded with. 'reuse' merges the values
+    /// of the installed release over the new chart's defaults, so custom overrides are kept
+    /// but changed chart defaults may be masked by the old values. 'reset' upgrades with the
+    /// new chart's defaults and only the '--values' set for this upgrade, which drops any
+    /// custom overrides from the installed release. 'reset-then-reuse' has helm apply the new
+    /// chart's defaults and then the installed release's overrides, and requires helm v3.14 or
+    /// later.
+    #[arg(long, value_enum, default_value_t = ValuesMode::Reuse)]
+    values_mode: ValuesMode,
+
+    /// If set then only helm chart versions of the form 'major.minor.patch' are accepted. Chart
+    /// versions with a semver pre-release (e.g. 2.3.0-rc1) or build metadata (e.g. 2.3.0+build.5)
+    /// are accepted otherwise.
+    #[arg(long, default_value_t = false)]
+    strict_semver: bool,
+
+    /// These are the patterns of the helm value keys whose values are masked wherever the helm
+    /// command arguments are logged or used in error messages, e.g. the values set using
+    /// '--values'. The patterns are case-insensitive regular expressions, which may match any part
+    /// of the key.
+    #[arg(
+        long,
+        value_delimiter = ',',
+        default_value = "password,passwd,secret,token,credential,apikey,api_key,accesskey"
+    )]
+    redact_value_keys: Vec<String>,
+
+    /// These are the storage Node drain errors which are treated as the drain having been issued,
+    /// e.g. when a previous upgrade attempt left the Node draining. A drain request which fails
+    /// with a response whose error contains any of these, case-insensitively, is not retried and
+    /// does not fail the upgrade.
+    #[arg(
+        long,
+        value_delimiter = ',',
+        default_value = "already draining,already drained,already in desired state"
+    )]
+    benign_drain_errors: Vec<String>,
+
     /// The name of the Kubernetes Job Pod. The Job object will be used to post upgrade event.
     #[arg(env = "POD_NAME")]
     pod_name: String,
@@ -43,27 +525,73 @@ pub(crate) struct CliArgs {
     /// (can specify multiple or separate values with commas: key1=val1,key2=val2).
     #[arg(short, long)]
     values: String,
+
+    /// These are helm values to change during the upgrade, e.g. a replica count or a resource
+    /// limit, in the 'key=value' form. This may be repeated. These are passed to the helm upgrade
+    /// as '--set' options after the carried over values and the '--values', e.g. after
+    /// '--reuse-values', so these take precedence over them.
+    #[arg(long = "set", value_name = "KEY=VALUE", value_parser = parse_set_value)]
+    set_values: Vec<String>,
+
+    /// This is the command to run instead of the upgrade, if any.
+    #[command(subcommand)]
+    command: Option<Command>,
 }
 
 impl CliArgs {
-    /// This returns the URL to the storage REST API.
-    pub(crate) fn rest_endpoint(&self) -> String {
+    /// This returns the URL to the storage REST API, if set.
+    pub(crate) fn rest_endpoint_opt(&self) -> Option<String> {
         self.rest_endpoint.clone()
     }
 
+    /// This returns the URL to the storage REST API. This fails if it is not set, so it is only
+    /// called when an operation which needs the REST API is reached.
+    pub(crate) fn rest_endpoint(&self) -> Result<String> {
+        self.rest_endpoint_opt().ok_or(RestEndpointAbsent.build())
+    }
+
     /// This returns the Kubernetes Namespace for the Helm chart release.
     pub(crate) fn namespace(&self) -> String {
         self.namespace.clone()
     }
 
+    /// This returns the Kubernetes Namespace of the control-plane components. This defaults to the
+    /// Namespace of the Helm chart release.
+    pub(crate) fn control_plane_namespace(&self) -> String {
+        self.control_plane_namespace
+            .clone()
+            .unwrap_or_else(|| self.namespace())
+    }
+
     /// This returns the Helm release name for the installed Helm chart.
     pub(crate) fn release_name(&self) -> String {
         self.release_name.clone()
     }
 
     /// This returns the Helm chart directory filepath for a crate::helm::upgrade::HelmChart::Core.
-    pub(crate) fn core_chart_dir(&self) -> PathBuf {
-        self.core_chart_dir.clone()
+    /// This is the directory of the pulled chart, if the chart was pulled from a Helm repository.
+    pub(crate) fn core_chart_dir(&self) -> Option<PathBuf> {
+        match self.repo_chart.as_ref() {
+            Some(repo_chart) => Some(repo_chart.chart_dir()),
+            None => self.core_chart_dir.clone(),
+        }
+    }
+
+    /// This returns the container image tag to upgrade to, if set.
+    pub(crate) fn image_tag(&self) -> Option<String> {
+        self.image_tag.clone()
+    }
+
+    /// This pulls the core Helm chart from the Helm repository, if one is set. The Helm repository
+    /// is removed and the pulled chart is deleted when the CliArgs are dropped.
+    pub(crate) fn pull_core_chart(&mut self) -> Result<()> {
+        if let (Some(repo_url), Some(chart_version)) =
+            (self.repo_url.as_deref(), self.chart_version.as_deref())
+        {
+            let repo_name = self.repo_name.as_deref().unwrap_or(UPGRADE_HELM_REPO_NAME);
+            self.repo_chart = Some(HelmRepoChart::pull(repo_url, repo_name, chart_version)?);
+        }
+        Ok(())
     }
 
     /// This is a predicate to decide if <release-name>-io-engine Kubernetes DaemonSet Pods should
@@ -77,6 +605,399 @@ impl CliArgs {
         self.skip_upgrade_path_validation
     }
 
+    /// This is a predicate to decide if upgrades across more than a single minor version are
+    /// allowed.
+    pub(crate) fn allow_unsupported_skew(&self) -> bool {
+        self.allow_unsupported_skew
+    }
+
+    /// This is a predicate to decide if left over upgrade drain and cordon labels should be
+    /// removed before the upgrade starts.
+    pub(crate) fn auto_uncordon_stale(&self) -> bool {
+        self.auto_uncordon_stale
+    }
+
+    /// This is a predicate to decide if the io-engine version reported by the storage REST API
+    /// should be verified after the data-plane Pod restart.
+    pub(crate) fn verify_io_engine_version(&self) -> bool {
+        self.verify_io_engine_version
+    }
+
+    /// This is a predicate to decide if the Nodes' allocatable hugepages should be verified before
+    /// the io-engine Pods are restarted.
+    pub(crate) fn verify_hugepages(&self) -> bool {
+        self.verify_hugepages
+    }
+
+    /// This is a predicate to decide if the spare pool capacity should not be verified before the
+    /// storage Nodes are drained.
+    pub(crate) fn skip_capacity_check(&self) -> bool {
+        self.skip_capacity_check
+    }
+
+    /// This is a predicate to decide if the io-engine container image should be looked up in its
+    /// registry before the io-engine Pods are restarted.
+    pub(crate) fn verify_image_exists(&self) -> bool {
+        self.verify_image_exists
+    }
+
+    /// This is a predicate to decide if the upgrade should only be previewed.
+    pub(crate) fn dry_run(&self) -> bool {
+        self.dry_run
+    }
+
+    /// This is a predicate to decide if the helm chart's CRDs should be dry-run applied.
+    pub(crate) fn dry_run_crds(&self) -> bool {
+        self.dry_run_crds
+    }
+
+    /// This is a predicate to decide if the upgrade-job's own version should be verified against
+    /// the target helm chart version.
+    pub(crate) fn verify_job_version(&self) -> bool {
+        self.verify_job_version
+    }
+
+    /// This is a predicate to decide if a single active agent-core should be verified before the
+    /// upgrade starts.
+    pub(crate) fn verify_control_plane_leader(&self) -> bool {
+        self.verify_control_plane_leader
+    }
+
+    /// This decides if post-upgrade verification mismatches should fail the upgrade.
+    pub(crate) fn strict(&self) -> bool {
+        self.strict
+    }
+
+    /// This is a predicate to decide if the binding health of PersistentVolumeClaims should be
+    /// verified after the upgrade.
+    pub(crate) fn verify_pvc_binding(&self) -> bool {
+        self.verify_pvc_binding
+    }
+
+    /// This is a predicate to decide if the health of the volumes and the io-engine Pods should
+    /// be verified after the upgrade.
+    pub(crate) fn verify_health(&self) -> bool {
+        self.verify_health
+    }
+
+    /// This returns the scope of the PersistentVolumeClaims to verify.
+    pub(crate) fn pvc_binding_scope(&self) -> PvcScope {
+        self.pvc_binding_scope
+    }
+
+    /// This returns the strategy used to cordon the storage Nodes during the data-plane upgrade.
+    pub(crate) fn cordon_strategy(&self) -> CordonStrategy {
+        self.cordon_strategy
+    }
+
+    /// This is a predicate to decide if the scheduling of a batch of restarted io-engine Pods
+    /// should be simulated before the batch is cordoned.
+    pub(crate) fn verify_batch_scheduling(&self) -> bool {
+        self.verify_batch_scheduling
+    }
+
+    /// This returns the maximum number of Nodes to upgrade concurrently.
+    pub(crate) fn max_parallel_nodes(&self) -> usize {
+        self.max_parallel_nodes as usize
+    }
+
+    /// This returns the Kubernetes Node label selector for the Nodes to upgrade, if set.
+    pub(crate) fn node_selector(&self) -> Option<String> {
+        self.node_selector.clone()
+    }
+
+    /// This returns the control-plane components which are health-checked.
+    pub(crate) fn control_plane_components(&self) -> Vec<ControlPlaneComponent> {
+        self.control_plane_components.clone()
+    }
+
+    /// This is a predicate to decide if the data-plane upgrade should pause after the first Node.
+    pub(crate) fn canary_first(&self) -> bool {
+        self.canary_first
+    }
+
+    /// This returns the trigger which approves the data-plane upgrade to continue after the
+    /// canary Node.
+    pub(crate) fn canary_resume(&self) -> CanaryResume {
+        self.canary_resume
+    }
+
+    /// This returns the name of the ConfigMap which approves the data-plane upgrade to continue
+    /// after the canary Node.
+    pub(crate) fn canary_configmap(&self) -> String {
+        self.canary_configmap.clone()
+    }
+
+    /// This returns the action taken when the control-plane is not running before an io-engine
+    /// Pod restart.
+    pub(crate) fn on_control_plane_unhealthy(&self) -> ControlPlaneUnhealthyAction {
+        self.on_control_plane_unhealthy
+    }
+
+    /// This is a predicate to decide if the helm release should be rolled back when the
+    /// data-plane upgrade fails.
+    pub(crate) fn rollback_on_failure(&self) -> bool {
+        self.rollback_on_failure
+    }
+
+    /// This returns the minimum fraction of Ready Pods for each control-plane component.
+    pub(crate) fn min_ready_fraction(&self) -> f64 {
+        self.min_ready_fraction
+    }
+
+    /// This returns the number of times the upgrade of the io-engine Pod on a Node is retried.
+    pub(crate) fn node_retries(&self) -> u32 {
+        self.node_retries
+    }
+
+    /// This returns the maximum number of Degraded volumes to restart an io-engine Pod with, if
+    /// any.
+    pub(crate) fn max_degraded_volumes(&self) -> Option<usize> {
+        self.max_degraded_volumes.map(|max| max as usize)
+    }
+
+    /// This returns the time to wait for the Degraded volumes to go below the maximum.
+    pub(crate) fn degraded_volumes_timeout(&self) -> Duration {
+        Duration::from_secs(self.degraded_volumes_timeout_seconds)
+    }
+
+    /// This returns the time for which no volume may be rebuilding, before an io-engine Pod
+    /// restart.
+    pub(crate) fn rebuild_quiet_period(&self) -> Duration {
+        Duration::from_secs(self.rebuild_quiet_period_seconds)
+    }
+
+    /// This returns the time to wait for before checking for volume rebuilds.
+    pub(crate) fn rebuild_grace_period(&self) -> Duration {
+        self.rebuild_grace_period
+    }
+
+    /// This returns the time between the checks of the data-plane upgrade's waits, if set.
+    pub(crate) fn poll_interval(&self) -> Option<Duration> {
+        self.poll_interval
+    }
+
+    /// This returns the maximum time between the checks for the drain of a Node to complete.
+    pub(crate) fn drain_poll_max(&self) -> Duration {
+        self.drain_poll_max
+    }
+
+    /// This returns the time a volume rebuild may go without progress, before it is stalled.
+    pub(crate) fn rebuild_stall_timeout(&self) -> Option<Duration> {
+        self.rebuild_stall_timeout_seconds.map(Duration::from_secs)
+    }
+
+    /// This returns the time to wait for after the helm upgrade, before the data-plane upgrade.
+    pub(crate) fn post_helm_settle(&self) -> Duration {
+        Duration::from_secs(self.post_helm_settle_seconds)
+    }
+
+    /// This returns the minimum number of healthy replicas to keep for each volume while a Node is
+    /// drained, if any.
+    pub(crate) fn min_healthy_replicas(&self) -> Option<usize> {
+        self.min_healthy_replicas.map(|min| min as usize)
+    }
+
+    /// This returns the time to wait for the volumes to have the minimum healthy replicas.
+    pub(crate) fn min_healthy_replicas_timeout(&self) -> Duration {
+        Duration::from_secs(self.min_healthy_replicas_timeout_seconds)
+    }
+
+    /// This returns the time which the drain of a storage Node may take, if bounded.
+    pub(crate) fn volume_move_timeout(&self) -> Option<Duration> {
+        self.volume_move_timeout_seconds.map(Duration::from_secs)
+    }
+
+    /// This is a predicate to decide if the preflight diagnostics should run concurrently with
+    /// the helm upgrade dry-run.
+    pub(crate) fn concurrent_preflight(&self) -> bool {
+        self.concurrent_preflight
+    }
+
+    /// This is a predicate to decide if the per-Node step timings should be recorded as metrics.
+    pub(crate) fn node_timing_metrics(&self) -> bool {
+        self.node_timing_metrics
+    }
+
+    /// This is a predicate to decide if the progress bar should be drawn on a terminal.
+    pub(crate) fn progress(&self) -> bool {
+        self.progress
+    }
+
+    /// This returns the format of the log lines.
+    pub(crate) fn log_format(&self) -> LogFormat {
+        self.log_format
+    }
+
+    /// This returns the port to serve the upgrade metrics on, if any.
+    pub(crate) fn metrics_port(&self) -> Option<u16> {
+        self.metrics_port
+    }
+
+    /// This returns the time to wait for the control-plane to be running.
+    pub(crate) fn control_plane_ready_timeout(&self) -> Duration {
+        Duration::from_secs(self.control_plane_ready_timeout_seconds)
+    }
+
+    /// This returns the time to wait for a restarted io-engine Pod to be Ready.
+    pub(crate) fn data_plane_ready_timeout(&self) -> Duration {
+        Duration::from_secs(self.data_plane_ready_timeout_seconds)
+    }
+
+    /// This is a predicate to decide if the io-engine PodDisruptionBudgets should be honoured
+    /// before each io-engine Pod delete.
+    pub(crate) fn respect_pdb(&self) -> bool {
+        self.respect_pdb
+    }
+
+    /// This returns the time to wait for the PodDisruptionBudgets to allow disruptions.
+    pub(crate) fn pdb_wait_timeout(&self) -> Duration {
+        self.pdb_wait_timeout
+    }
+
+    /// This returns the time the data-plane upgrade may take, if it is to be timed out.
+    pub(crate) fn timeout(&self) -> Option<Duration> {
+        self.timeout
+    }
+
+    /// This returns the time to wait for after an io-engine Pod delete.
+    pub(crate) fn pod_settle_delay(&self) -> Duration {
+        self.pod_settle_delay
+    }
+
+    /// This returns the time for which a replacement io-engine Pod may stay unschedulable for
+    /// lack of resources.
+    pub(crate) fn unschedulable_grace_period(&self) -> Duration {
+        self.unschedulable_grace_period
+    }
+
+    /// This returns the path to the per-volume rebuild wait policy file, if any.
+    pub(crate) fn rebuild_wait_policy_file(&self) -> Option<PathBuf> {
+        self.rebuild_wait_policy_file.clone()
+    }
+
+    /// This returns the path to the file which the upgrade's progress is recorded in, if any.
+    pub(crate) fn state_file(&self) -> Option<PathBuf> {
+        self.state_file.clone()
+    }
+
+    /// This returns the path to the file which the upgrade's summary is written to, if any.
+    pub(crate) fn output_summary(&self) -> Option<PathBuf> {
+        self.output_summary.clone()
+    }
+
+    /// This returns the 's3://bucket/key' URL which the upgrade's summary is uploaded to, if any.
+    pub(crate) fn report_s3_url(&self) -> Option<String> {
+        self.report_s3_url.clone()
+    }
+
+    /// This returns the endpoint of the S3-compatible store.
+    pub(crate) fn report_s3_endpoint(&self) -> String {
+        self.report_s3_endpoint.clone()
+    }
+
+    /// This returns the region of the S3-compatible store.
+    pub(crate) fn report_s3_region(&self) -> String {
+        self.report_s3_region.clone()
+    }
+
+    /// This returns the access key id of the S3-compatible store, if any.
+    pub(crate) fn report_s3_access_key_id(&self) -> Option<String> {
+        self.report_s3_access_key_id.clone()
+    }
+
+    /// This returns the secret access key of the S3-compatible store, if any.
+    pub(crate) fn report_s3_secret_access_key(&self) -> Option<String> {
+        self.report_s3_secret_access_key.clone()
+    }
+
+    /// This returns the session token of the S3-compatible store, if any.
+    pub(crate) fn report_s3_session_token(&self) -> Option<String> {
+        self.report_s3_session_token.clone()
+    }
+
+    /// This returns the number of pages of volumes to fetch concurrently.
+    pub(crate) fn volume_list_parallelism(&self) -> usize {
+        self.volume_list_parallelism as usize
+    }
+
+    /// This returns the maximum number of concurrent Kubernetes API requests.
+    pub(crate) fn kube_api_concurrency(&self) -> usize {
+        self.kube_api_concurrency as usize
+    }
+
+    /// This returns the number of times a read-only storage REST API request is retried.
+    pub(crate) fn max_retries(&self) -> u32 {
+        self.max_retries
+    }
+
+    /// This returns the helm binary which the helm commands are run with.
+    pub(crate) fn helm_binary(&self) -> String {
+        self.helm_binary.clone()
+    }
+
+    /// This returns the Slack incoming webhook URL, if any.
+    pub(crate) fn notify_slack(&self) -> Option<String> {
+        self.notify_slack.clone()
+    }
+
+    /// This returns the PagerDuty Events API v2 routing key, if any.
+    pub(crate) fn notify_pagerduty(&self) -> Option<String> {
+        self.notify_pagerduty.clone()
+    }
+
+    /// This is a predicate to decide if a successful upgrade should be notified.
+    pub(crate) fn notify_on_success(&self) -> bool {
+        self.notify_on_success
+    }
+
+    /// This is a predicate to decide if the resolved options should be printed, instead of running
+    /// the upgrade.
+    pub(crate) fn dump_effective_config(&self) -> bool {
+        self.dump_effective_config
+    }
+
+    /// This returns the resolved options as YAML, with the passwords in URLs and the sensitive
+    /// helm values masked.
+    pub(crate) fn effective_config_yaml(&self) -> Result<String> {
+        let mut config = serde_yaml::to_value(self).context(SerializeEffectiveConfig)?;
+        if let Some(values) = config.get_mut("values") {
+            let redactor = ValueRedactor::new(self.redact_value_keys.as_slice())?;
+            *values = serde_yaml::Value::String(redactor.redact_set_values(self.values.as_str()));
+        }
+        if let Some(set_values) = config.get_mut("setValues") {
+            let redactor = ValueRedactor::new(self.redact_value_keys.as_slice())?;
+            *set_values = serde_yaml::Value::Sequence(
+                self.set_values
+                    .iter()
+                    .map(|value| serde_yaml::Value::String(redactor.redact_set_values(value)))
+                    .collect(),
+            );
+        }
+        serde_yaml::to_string(&config).context(SerializeEffectiveConfig)
+    }
+
+    /// This returns the mode which decides the helm values used for the upgrade.
+    pub(crate) fn values_mode(&self) -> ValuesMode {
+        self.values_mode
+    }
+
+    /// This is a predicate to decide if only 'major.minor.patch' chart versions are accepted.
+    pub(crate) fn strict_semver(&self) -> bool {
+        self.strict_semver
+    }
+
+    /// This returns the storage Node drain errors which are treated as the drain having been
+    /// issued.
+    pub(crate) fn benign_drain_errors(&self) -> Vec<String> {
+        self.benign_drain_errors.clone()
+    }
+
+    /// This returns the patterns of the helm value keys whose values are masked in the logs.
+    pub(crate) fn redact_value_keys(&self) -> Vec<String> {
+        self.redact_value_keys.clone()
+    }
+
     /// This returns the name of the Kubernetes Pod where this binary will be running.
     pub(crate) fn pod_name(&self) -> String {
         self.pod_name.clone()
@@ -86,4 +1007,205 @@ impl CliArgs {
     pub(crate) fn values(&self) -> String {
         self.values.clone()
     }
+
+    /// This returns the helm values to change during the upgrade, in the 'key=value' form.
+    pub(crate) fn set_values(&self) -> Vec<String> {
+        self.set_values.clone()
+    }
+
+    /// This returns the command to run instead of the upgrade, if any.
+    pub(crate) fn command(&self) -> Option<&Command> {
+        self.command.as_ref()
+    }
+}
+
+/// Masks the password in a URL, if any.
+pub(crate) fn masked_url(url: &str) -> String {
+    match Url::parse(url) {
+        Ok(mut url) if url.password().is_some() => {
+            let _ = url.set_password(Some("*****"));
+            url.to_string()
+        }
+        _ => url.to_string(),
+    }
+}
+
+/// Serializes an optional URL with its password masked.
+fn serialize_masked_optional_url<S>(url: &Option<String>, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    url.as_deref().map(masked_url).serialize(serializer)
+}
+
+/// Serializes an optional secret as a mask, so that only its presence is visible.
+fn serialize_masked_secret<S>(secret: &Option<String>, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    secret.as_ref().map(|_| "*****").serialize(serializer)
+}
+
+/// Serializes a Duration in a human-readable form, e.g. '1m 30s'.
+fn serialize_duration<S>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    serializer.serialize_str(humantime::format_duration(*duration).to_string().as_str())
+}
+
+/// Serializes an optional Duration in a human-readable form, e.g. '1m 30s'.
+fn serialize_optional_duration<S>(
+    duration: &Option<Duration>,
+    serializer: S,
+) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    duration
+        .map(|duration| humantime::format_duration(duration).to_string())
+        .serialize(serializer)
+}
+
+/// Parses a helm value in the 'key=value' form. The key must not be empty or contain whitespace.
+fn parse_set_value(value: &str) -> Result<String, String> {
+    match value.split_once('=') {
+        Some((key, _)) if !key.is_empty() && !key.contains(char::is_whitespace) => {
+            Ok(value.to_string())
+        }
+        _ => Err(format!(
+            "'{value}' is not of the form 'key=value', e.g. 'agents.core.resources.limits.cpu=1'"
+        )),
+    }
+}
+
+/// Parses a fraction in the range (0, 1].
+fn parse_fraction(value: &str) -> Result<f64, String> {
+    let fraction: f64 = value
+        .parse()
+        .map_err(|error| format!("'{value}' is not a number: {error}"))?;
+    if fraction <= 0.0 || fraction > 1.0 {
+        return Err(format!("'{value}' is not in the range (0, 1]"));
+    }
+    Ok(fraction)
+}
+
+/// This is the scope of the PersistentVolumeClaims which are looked up.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize, ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) enum PvcScope {
+    /// Only the PersistentVolumeClaims in the namespace of the helm release.
+    Namespace,
+    /// The PersistentVolumeClaims across all namespaces.
+    Cluster,
+}
+
+/// This is the strategy used to cordon the storage Nodes during the data-plane upgrade.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize, ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) enum CordonStrategy {
+    /// Each Node is drained before its io-engine Pod restarts, and uncordoned right after.
+    PerNode,
+    /// All of the Nodes are cordoned before the first restart, and uncordoned after the last.
+    Batch,
+}
+
+/// This is the trigger which approves the data-plane upgrade to continue after the canary Node.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize, ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) enum CanaryResume {
+    /// The 'resume' key of a ConfigMap is set to "true".
+    ConfigMap,
+    /// The upgrade-job process receives SIGUSR1.
+    Signal,
+}
+
+/// These are the control-plane components which may be health-checked.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize, ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) enum ControlPlaneComponent {
+    /// The agent-core Pods.
+    AgentCore,
+    /// The REST API Pods.
+    Rest,
+    /// The in-cluster etcd Pods.
+    Etcd,
+}
+
+/// This is the action taken when the control-plane is not running during the data-plane upgrade.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize, ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) enum ControlPlaneUnhealthyAction {
+    /// The upgrade fails.
+    Abort,
+    /// The upgrade waits for the control-plane to be running again.
+    Pause,
+    /// The helm release is rolled back, and the upgrade fails.
+    Rollback,
+}
+
+/// These are the commands which may be run instead of the upgrade.
+#[derive(Serialize, Subcommand)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) enum Command {
+    /// Lists the io-engine Pods, their Nodes and current image tags, and whether each of them
+    /// would be restarted by the data-plane upgrade. This does not make any changes to the
+    /// cluster.
+    PlanDataPlane {
+        /// This is the io-engine image tag to plan the upgrade to. If not set, this is read from
+        /// the values of the core Helm chart.
+        #[arg(long)]
+        image_tag: Option<String>,
+    },
+    /// Lists the storage Nodes, their current and target io-engine image tags, their cordon or
+    /// drain state and the number of replicas each of them hosts. This does not make any changes
+    /// to the cluster.
+    ListNodes {
+        /// This is the target io-engine image tag. If not set, this is read from the values of the
+        /// core Helm chart.
+        #[arg(long)]
+        image_tag: Option<String>,
+
+        /// This is the format the storage Nodes are printed in.
+        #[arg(long, value_enum, default_value_t = OutputFormat::Table)]
+        output: OutputFormat,
+    },
+    /// Validates the inputs of the upgrade, and checks that the control-plane Pods are Ready and
+    /// that no volumes are rebuilding and no storage Nodes are draining. This prints a report with
+    /// the result of each check, and fails if any of them fails. This does not make any changes
+    /// to the cluster.
+    Preflight,
+}
+
+/// This is the format which the output of a read-only command is printed in.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize, ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) enum OutputFormat {
+    /// A table with a row for each entry.
+    Table,
+    /// A JSON array with an object for each entry.
+    Json,
+}
+
+/// This is the format which the log lines are printed in.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize, ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) enum LogFormat {
+    /// Human-readable log lines.
+    Pretty,
+    /// A JSON object for each log line.
+    Json,
+}
+
+/// This decides the helm values which the Core chart is upgraded with.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Serialize, ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) enum ValuesMode {
+    /// The installed release's values are merged over the new chart's defaults.
+    #[default]
+    Reuse,
+    /// The new chart's defaults are used, helm's '--reset-values'.
+    Reset,
+    /// Helm's '--reset-then-reuse-values'.
+    ResetThenReuse,
 }
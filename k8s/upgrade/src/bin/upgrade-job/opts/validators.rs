@@ -1,27 +1,33 @@
 use crate::{
     common::{
-        constants::CORE_CHART_NAME,
+        constants::{CORE_CHART_NAME, IO_ENGINE_LABEL},
         error::{
-            FindingHelmChart, GetNamespace, HelmCommand, HelmListCommand, HelmRelease, HelmVersion,
-            HelmVersionCommand, ListStorageNodes, NotADirectory, NotAFile, ReadingFile,
-            RegexCompile, Result, U8VectorToString, ValidateDirPath, ValidateFilePath,
-            YamlParseFromFile,
+            FindingHelmChart, GetNamespace, HelmBinaryNotExecutable, HelmCommand, HelmRelease,
+            HelmVersion, HelmVersionCommand, InsufficientRbac, ListPodsWithLabel, ListStorageNodes,
+            NoInputHelmChartDir, NotADirectory, NotAFile, ReadingFile, RegexCompile,
+            RestEndpointMismatch, Result, SelfSubjectAccessReview, U8VectorToString,
+            ValidateDirPath, ValidateFilePath, YamlParseFromFile,
         },
         kube_client::KubeClientSet,
         rest_client::RestClientSet,
     },
-    helm::chart::Chart,
+    helm::{chart::Chart, client::ensure_helm_success, helm_binary},
+    opts::{CanaryResume, CliArgs, PvcScope},
     vec_to_strings,
 };
+use k8s_openapi::api::authorization::v1::{
+    ResourceAttributes, SelfSubjectAccessReview as AccessReview, SelfSubjectAccessReviewSpec,
+};
+use kube::api::{Api, ListParams, PostParams};
 use regex::bytes::Regex;
 use snafu::{ensure, ResultExt};
-use std::{fs, path::PathBuf, process::Command, str};
+use std::{fs, os::unix::fs::PermissionsExt, path::PathBuf, process::Command, str};
 use tracing::debug;
 
 /// Validate that the helm release specified in the CLI options exists in the namespace,
 /// which is also specified in the CLI options.
 pub(crate) fn validate_helm_release(name: String, namespace: String) -> Result<()> {
-    let command: &str = "helm";
+    let command: &str = helm_binary();
     let args: Vec<String> =
         vec_to_strings!["list", "-n", namespace.as_str(), "--deployed", "--short"];
 
@@ -38,16 +44,7 @@ pub(crate) fn validate_helm_release(name: String, namespace: String) -> Result<(
 
     let stdout_str = str::from_utf8(output.stdout.as_slice()).context(U8VectorToString)?;
     debug!(stdout=%stdout_str, "Helm list command standard output");
-    ensure!(
-        output.status.success(),
-        HelmListCommand {
-            command: command.to_string(),
-            args,
-            std_err: str::from_utf8(output.stderr.as_slice())
-                .context(U8VectorToString)?
-                .to_string()
-        }
-    );
+    ensure_helm_success(command, args, &output)?;
 
     // Validate that the release-name list has the name which is specified in the CLI options.
     let regex = format!(r"(\n)?{name}(\n)?");
@@ -61,9 +58,27 @@ pub(crate) fn validate_helm_release(name: String, namespace: String) -> Result<(
     Ok(())
 }
 
+/// Validate that the helm binary exists and is executable, if it is set as a path. A bare binary
+/// name is looked up in the shell's $PATH when it is run.
+pub(crate) fn validate_helm_binary(helm_binary: &str) -> Result<()> {
+    if !helm_binary.contains('/') {
+        return Ok(());
+    }
+
+    let path = PathBuf::from(helm_binary);
+    let metadata = fs::metadata(path.as_path()).context(ValidateFilePath { path: path.clone() })?;
+    ensure!(metadata.is_file(), NotAFile { path });
+    ensure!(
+        metadata.permissions().mode() & 0o111 != 0,
+        HelmBinaryNotExecutable { path }
+    );
+
+    Ok(())
+}
+
 /// Validate that the helm v3 binary is present in the shell's $PATH.
 pub(crate) fn validate_helmv3_in_path() -> Result<()> {
-    let command: &str = "helm";
+    let command: &str = helm_binary();
     let args: Vec<String> = vec_to_strings!["version", "--short"];
 
     debug!(%command, ?args, "Helm version command");
@@ -108,7 +123,13 @@ pub(crate) fn validate_helmv3_in_path() -> Result<()> {
 }
 
 /// Validate the input helm chart directory path.
-pub(crate) fn validate_helm_chart_dir(core_dir: PathBuf) -> Result<()> {
+pub(crate) fn validate_helm_chart_dir(core_dir: Option<PathBuf>) -> Result<()> {
+    let core_dir = core_dir.ok_or(
+        NoInputHelmChartDir {
+            chart_name: CORE_CHART_NAME.to_string(),
+        }
+        .build(),
+    )?;
     validate_core_helm_chart_variant_in_dir(core_dir)
 }
 
@@ -228,15 +249,226 @@ pub(crate) async fn validate_namespace(ns: String) -> Result<()> {
     Ok(())
 }
 
-/// This checks if the storage API is reachable and usable.
-pub(crate) async fn validate_rest_endpoint(rest_endpoint: String) -> Result<()> {
-    let rest_client = RestClientSet::new_with_url(rest_endpoint)?;
+/// These are the Kubernetes API requests which the upgrade makes in the release's namespace, as
+/// (API group, resource, verb).
+const NAMESPACE_PERMISSIONS: [(&str, &str, &str); 10] = [
+    ("", "pods", "get"),
+    ("", "pods", "list"),
+    ("apps", "deployments", "list"),
+    ("batch", "jobs", "patch"),
+    ("events.k8s.io", "events", "create"),
+    ("coordination.k8s.io", "leases", "get"),
+    ("coordination.k8s.io", "leases", "create"),
+    ("coordination.k8s.io", "leases", "update"),
+    ("coordination.k8s.io", "leases", "patch"),
+    ("coordination.k8s.io", "leases", "delete"),
+];
+
+/// These are the Kubernetes API requests which the data-plane upgrade makes in the release's
+/// namespace, to restart the io-engine Pods.
+const DATA_PLANE_NAMESPACE_PERMISSIONS: [(&str, &str, &str); 4] = [
+    ("", "pods", "delete"),
+    ("apps", "daemonsets", "get"),
+    ("apps", "statefulsets", "list"),
+    ("apps", "statefulsets", "patch"),
+];
+
+/// These are the Kubernetes API requests which the upgrade makes in the control-plane's
+/// namespace, to check on the control-plane Pods.
+const CONTROL_PLANE_NAMESPACE_PERMISSIONS: [(&str, &str, &str); 1] = [("", "pods", "list")];
+
+/// These are the cluster-scoped Kubernetes API requests which the upgrade makes.
+const CLUSTER_PERMISSIONS: [(&str, &str, &str); 6] = [
+    ("", "namespaces", "get"),
+    ("", "nodes", "get"),
+    ("", "nodes", "list"),
+    ("apiextensions.k8s.io", "customresourcedefinitions", "get"),
+    (
+        "apiextensions.k8s.io",
+        "customresourcedefinitions",
+        "create",
+    ),
+    ("apiextensions.k8s.io", "customresourcedefinitions", "patch"),
+];
+
+/// This is a Kubernetes API request which the upgrade makes.
+struct RequiredPermission {
+    group: &'static str,
+    resource: &'static str,
+    verb: &'static str,
+    /// This is None for the cluster-scoped requests.
+    namespace: Option<String>,
+}
+
+/// Returns the (API group, resource, verb) requests as RequiredPermissions in the namespace.
+fn permissions_in(
+    permissions: &[(&'static str, &'static str, &'static str)],
+    namespace: Option<&str>,
+) -> Vec<RequiredPermission> {
+    permissions
+        .iter()
+        .map(|&(group, resource, verb)| RequiredPermission {
+            group,
+            resource,
+            verb,
+            namespace: namespace.map(ToString::to_string),
+        })
+        .collect()
+}
+
+/// Returns the Kubernetes API requests which the upgrade makes with these options.
+fn required_permissions(opts: &CliArgs) -> Vec<RequiredPermission> {
+    let namespace = opts.namespace();
+    let control_plane_namespace = opts.control_plane_namespace();
+
+    let mut permissions = permissions_in(&NAMESPACE_PERMISSIONS, Some(namespace.as_str()));
+    if !opts.skip_data_plane_restart() {
+        permissions.extend(permissions_in(
+            &DATA_PLANE_NAMESPACE_PERMISSIONS,
+            Some(namespace.as_str()),
+        ));
+        if opts.respect_pdb() {
+            permissions.extend(permissions_in(
+                &[("policy", "poddisruptionbudgets", "list")],
+                Some(namespace.as_str()),
+            ));
+        }
+        if opts.canary_first() && opts.canary_resume().eq(&CanaryResume::ConfigMap) {
+            permissions.extend(permissions_in(
+                &[("", "configmaps", "get"), ("", "configmaps", "patch")],
+                Some(namespace.as_str()),
+            ));
+        }
+    }
+    if control_plane_namespace.ne(&namespace) {
+        permissions.extend(permissions_in(
+            &CONTROL_PLANE_NAMESPACE_PERMISSIONS,
+            Some(control_plane_namespace.as_str()),
+        ));
+    }
+    if opts.verify_pvc_binding() {
+        let pvc_namespace = match opts.pvc_binding_scope() {
+            PvcScope::Namespace => Some(namespace.as_str()),
+            PvcScope::Cluster => None,
+        };
+        permissions.extend(permissions_in(
+            &[("", "persistentvolumeclaims", "list")],
+            pvc_namespace,
+        ));
+    }
+    permissions.extend(permissions_in(&CLUSTER_PERMISSIONS, None));
+    permissions
+}
+
+/// This checks that the upgrade Job's ServiceAccount has the Kubernetes RBAC permissions for the
+/// Kubernetes API requests which the upgrade makes with these options, using
+/// SelfSubjectAccessReviews. All of the missing permissions are reported together. The storage Node
+/// drains are requests to the storage REST API, which are not subject to Kubernetes RBAC, so they
+/// are not checked here.
+pub(crate) async fn validate_rbac(opts: &CliArgs) -> Result<()> {
+    let k8s_client = KubeClientSet::builder()
+        .with_namespace(opts.namespace())
+        .build()
+        .await?;
+    let reviews_api: Api<AccessReview> = Api::all(k8s_client.client());
+
+    let mut missing_permissions: Vec<String> = Vec::new();
+    for RequiredPermission {
+        group,
+        resource,
+        verb,
+        namespace,
+    } in required_permissions(opts)
+    {
+        let scope = match namespace.as_deref() {
+            Some(namespace) => format!("namespace {namespace}"),
+            None => "all namespaces".to_string(),
+        };
+        let review = AccessReview {
+            spec: SelfSubjectAccessReviewSpec {
+                resource_attributes: Some(ResourceAttributes {
+                    group: Some(group.to_string()),
+                    resource: Some(resource.to_string()),
+                    verb: Some(verb.to_string()),
+                    namespace,
+                    ..Default::default()
+                }),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let review = reviews_api
+            .create(&PostParams::default(), &review)
+            .await
+            .context(SelfSubjectAccessReview {
+                verb,
+                resource,
+                scope: scope.as_str(),
+            })?;
+
+        let allowed = review.status.map(|status| status.allowed).unwrap_or(false);
+        if !allowed {
+            let resource = match group {
+                "" => resource.to_string(),
+                group => format!("{resource}.{group}"),
+            };
+            missing_permissions.push(format!("{verb} {resource} in {scope}"));
+        }
+    }
+
+    debug!(?missing_permissions, "Reviewed Kubernetes RBAC permissions");
+    ensure!(
+        missing_permissions.is_empty(),
+        InsufficientRbac {
+            permissions: missing_permissions
+        }
+    );
+    Ok(())
+}
+
+/// This checks if the storage API is reachable and usable, and that it is the storage API of the
+/// helm release in the namespace, i.e. that at least one of its Nodes runs an io-engine Pod from
+/// the namespace. This guards against draining the Nodes of another cluster.
+pub(crate) async fn validate_rest_endpoint(rest_endpoint: String, namespace: String) -> Result<()> {
+    let rest_client = RestClientSet::new_with_url(rest_endpoint.clone())?;
 
-    rest_client
+    let storage_nodes: Vec<String> = rest_client
         .nodes_api()
         .get_nodes(None)
         .await
-        .context(ListStorageNodes)?;
+        .context(ListStorageNodes)?
+        .into_body()
+        .into_iter()
+        .map(|storage_node| storage_node.id)
+        .collect();
+
+    let k8s_client = KubeClientSet::builder()
+        .with_namespace(namespace.as_str())
+        .build()
+        .await?;
+    let io_engine_nodes: Vec<String> = k8s_client
+        .list_pods(&ListParams::default().labels(IO_ENGINE_LABEL))
+        .await
+        .context(ListPodsWithLabel {
+            label: IO_ENGINE_LABEL.to_string(),
+            namespace: namespace.clone(),
+        })?
+        .into_iter()
+        .filter_map(|pod| pod.spec.and_then(|spec| spec.node_name))
+        .collect();
+
+    ensure!(
+        io_engine_nodes.is_empty()
+            || io_engine_nodes
+                .iter()
+                .any(|node| storage_nodes.contains(node)),
+        RestEndpointMismatch {
+            rest_endpoint,
+            namespace,
+            storage_nodes,
+            io_engine_nodes
+        }
+    );
 
     Ok(())
 }
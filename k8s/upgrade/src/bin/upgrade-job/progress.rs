@@ -0,0 +1,129 @@
+use crate::upgrade::phase::UpgradePhase;
+use std::{
+    io::{IsTerminal, Write},
+    sync::{Mutex, OnceLock},
+};
+
+/// This is the number of characters the progress bar is drawn with.
+const BAR_WIDTH: usize = 30;
+
+/// This is the progress display of the upgrade, set up once the CLI arguments are parsed.
+static PROGRESS: OnceLock<UpgradeProgress> = OnceLock::new();
+
+/// This is the state which the progress line is drawn from.
+#[derive(Default)]
+struct ProgressState {
+    phase: Option<UpgradePhase>,
+    node: Option<String>,
+    step: Option<&'static str>,
+    nodes_done: usize,
+    nodes_total: usize,
+}
+
+/// This draws a single line showing the upgrade phase and the data-plane progress across the
+/// Nodes, e.g. '[=====>    ] node 3/10 (node-a), phase: data-plane, draining'. The line is drawn
+/// on stderr, and only if stderr is a terminal, so that piped and JSON output are not corrupted.
+/// The log lines are printed as usual either way.
+pub(crate) struct UpgradeProgress {
+    enabled: bool,
+    state: Mutex<ProgressState>,
+}
+
+impl UpgradeProgress {
+    /// This creates a progress display, which is only drawn if it's enabled and stderr is a
+    /// terminal.
+    fn new(enabled: bool) -> Self {
+        Self {
+            enabled: enabled && std::io::stderr().is_terminal(),
+            state: Mutex::new(ProgressState::default()),
+        }
+    }
+
+    /// Updates the phase of the upgrade. The line is ended when the upgrade reaches a terminal
+    /// phase, so that it is not drawn over.
+    pub(crate) fn set_phase(&self, phase: UpgradePhase) {
+        self.update(|state| state.phase = Some(phase));
+        if self.enabled && phase.is_terminal() {
+            eprintln!();
+        }
+    }
+
+    /// Updates the total number of Nodes whose io-engine Pods are to be restarted.
+    pub(crate) fn set_nodes_total(&self, nodes_total: usize) {
+        self.update(|state| state.nodes_total = nodes_total);
+    }
+
+    /// Updates the Node which is being upgraded, and the step of its upgrade.
+    pub(crate) fn set_node_step(&self, node: &str, step: &'static str) {
+        self.update(|state| {
+            state.node = Some(node.to_string());
+            state.step = Some(step);
+        });
+    }
+
+    /// Marks the upgrade of the current Node as done.
+    pub(crate) fn node_done(&self) {
+        self.update(|state| {
+            state.nodes_done += 1;
+            state.node = None;
+            state.step = None;
+        });
+    }
+
+    /// Applies the change to the state and redraws the line, if enabled.
+    fn update<F>(&self, change: F)
+    where
+        F: FnOnce(&mut ProgressState),
+    {
+        if !self.enabled {
+            return;
+        }
+
+        let Ok(mut state) = self.state.lock() else {
+            return;
+        };
+        change(&mut state);
+
+        let mut line = String::new();
+        if state.nodes_total > 0 {
+            let nodes_done = state.nodes_done.min(state.nodes_total);
+            let filled = BAR_WIDTH * nodes_done / state.nodes_total;
+            line.push_str(
+                format!(
+                    "[{}>{}] node {}/{}",
+                    "=".repeat(filled),
+                    " ".repeat(BAR_WIDTH - filled),
+                    (nodes_done + 1).min(state.nodes_total),
+                    state.nodes_total
+                )
+                .as_str(),
+            );
+            if let Some(node) = state.node.as_ref() {
+                line.push_str(format!(" ({node})").as_str());
+            }
+            line.push_str(", ");
+        }
+        if let Some(phase) = state.phase {
+            line.push_str(format!("phase: {phase}").as_str());
+        }
+        if let Some(step) = state.step {
+            line.push_str(format!(", {step}").as_str());
+        }
+
+        // Clear the line and draw over it.
+        let mut stderr = std::io::stderr().lock();
+        let _ = write!(stderr, "\r\x1b[2K{line}");
+        let _ = stderr.flush();
+    }
+}
+
+/// This sets up the progress display. It is drawn only if 'enabled' is set and stderr is a
+/// terminal.
+pub(crate) fn init_progress(enabled: bool) {
+    PROGRESS.get_or_init(|| UpgradeProgress::new(enabled));
+}
+
+/// This returns the progress display of the upgrade. This is a disabled one, if it is not set up.
+pub(crate) fn progress() -> &'static UpgradeProgress {
+    PROGRESS.get_or_init(|| UpgradeProgress::new(false))
+}
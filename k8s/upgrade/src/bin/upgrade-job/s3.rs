@@ -0,0 +1,282 @@
+use crate::{
+    common::error::{
+        InvalidS3Endpoint, InvalidS3Url, NoS3Credentials, Result, S3Client, S3UploadRejected,
+        S3UploadRequest,
+    },
+    opts::CliArgs,
+};
+use k8s_openapi::chrono::Utc;
+use ring::{digest, hmac};
+use snafu::{IntoError, ResultExt};
+use std::time::Duration;
+use url::Url;
+
+/// This is the time an S3-compatible store is given to accept an upload.
+const S3_UPLOAD_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// This is the AWS Signature Version 4 signing algorithm.
+const SIGV4_ALGORITHM: &str = "AWS4-HMAC-SHA256";
+
+/// This is the location of an object in an S3-compatible store, e.g. 's3://bucket/path/to/key'.
+#[derive(Debug, PartialEq)]
+struct S3Url {
+    bucket: String,
+    key: String,
+}
+
+impl S3Url {
+    /// Parses an 's3://bucket/key' URL. Both the bucket and the key must be non-empty.
+    fn parse(url: &str) -> Result<Self> {
+        let (bucket, key) = url
+            .strip_prefix("s3://")
+            .and_then(|location| location.split_once('/'))
+            .filter(|(bucket, key)| !bucket.is_empty() && !key.is_empty())
+            .ok_or(InvalidS3Url { url }.build())?;
+
+        Ok(Self {
+            bucket: bucket.to_string(),
+            key: key.to_string(),
+        })
+    }
+
+    /// This is the path of the object, for path-style requests, with each of the key's segments
+    /// URI-encoded as per the AWS Signature Version 4 rules.
+    fn path(&self) -> String {
+        let key = self
+            .key
+            .split('/')
+            .map(uri_encode)
+            .collect::<Vec<String>>()
+            .join("/");
+        format!("/{}/{key}", uri_encode(self.bucket.as_str()))
+    }
+}
+
+/// This is the access key of an S3-compatible store.
+struct S3Credentials {
+    access_key_id: String,
+    secret_access_key: String,
+    session_token: Option<String>,
+}
+
+/// This uploads objects to an S3-compatible store, with path-style requests signed with AWS
+/// Signature Version 4.
+pub(crate) struct S3Uploader {
+    endpoint: Url,
+    region: String,
+    credentials: S3Credentials,
+    client: reqwest::Client,
+}
+
+impl S3Uploader {
+    /// This creates an S3Uploader for the S3-compatible store in the options.
+    pub(crate) fn new(opts: &CliArgs) -> Result<Self> {
+        let endpoint_str = opts.report_s3_endpoint();
+        let endpoint = Url::parse(endpoint_str.as_str()).context(InvalidS3Endpoint {
+            endpoint: endpoint_str.clone(),
+        })?;
+        if endpoint.host_str().is_none() {
+            return Err(InvalidS3Endpoint {
+                endpoint: endpoint_str,
+            }
+            .into_error(url::ParseError::EmptyHost));
+        }
+
+        let (Some(access_key_id), Some(secret_access_key)) = (
+            opts.report_s3_access_key_id(),
+            opts.report_s3_secret_access_key(),
+        ) else {
+            return NoS3Credentials.fail();
+        };
+
+        let client = reqwest::Client::builder()
+            .timeout(S3_UPLOAD_TIMEOUT)
+            .build()
+            .context(S3Client)?;
+
+        Ok(Self {
+            endpoint,
+            region: opts.report_s3_region(),
+            credentials: S3Credentials {
+                access_key_id,
+                secret_access_key,
+                session_token: opts.report_s3_session_token(),
+            },
+            client,
+        })
+    }
+
+    /// PUTs the body as the object at the 's3://bucket/key' URL.
+    pub(crate) async fn put_object(&self, s3_url: &str, body: Vec<u8>) -> Result<()> {
+        let location = S3Url::parse(s3_url)?;
+        let path = location.path();
+        let mut request_url = self.endpoint.clone();
+        request_url.set_path(path.as_str());
+
+        // The endpoint's host is checked when the S3Uploader is created.
+        let host = match self.endpoint.port() {
+            Some(port) => format!("{}:{port}", self.endpoint.host_str().unwrap_or_default()),
+            None => self.endpoint.host_str().unwrap_or_default().to_string(),
+        };
+        let now = Utc::now();
+        let amz_date = now.format("%Y%m%dT%H%M%SZ").to_string();
+        let date_stamp = now.format("%Y%m%d").to_string();
+        let payload_hash = sha256_hex(body.as_slice());
+
+        // The headers are signed in the order of their names.
+        let mut headers: Vec<(&str, String)> = vec![
+            ("host", host),
+            ("x-amz-content-sha256", payload_hash.clone()),
+            ("x-amz-date", amz_date.clone()),
+        ];
+        if let Some(session_token) = self.credentials.session_token.as_ref() {
+            headers.push(("x-amz-security-token", session_token.clone()));
+        }
+        let signed_headers = headers
+            .iter()
+            .map(|(name, _)| *name)
+            .collect::<Vec<&str>>()
+            .join(";");
+        let canonical_headers: String = headers
+            .iter()
+            .map(|(name, value)| format!("{name}:{}\n", value.trim()))
+            .collect();
+
+        let canonical_request =
+            format!("PUT\n{path}\n\n{canonical_headers}\n{signed_headers}\n{payload_hash}");
+        let scope = format!("{date_stamp}/{}/s3/aws4_request", self.region);
+        let string_to_sign = format!(
+            "{SIGV4_ALGORITHM}\n{amz_date}\n{scope}\n{}",
+            sha256_hex(canonical_request.as_bytes())
+        );
+        let signing_key = signing_key(
+            self.credentials.secret_access_key.as_str(),
+            date_stamp.as_str(),
+            self.region.as_str(),
+            "s3",
+        );
+        let signature = to_hex(hmac::sign(&signing_key, string_to_sign.as_bytes()).as_ref());
+        let authorization = format!(
+            "{SIGV4_ALGORITHM} Credential={}/{scope}, SignedHeaders={signed_headers}, \
+            Signature={signature}",
+            self.credentials.access_key_id
+        );
+
+        let mut request = self
+            .client
+            .put(request_url.as_str())
+            .header("authorization", authorization)
+            .header("content-type", "application/json");
+        // The host header is set by the HTTP client, from the URL.
+        for (name, value) in headers.into_iter().filter(|(name, _)| name.ne(&"host")) {
+            request = request.header(name, value);
+        }
+
+        let response = request
+            .body(body)
+            .send()
+            .await
+            .context(S3UploadRequest { url: s3_url })?;
+        let status = response.status();
+        if !status.is_success() {
+            return S3UploadRejected {
+                url: s3_url,
+                status: status.as_u16(),
+                body: response.text().await.unwrap_or_default(),
+            }
+            .fail();
+        }
+
+        Ok(())
+    }
+}
+
+/// Derives the AWS Signature Version 4 signing key for the date, region and service.
+fn signing_key(
+    secret_access_key: &str,
+    date_stamp: &str,
+    region: &str,
+    service: &str,
+) -> hmac::Key {
+    let mut key = format!("AWS4{secret_access_key}").into_bytes();
+    for data in [date_stamp, region, service, "aws4_request"] {
+        let tag = hmac::sign(&hmac::Key::new(hmac::HMAC_SHA256, &key), data.as_bytes());
+        key = tag.as_ref().to_vec();
+    }
+    hmac::Key::new(hmac::HMAC_SHA256, &key)
+}
+
+/// URI-encodes every byte except the unreserved characters, as per the AWS Signature Version 4
+/// rules.
+fn uri_encode(segment: &str) -> String {
+    segment
+        .bytes()
+        .map(|byte| match byte {
+            b'A' ..= b'Z' | b'a' ..= b'z' | b'0' ..= b'9' | b'-' | b'_' | b'.' | b'~' => {
+                (byte as char).to_string()
+            }
+            _ => format!("%{byte:02X}"),
+        })
+        .collect()
+}
+
+/// Returns the lowercase hex encoded SHA-256 digest of the data.
+fn sha256_hex(data: &[u8]) -> String {
+    to_hex(digest::digest(&digest::SHA256, data).as_ref())
+}
+
+/// Returns the lowercase hex encoding of the bytes.
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn s3_url_is_parsed() {
+        assert_eq!(
+            S3Url::parse("s3://upgrades/cluster-a/mayastor 2.4.0.json").unwrap(),
+            S3Url {
+                bucket: "upgrades".to_string(),
+                key: "cluster-a/mayastor 2.4.0.json".to_string(),
+            }
+        );
+        assert!(S3Url::parse("s3://upgrades").is_err());
+        assert!(S3Url::parse("s3://upgrades/").is_err());
+        assert!(S3Url::parse("https://upgrades/report.json").is_err());
+    }
+
+    #[test]
+    fn s3_url_path_is_uri_encoded() {
+        let location = S3Url::parse("s3://upgrades/cluster-a/mayastor 2.4.0+1.json").unwrap();
+        assert_eq!(
+            location.path(),
+            "/upgrades/cluster-a/mayastor%202.4.0%2B1.json"
+        );
+    }
+
+    /// This is the signing key example from the AWS Signature Version 4 documentation.
+    #[test]
+    fn signing_key_is_derived() {
+        let key = signing_key(
+            "wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY",
+            "20120215",
+            "us-east-1",
+            "iam",
+        );
+        let expected = hmac::Key::new(
+            hmac::HMAC_SHA256,
+            &[
+                0xf4, 0x78, 0x0e, 0x2d, 0x9f, 0x65, 0xfa, 0x89, 0x5f, 0x9c, 0x67, 0xb3, 0x2c, 0xe1,
+                0xba, 0xf0, 0xb0, 0xd8, 0xa4, 0x35, 0x05, 0xa0, 0x00, 0xa1, 0xa9, 0xe0, 0x90, 0xd4,
+                0x14, 0xdb, 0x40, 0x4d,
+            ],
+        );
+        assert_eq!(
+            hmac::sign(&key, b"upgrade").as_ref(),
+            hmac::sign(&expected, b"upgrade").as_ref()
+        );
+    }
+}
@@ -0,0 +1,89 @@
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    OnceLock,
+};
+use tokio::{
+    signal::unix::{signal, SignalKind},
+    sync::Notify,
+};
+use tracing::{error, warn};
+use utils::tracing_telemetry::flush_traces;
+
+/// This is the exit code of the upgrade Job, when it is stopped by a termination signal. This is
+/// the exit code of a process killed by SIGTERM.
+pub(crate) const INTERRUPTED_EXIT_CODE: i32 = 143;
+
+/// This is the cancellation token of the upgrade, set up once the CLI arguments are parsed.
+static SHUTDOWN: OnceLock<ShutdownToken> = OnceLock::new();
+
+/// This is cancelled when the upgrade Job receives a SIGTERM or a SIGINT, e.g. when its Pod is
+/// evicted, or when it loses the upgrade Lease. Once cancelled, the data-plane upgrade starts no
+/// more Nodes, and the Nodes which are being upgraded are uncordoned before the upgrade Job exits.
+#[derive(Default)]
+pub(crate) struct ShutdownToken {
+    cancelled: AtomicBool,
+    notify: Notify,
+}
+
+impl ShutdownToken {
+    /// Cancels the token, and wakes up all of the waiters.
+    pub(crate) fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+        self.notify.notify_waiters();
+    }
+
+    /// This is a predicate which is true if the upgrade Job has been asked to terminate.
+    pub(crate) fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+
+    /// Waits until the upgrade Job is asked to terminate.
+    pub(crate) async fn cancelled(&self) {
+        loop {
+            // The waiter is registered before the flag is checked, so that a cancel in between
+            // is not missed.
+            let notified = self.notify.notified();
+            if self.is_cancelled() {
+                return;
+            }
+            notified.await;
+        }
+    }
+}
+
+/// This sets up the handling of the termination signals. The first SIGTERM or SIGINT cancels the
+/// upgrade, so that it stops gracefully. A second one exits right away.
+pub(crate) fn init_shutdown_handler() {
+    let (Ok(mut sigterm), Ok(mut sigint)) = (
+        signal(SignalKind::terminate()),
+        signal(SignalKind::interrupt()),
+    ) else {
+        warn!("Failed to set up the termination signal handler");
+        return;
+    };
+
+    tokio::spawn(async move {
+        tokio::select! {
+            _ = sigterm.recv() => {}
+            _ = sigint.recv() => {}
+        }
+        warn!(
+            "Received a termination signal, stopping the upgrade once the Nodes being upgraded \
+            are uncordoned, send it again to exit right away"
+        );
+        shutdown().cancel();
+
+        tokio::select! {
+            _ = sigterm.recv() => {}
+            _ = sigint.recv() => {}
+        }
+        error!("Received a second termination signal, exiting right away");
+        flush_traces();
+        std::process::exit(INTERRUPTED_EXIT_CODE);
+    });
+}
+
+/// This returns the cancellation token of the upgrade.
+pub(crate) fn shutdown() -> &'static ShutdownToken {
+    SHUTDOWN.get_or_init(ShutdownToken::default)
+}
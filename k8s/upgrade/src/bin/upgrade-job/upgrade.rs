@@ -1,10 +1,53 @@
 use crate::{
-    common::{constants::PRODUCT, error::Result},
+    common::{
+        constants::PRODUCT,
+        error::{
+            DataPlaneUpgradeIncomplete, Error, Result, SerializeUpgradeSummary, UpgradeTimeout,
+            WritingFile,
+        },
+        kube_client::KubeClientSet,
+        rest_client::RestClientSet,
+    },
     events::event_recorder::{EventAction, EventRecorder},
-    helm::upgrade::{HelmUpgrade, HelmUpgradeRunner},
-    opts::CliArgs,
+    helm::{
+        client::HelmReleaseClient,
+        upgrade::{HelmUpgrade, HelmUpgradeRunner, UpgradeReport},
+    },
+    notify::Notifier,
+    opts::{CliArgs, OutputFormat},
+    s3::S3Uploader,
 };
-use data_plane::upgrade_data_plane;
+use checkpoint::UpgradeCheckpoint;
+use crd::dry_run_apply_crds;
+use data_plane::{
+    data_plane_upgrade_is_required, nodes_with_upgrade_cordons, outdated_data_plane_nodes,
+    remove_upgrade_cordons, restore_io_engine_update_strategy, rollback_upgraded_data_plane,
+    selected_node_names, set_io_engine_on_delete_update_strategy, settle_after_helm_upgrade,
+    upgrade_data_plane, verify_agent_core_leader, DataPlaneUpgradeOutcome, DataPlaneUpgradeTracker,
+};
+use lock::UpgradeLock;
+use nodes::list_nodes;
+use path::{validate_crd_compatibility, verify_job_version};
+use phase::{UpgradePhase, UpgradePhaseDriver};
+use plan::plan_data_plane;
+use snafu::{ensure, ResultExt};
+use std::{
+    collections::HashSet,
+    fs,
+    path::Path,
+    time::{Duration, Instant},
+};
+use tracing::{error, info, warn};
+use verify::{pvc_binding_snapshot, verify_cluster_health, verify_pvc_binding, PvcBindingSnapshot};
+
+/// Contains the dry-run apply of the helm chart's CustomResourceDefinitions.
+pub(crate) mod crd;
+
+/// Contains the record of the upgrade's progress, which an interrupted upgrade continues from.
+pub(crate) mod checkpoint;
+
+/// Contains the Lease which keeps more than one upgrade from running at a time.
+pub(crate) mod lock;
 
 /// Contains the data-plane upgrade logic.
 pub(crate) mod data_plane;
@@ -15,37 +58,422 @@ pub(crate) mod utils;
 /// Tools to validate upgrade path.
 pub(crate) mod path;
 
+/// Contains the read-only listing of the storage Nodes' upgrade status.
+pub(crate) mod nodes;
+
+/// Contains the read-only planning of the data-plane upgrade.
+pub(crate) mod plan;
+
+/// Contains the upgrade phases and the driver which moves the upgrade through them.
+pub(crate) mod phase;
+
+/// Contains the container image registry lookups.
+pub(crate) mod registry;
+
+/// Contains the post-upgrade verification checks.
+pub(crate) mod verify;
+
+/// Contains the preflight checks, which report whether the upgrade may be started.
+pub(crate) mod checks;
+
 /// This function starts and sees upgrade through to the end.
 pub(crate) async fn upgrade(opts: &CliArgs) -> Result<()> {
+    if opts.dry_run() {
+        return upgrade_dry_run(opts).await;
+    }
+
+    let notifier = Notifier::new(opts)?;
+
     let mut event = EventRecorder::builder()
         .with_pod_name(&opts.pod_name())
         .with_namespace(&opts.namespace())
         .build()
         .await?;
 
-    let result = upgrade_product(opts, &mut event).await;
+    // Only one upgrade at a time may drain the Nodes.
+    let result = match UpgradeLock::acquire(
+        opts.release_name().as_str(),
+        opts.namespace().as_str(),
+        opts.pod_name().as_str(),
+    )
+    .await
+    {
+        Ok(lock) => {
+            let result = upgrade_product(opts, &mut event).await;
+            lock.release().await;
+            result
+        }
+        Err(error) => {
+            event.publish_unrecoverable(&error, true).await;
+            Err(error)
+        }
+    };
+
+    if let Ok(report) = result.as_ref() {
+        if let Some(path) = opts.output_summary() {
+            if let Err(error) = write_upgrade_summary(path.as_path(), report) {
+                warn!(%error, "Failed to write the upgrade summary");
+            }
+        }
+        if let Some(report_s3_url) = opts.report_s3_url() {
+            match upload_upgrade_summary(opts, report_s3_url.as_str(), report).await {
+                Ok(()) => info!(url = %report_s3_url, "Uploaded the upgrade summary"),
+                Err(error) => warn!(%error, "Failed to upload the upgrade summary"),
+            }
+        }
+    }
+    let result = result.map(|_| ());
+
+    event.annotate_job_outcome(result.is_ok()).await;
 
     // This makes sure that the event worker attempts to publish
     // all of its events. It waits for the event worker to exit.
     event.shutdown_worker().await;
 
+    match result.as_ref() {
+        Ok(()) => notifier.notify_success().await,
+        Err(error) => notifier.notify_failure(error).await,
+    }
+
     result
 }
 
+/// Uploads the summary of the upgrade to the 's3://bucket/key' URL, as JSON.
+async fn upload_upgrade_summary(
+    opts: &CliArgs,
+    report_s3_url: &str,
+    report: &UpgradeReport,
+) -> Result<()> {
+    let summary = serde_json::to_vec_pretty(report).context(SerializeUpgradeSummary)?;
+    S3Uploader::new(opts)?
+        .put_object(report_s3_url, summary)
+        .await
+}
+
+/// Writes the summary of the upgrade to the file, as JSON.
+fn write_upgrade_summary(path: &Path, report: &UpgradeReport) -> Result<()> {
+    let summary = serde_json::to_vec_pretty(report).context(SerializeUpgradeSummary)?;
+    fs::write(path, summary).context(WritingFile {
+        filepath: path.to_path_buf(),
+    })?;
+    info!(path = %path.display(), "Wrote the upgrade summary");
+    Ok(())
+}
+
+/// This previews the upgrade, without making any changes to the cluster. The upgrade is validated
+/// and the helm upgrade is dry-run, as in the Preflight phase. The helm upgrade is then dropped
+/// instead of being run, and the io-engine Pods which the data-plane upgrade would restart are
+/// printed, along with the storage Nodes as reported by the storage REST API.
+async fn upgrade_dry_run(opts: &CliArgs) -> Result<()> {
+    let mut event = EventRecorder::builder()
+        .with_pod_name(&opts.pod_name())
+        .with_namespace(&opts.namespace())
+        .build()
+        .await?;
+    let result = preflight(opts, &mut event).await;
+    event.shutdown_worker().await;
+    let (_, plan) = result?;
+
+    info!(
+        from_version = %plan.from_version,
+        to_version = %plan.to_version,
+        "Dry-run: the helm upgrade would upgrade the control-plane"
+    );
+    if plan.data_plane_is_outdated {
+        let k8s_client = KubeClientSet::builder()
+            .with_namespace(opts.namespace())
+            .build()
+            .await?;
+        let rest_client = RestClientSet::new_with_url(opts.rest_endpoint()?)?;
+        let selected_nodes =
+            selected_node_names(opts.node_selector().as_deref(), &k8s_client).await?;
+        for node_name in outdated_data_plane_nodes(
+            &k8s_client,
+            Some(&rest_client),
+            opts.namespace().as_str(),
+            &plan.to_version,
+        )
+        .await?
+        {
+            if let Some(selected_nodes) = selected_nodes.as_ref() {
+                if !selected_nodes.contains(&node_name) {
+                    info!(
+                        node.name = %node_name,
+                        "Dry-run: the Node does not match the Node label selector, its io-engine \
+                        Pod would not be restarted"
+                    );
+                    continue;
+                }
+            }
+            info!(
+                node.name = %node_name,
+                "Dry-run: the data-plane upgrade would drain the Node, restart its io-engine Pod \
+                and uncordon the Node"
+            );
+        }
+    } else {
+        info!("Dry-run: the data-plane upgrade would not restart any io-engine Pods");
+    }
+
+    plan_data_plane(opts, opts.image_tag()).await?;
+    if opts.rest_endpoint_opt().is_some() {
+        println!();
+        list_nodes(opts, opts.image_tag(), OutputFormat::Table).await?;
+    }
+
+    info!("Dry-run complete, no changes were made to the cluster");
+    Ok(())
+}
+
+/// This is the outcome of the Preflight phase. It carries what the phases after it need to know.
+struct UpgradePlan {
+    /// This is true if the control-plane is already on the upgrade target version.
+    control_plane_is_upgraded: bool,
+    /// This is true if some of the io-engine Pods are yet to be upgraded.
+    data_plane_is_outdated: bool,
+    /// This is the number of Nodes whose io-engine Pods are yet to be upgraded.
+    data_plane_nodes_to_restart: usize,
+    /// This is the version of the Core helm chart being upgraded from.
+    from_version: String,
+    /// This is the revision of the helm release before the upgrade, if known.
+    from_revision: Option<u32>,
+    /// This is the version of the Core helm chart being upgraded to.
+    to_version: String,
+    /// This is the container image tag being upgraded to, if it is known.
+    to_image_tag: Option<String>,
+    /// This is the binding state of the PersistentVolumeClaims before the upgrade, if the binding
+    /// health is to be verified.
+    pvc_snapshot: Option<PvcBindingSnapshot>,
+    /// These are the storage Nodes left with the upgrade drain or cordon labels by a previous
+    /// upgrade attempt.
+    stale_cordoned_nodes: Vec<String>,
+}
+
 /// This carries out the helm upgrade validation, actual helm upgrade, and the io-engine Pod
-/// restarts.
-async fn upgrade_product(opts: &CliArgs, event: &mut EventRecorder) -> Result<()> {
+/// restarts, by driving the upgrade through its phases. This returns the report of the upgrade.
+async fn upgrade_product(opts: &CliArgs, event: &mut EventRecorder) -> Result<UpgradeReport> {
+    let mut phases = UpgradePhaseDriver::new();
+
+    let result = run_phases(opts, event, &mut phases).await;
+
+    if let Err(error) = result.as_ref() {
+        let validation_error = phases
+            .failed_phase()
+            .map_or(true, |phase| phase.eq(&UpgradePhase::Preflight));
+        event.publish_unrecoverable(error, validation_error).await;
+    }
+
+    for record in phases.records() {
+        info!(
+            phase = %record.phase(),
+            duration = ?record.duration(),
+            error = record.error().unwrap_or_default(),
+            "Upgrade phase summary"
+        );
+    }
+
+    result
+}
+
+/// This runs each of the upgrade phases, in order, and returns the report of the upgrade.
+async fn run_phases(
+    opts: &CliArgs,
+    event: &mut EventRecorder,
+    phases: &mut UpgradePhaseDriver,
+) -> Result<UpgradeReport> {
+    let started_at = Instant::now();
+
+    let (run_helm_upgrade, plan) = phases
+        .run(UpgradePhase::Preflight, preflight(opts, event))
+        .await?;
+
+    // Continue from the progress recorded by an interrupted run of this upgrade, if any. The
+    // recorded progress only skips work which the cluster also shows as done, so a helm release
+    // which is not on the target version, e.g. after a manual 'helm rollback', is upgraded again.
+    let checkpoint = UpgradeCheckpoint::load(opts.state_file(), plan.to_version.as_str());
+    if checkpoint.control_plane_is_upgraded() && !plan.control_plane_is_upgraded {
+        warn!(
+            version = %plan.to_version,
+            "The state file records the control-plane upgrade as complete, but the helm release \
+            is not on the target version, discarding the recorded progress"
+        );
+        checkpoint.clear();
+    }
+
+    let data_plane_plan = if opts.skip_data_plane_restart() {
+        "data-plane restart is skipped".to_string()
+    } else {
+        format!(
+            "data-plane restart is enabled, {} nodes to restart",
+            plan.data_plane_nodes_to_restart
+        )
+    };
+    event
+        .publish_progress(
+            format!(
+                "Planned upgrade of {} from {} to {}: {data_plane_plan}",
+                opts.release_name(),
+                plan.from_version,
+                plan.to_version
+            ),
+            EventAction::Planned,
+        )
+        .await;
+
+    if !plan.stale_cordoned_nodes.is_empty() && opts.auto_uncordon_stale() {
+        remove_stale_upgrade_cordons(opts, plan.stale_cordoned_nodes.as_slice()).await?;
+    }
+
+    event
+        .publish_progress(
+            format!("Starting {PRODUCT} upgrade..."),
+            EventAction::UpgradingCP,
+        )
+        .await;
+
+    // The helm upgrade updates the Pod template of the io-engine StatefulSets, if any. Their Pods
+    // are to be replaced one Node at a time by the data-plane upgrade, and not rolled by the
+    // StatefulSet controller while helm waits on the release.
+    if !plan.control_plane_is_upgraded && plan.data_plane_is_outdated {
+        set_io_engine_on_delete_update_strategy(opts).await?;
+    }
+
+    let mut report = phases
+        .run(
+            UpgradePhase::ControlPlane,
+            upgrade_control_plane(
+                run_helm_upgrade,
+                plan.control_plane_is_upgraded,
+                plan.to_version.as_str(),
+                event,
+            ),
+        )
+        .await?
+        .unwrap_or_else(|| {
+            UpgradeReport::new(
+                plan.from_version.clone(),
+                plan.to_version.clone(),
+                plan.to_image_tag.clone(),
+            )
+        });
+    checkpoint.record_control_plane_upgraded();
+
+    let data_plane_outcome = phases
+        .run(
+            UpgradePhase::DataPlane,
+            upgrade_data_plane_phase(opts, &plan, event, &checkpoint),
+        )
+        .await?;
+
+    for node_timing in data_plane_outcome.node_timings.iter() {
+        let [(_, rebuild_wait), (_, drain), (_, restart)] = node_timing.steps();
+        info!(
+            node.name = %node_timing.node(),
+            ?rebuild_wait,
+            ?drain,
+            ?restart,
+            "Data-plane node timing summary"
+        );
+    }
+
+    // The io-engine Pods on the skipped Nodes are expected to be left behind.
+    let skipped_nodes: Vec<String> = data_plane_outcome
+        .crashlooping_nodes
+        .iter()
+        .chain(data_plane_outcome.drain_timed_out_nodes.iter())
+        .chain(data_plane_outcome.up_to_date_nodes.iter())
+        .chain(data_plane_outcome.unselected_nodes.iter())
+        .chain(data_plane_outcome.maintenance_nodes.iter())
+        .cloned()
+        .collect();
+    phases
+        .run(
+            UpgradePhase::Verify,
+            verify(
+                opts,
+                event,
+                plan.to_version.as_str(),
+                plan.pvc_snapshot.as_ref(),
+                skipped_nodes.as_slice(),
+            ),
+        )
+        .await?;
+
+    phases.finish()?;
+
+    let restarted_nodes = data_plane_outcome.restarted_nodes;
+    let restarted_pods = restarted_nodes.len();
+    let restarted_across_nodes = restarted_nodes.iter().collect::<HashSet<_>>().len();
+    let duration = humantime::format_duration(Duration::from_secs(started_at.elapsed().as_secs()));
+    let mut summary = format!(
+        "Upgraded {} from {} to {}, restarted {restarted_pods} data-plane pods across \
+        {restarted_across_nodes} nodes in {duration}",
+        opts.release_name(),
+        plan.from_version,
+        plan.to_version
+    );
+    if !data_plane_outcome.crashlooping_nodes.is_empty() {
+        summary.push_str(
+            format!(
+                ", skipped nodes with io-engine pods in CrashLoopBackOff: {}",
+                data_plane_outcome.crashlooping_nodes.join(", ")
+            )
+            .as_str(),
+        );
+    }
+    if !data_plane_outcome.drain_timed_out_nodes.is_empty() {
+        summary.push_str(
+            format!(
+                ", skipped nodes whose drain timed out: {}",
+                data_plane_outcome.drain_timed_out_nodes.join(", ")
+            )
+            .as_str(),
+        );
+    }
+    if !data_plane_outcome.maintenance_nodes.is_empty() {
+        summary.push_str(
+            format!(
+                ", skipped nodes cordoned or drained for maintenance: {}",
+                data_plane_outcome.maintenance_nodes.join(", ")
+            )
+            .as_str(),
+        );
+    }
+    event
+        .publish_progress(summary, EventAction::Successful)
+        .await;
+
+    report.set_upgraded_nodes(restarted_nodes.as_slice());
+    report.set_duration(started_at.elapsed());
+    Ok(report)
+}
+
+/// This validates the upgrade, detects which of the control-plane and the data-plane are outdated,
+/// and runs the helm upgrade dry-run. This does not make any changes to the cluster. This returns
+/// the helm upgrade, which runs when awaited on, along with the plan for the rest of the upgrade.
+async fn preflight(
+    opts: &CliArgs,
+    event: &mut EventRecorder,
+) -> Result<(HelmUpgradeRunner, UpgradePlan)> {
     let helm_upgrade = HelmUpgrade::builder()
         .with_namespace(opts.namespace())
         .with_release_name(opts.release_name())
         .with_core_chart_dir(opts.core_chart_dir())
+        .with_image_tag(opts.image_tag())
         .with_skip_upgrade_path_validation(opts.skip_upgrade_path_validation())
+        .with_allow_unsupported_skew(opts.allow_unsupported_skew())
         .with_values(opts.values())
+        .with_values_mode(opts.values_mode())
+        .with_strict_semver(opts.strict_semver())
+        .with_redacted_value_keys(opts.redact_value_keys())
+        .with_set_values(opts.set_values())
         .build()
         .await?;
 
     let from_version = helm_upgrade.upgrade_from_version();
+    let from_revision = helm_upgrade.upgrade_from_revision();
     let to_version = helm_upgrade.upgrade_to_version();
+    let to_image_tag = helm_upgrade.upgrade_to_image_tag();
 
     // Updating the EventRecorder with version values from the HelmUpgrade.
     // These two operations are thread-safe. The EventRecorder itself is not
@@ -53,73 +481,478 @@ async fn upgrade_product(opts: &CliArgs, event: &mut EventRecorder) -> Result<()
     event.set_from_version(from_version.clone());
     event.set_to_version(to_version.clone());
 
-    // Dry-run helm upgrade.
-    let dry_run_result: Result<HelmUpgradeRunner> = helm_upgrade.dry_run().await;
-    let run_helm_upgrade = match dry_run_result {
-        Err(error) => {
-            event.publish_unrecoverable(&error, true).await;
-            Err(error)
-        }
-        Ok(run_helm_upgrade) => Ok(run_helm_upgrade),
-    }?;
+    // Verify this upgrade-job is of the target version.
+    if opts.verify_job_version() {
+        verify_job_version(to_version.as_str(), opts.strict())?;
+    }
 
-    event
-        .publish_normal(
-            format!("Starting {PRODUCT} upgrade..."),
-            EventAction::UpgradingCP,
+    // Per-phase outdatedness detection. Reruns after a control-plane-only success skip helm.
+    let control_plane_is_upgraded = helm_upgrade.control_plane_is_upgraded();
+    let k8s_client = KubeClientSet::builder()
+        .with_namespace(opts.namespace())
+        .build()
+        .await?;
+    let rest_client = opts
+        .rest_endpoint_opt()
+        .map(RestClientSet::new_with_url)
+        .transpose()?;
+    let data_plane_is_outdated = !opts.skip_data_plane_restart()
+        && data_plane_upgrade_is_required(
+            &k8s_client,
+            rest_client.as_ref(),
+            opts.namespace().as_str(),
+            &to_version,
         )
         .await?;
 
+    let data_plane_nodes_to_restart = if data_plane_is_outdated {
+        outdated_data_plane_nodes(
+            &k8s_client,
+            rest_client.as_ref(),
+            opts.namespace().as_str(),
+            &to_version,
+        )
+        .await?
+        .len()
+    } else {
+        0
+    };
+
+    // The diagnostics are read-only, so they may run alongside the helm upgrade dry-run. Both are
+    // joined here, before any changes are made to the cluster.
+    let diagnostics = preflight_diagnostics(opts, &k8s_client, control_plane_is_upgraded);
+    let (run_helm_upgrade, (pvc_snapshot, stale_cordoned_nodes)) = if opts.concurrent_preflight() {
+        tokio::try_join!(helm_upgrade.dry_run(), diagnostics)?
+    } else {
+        let diagnostics = diagnostics.await?;
+        (helm_upgrade.dry_run().await?, diagnostics)
+    };
+
+    Ok((
+        run_helm_upgrade,
+        UpgradePlan {
+            control_plane_is_upgraded,
+            data_plane_is_outdated,
+            data_plane_nodes_to_restart,
+            from_version,
+            from_revision,
+            to_version,
+            to_image_tag,
+            pvc_snapshot,
+            stale_cordoned_nodes,
+        },
+    ))
+}
+
+/// This runs the read-only preflight diagnostics. This returns the PersistentVolumeClaims' binding
+/// state, if it is to be verified, and the storage Nodes left cordoned by a previous upgrade
+/// attempt.
+async fn preflight_diagnostics(
+    opts: &CliArgs,
+    k8s_client: &KubeClientSet,
+    control_plane_is_upgraded: bool,
+) -> Result<(Option<PvcBindingSnapshot>, Vec<String>)> {
+    // Validate the target helm chart's CRDs against the custom resources in the cluster.
+    if !control_plane_is_upgraded {
+        if let Some(core_chart_dir) = opts.core_chart_dir() {
+            validate_crd_compatibility(core_chart_dir.as_path(), k8s_client, opts.strict()).await?;
+            if opts.dry_run_crds() {
+                dry_run_apply_crds(core_chart_dir.as_path(), k8s_client, opts.strict()).await?;
+            }
+        }
+    }
+
+    // Capture the PersistentVolumeClaims' binding state, to compare against after the upgrade.
+    let pvc_snapshot = if opts.verify_pvc_binding() {
+        Some(
+            pvc_binding_snapshot(
+                k8s_client,
+                opts.namespace().as_str(),
+                opts.pvc_binding_scope(),
+            )
+            .await?,
+        )
+    } else {
+        None
+    };
+
+    // Verify the control-plane has a single leader.
+    if opts.verify_control_plane_leader() {
+        let control_plane_namespace = opts.control_plane_namespace();
+        let control_plane_k8s_client = KubeClientSet::builder()
+            .with_namespace(control_plane_namespace.clone())
+            .build()
+            .await?;
+        verify_agent_core_leader(control_plane_namespace.as_str(), &control_plane_k8s_client)
+            .await?;
+    }
+
+    // Look for storage Nodes left drained or cordoned by a previous upgrade attempt. The REST API
+    // may not be available when the io-engine Pods are not restarted.
+    let stale_cordoned_nodes = match opts.rest_endpoint_opt() {
+        Some(rest_endpoint) => {
+            let rest_client = RestClientSet::new_with_url(rest_endpoint)?;
+            nodes_with_upgrade_cordons(&rest_client).await?
+        }
+        None => Vec::new(),
+    };
+    if !stale_cordoned_nodes.is_empty() && !opts.auto_uncordon_stale() {
+        warn!(
+            nodes = ?stale_cordoned_nodes,
+            "Found {PRODUCT} Nodes drained or cordoned by a previous upgrade attempt, these are \
+            unschedulable until uncordoned; set '--auto-uncordon-stale' to uncordon them \
+            before the upgrade"
+        );
+    }
+
+    Ok((pvc_snapshot, stale_cordoned_nodes))
+}
+
+/// Removes the upgrade drain and cordon labels which a previous upgrade attempt left behind on the
+/// storage Nodes.
+async fn remove_stale_upgrade_cordons(opts: &CliArgs, node_ids: &[String]) -> Result<()> {
+    let rest_client = RestClientSet::new_with_url(opts.rest_endpoint()?)?;
+    for node_id in node_ids {
+        remove_upgrade_cordons(node_id.as_str(), &rest_client).await?;
+        info!(
+            node.id = %node_id,
+            "Uncordoned {PRODUCT} Node left cordoned by a previous upgrade attempt"
+        );
+    }
+    Ok(())
+}
+
+/// This runs the helm upgrade, unless the control-plane is already upgraded. This returns the
+/// report of the helm upgrade, if it was run.
+async fn upgrade_control_plane(
+    run_helm_upgrade: HelmUpgradeRunner,
+    control_plane_is_upgraded: bool,
+    to_version: &str,
+    event: &EventRecorder,
+) -> Result<Option<UpgradeReport>> {
+    if control_plane_is_upgraded {
+        info!(
+            version = %to_version,
+            "Skipping control-plane upgrade: control-plane is already upgraded"
+        );
+        return Ok(None);
+    }
+
     event
-        .publish_normal(
+        .publish_progress(
             format!("Upgrading {PRODUCT} control-plane"),
             EventAction::UpgradingCP,
         )
-        .await?;
+        .await;
 
     // Control plane containers are updated in this step.
-    if let Err(error) = run_helm_upgrade.await {
-        event.publish_unrecoverable(&error, false).await;
-        return Err(error);
-    }
+    let report = run_helm_upgrade.await?;
 
     event
-        .publish_normal(
+        .publish_progress(
             format!("Upgraded {PRODUCT} control-plane"),
             EventAction::UpgradedCP,
         )
+        .await;
+
+    Ok(Some(report))
+}
+
+/// This restarts the io-engine Pods, unless they are already upgraded or the restart is skipped.
+/// If the helm upgrade was run, the control-plane is given time to settle first. The settle and
+/// the restarts are aborted if they run past the upgrade timeout. This returns the Nodes whose
+/// io-engine Pods were restarted, and those which were skipped.
+async fn upgrade_data_plane_phase(
+    opts: &CliArgs,
+    plan: &UpgradePlan,
+    event: &EventRecorder,
+    checkpoint: &UpgradeCheckpoint,
+) -> Result<DataPlaneUpgradeOutcome> {
+    if !plan.data_plane_is_outdated {
+        if !opts.skip_data_plane_restart() {
+            info!("Skipping data-plane upgrade: All data-plane Pods are already upgraded");
+        }
+        return Ok(DataPlaneUpgradeOutcome::default());
+    }
+
+    event
+        .publish_progress(
+            format!("Upgrading {PRODUCT} data-plane"),
+            EventAction::UpgradingDP,
+        )
+        .await;
+
+    let helm_upgrade_was_run = !plan.control_plane_is_upgraded;
+    let tracker = DataPlaneUpgradeTracker::default();
+    let data_plane_upgrade = async {
+        if helm_upgrade_was_run && !opts.post_helm_settle().is_zero() {
+            settle_after_helm_upgrade(opts, plan.to_version.as_str()).await?;
+        }
+
+        // Data plane containers are updated in this step.
+        upgrade_data_plane(
+            opts,
+            plan.to_version.clone(),
+            plan.from_version.clone(),
+            plan.to_image_tag.clone(),
+            &tracker,
+            event,
+            checkpoint,
+        )
+        .await
+    };
+    let result = match opts.timeout() {
+        Some(timeout) => match tokio::time::timeout(timeout, data_plane_upgrade).await {
+            Ok(result) => result,
+            Err(_) => Err(data_plane_upgrade_timed_out(opts, timeout, &tracker, event).await),
+        },
+        None => data_plane_upgrade.await,
+    };
+
+    let outcome = match result {
+        Ok(outcome) => outcome,
+        Err(error)
+            if helm_upgrade_was_run
+                && opts.rollback_on_failure()
+                && !error.is_control_plane_rolled_back()
+                // An interrupted upgrade is left to be continued, e.g. from the state file.
+                && !error.is_interrupted() =>
+        {
+            rollback_control_plane(
+                opts,
+                plan.from_version.clone(),
+                plan.from_revision,
+                &error,
+                event,
+            )
+            .await?;
+            // The io-engine Pods which are already upgraded are restarted back onto the rolled
+            // back release, so that they do not stay ahead of the control-plane.
+            if !opts.skip_data_plane_restart() {
+                rollback_upgraded_data_plane(
+                    opts,
+                    tracker.upgraded_nodes().as_slice(),
+                    &plan.from_version,
+                    event,
+                    checkpoint,
+                )
+                .await?;
+                restore_update_strategy(opts, plan.from_version.as_str()).await;
+            }
+            checkpoint.clear();
+            return Err(error);
+        }
+        Err(error) => {
+            // The recorded progress no longer holds once the helm release is rolled back.
+            if error.is_control_plane_rolled_back() {
+                checkpoint.clear();
+            }
+            return Err(error);
+        }
+    };
+
+    restore_update_strategy(opts, plan.to_version.as_str()).await;
+
+    event
+        .publish_progress(
+            format!("Upgraded {PRODUCT} data-plane"),
+            EventAction::UpgradedDP,
+        )
+        .await;
+
+    Ok(outcome)
+}
+
+/// This restores the update strategy of the io-engine StatefulSets, if any, once their Pods are on
+/// the version. A failure is logged, the StatefulSets are then left with the 'OnDelete' strategy,
+/// which keeps their Pods from being restarted, until the strategy is restored by a later upgrade.
+async fn restore_update_strategy(opts: &CliArgs, version: &str) {
+    if let Err(error) = restore_io_engine_update_strategy(opts, version).await {
+        warn!(%error, "Failed to restore the update strategy of the io-engine StatefulSets");
+    }
+}
+
+/// This reports the Nodes whose upgrade was cut short by the upgrade timeout, and the Nodes which
+/// were already upgraded, and returns the timeout error. The Nodes whose upgrade was cut short,
+/// and those cordoned up front with the batch cordon strategy, are uncordoned first, as the
+/// upgrade of the Nodes stops without uncordoning them. A failure to uncordon a Node or to
+/// publish the event is logged, and does not replace the timeout error.
+async fn data_plane_upgrade_timed_out(
+    opts: &CliArgs,
+    timeout: Duration,
+    tracker: &DataPlaneUpgradeTracker,
+    event: &EventRecorder,
+) -> Error {
+    let upgrading_nodes = tracker.upgrading_nodes();
+    let upgraded_nodes = tracker.upgraded_nodes();
+    warn!(
+        ?timeout,
+        upgrading_nodes = ?upgrading_nodes,
+        upgraded_nodes = ?upgraded_nodes,
+        "Timed out upgrading the data-plane"
+    );
+
+    let cordoned_nodes = tracker.cordoned_nodes();
+    if !cordoned_nodes.is_empty() {
+        match opts.rest_endpoint().and_then(RestClientSet::new_with_url) {
+            Ok(rest_client) => {
+                for node_name in cordoned_nodes.iter() {
+                    match remove_upgrade_cordons(node_name.as_str(), &rest_client).await {
+                        Ok(()) => info!(
+                            node.name = %node_name,
+                            "Uncordoned the Node after the upgrade timed out"
+                        ),
+                        Err(error) => error!(
+                            node.name = %node_name,
+                            %error,
+                            "Failed to uncordon the Node after the upgrade timed out"
+                        ),
+                    }
+                }
+            }
+            Err(error) => error!(
+                nodes = ?cordoned_nodes,
+                %error,
+                "Failed to uncordon the Nodes after the upgrade timed out"
+            ),
+        }
+    }
+
+    if let Err(error) = event
+        .publish_warning(
+            format!(
+                "Timed out after {} upgrading the {} data-plane, while upgrading the Nodes \
+                {upgrading_nodes:?}, the Nodes {upgraded_nodes:?} are already upgraded",
+                humantime::format_duration(timeout),
+                opts.release_name()
+            ),
+            EventAction::TimedOut,
+        )
+        .await
+    {
+        error!(%error, "Failed to publish the upgrade timeout event");
+    }
+
+    UpgradeTimeout {
+        timeout,
+        upgrading_nodes,
+        upgraded_nodes,
+    }
+    .build()
+}
+
+/// This rolls the helm release back to the revision it was on before the upgrade, after the
+/// data-plane upgrade failed with the 'error'.
+async fn rollback_control_plane(
+    opts: &CliArgs,
+    from_version: String,
+    from_revision: Option<u32>,
+    error: &Error,
+    event: &EventRecorder,
+) -> Result<()> {
+    warn!(
+        %error,
+        revision = ?from_revision,
+        "Rolling back the helm release, the data-plane upgrade failed"
+    );
+    HelmReleaseClient::builder()
+        .with_namespace(opts.namespace())
+        .with_redacted_value_keys(opts.redact_value_keys())
+        .build()?
+        .rollback(opts.release_name(), from_revision)
+        .await?;
+
+    let revision = from_revision
+        .map(|revision| format!("revision {revision}"))
+        .unwrap_or_else(|| "the previous revision".to_string());
+    event
+        .publish_warning(
+            format!(
+                "Rolled back {} to {revision} of version {from_version}, after the data-plane \
+                upgrade failed: {error}",
+                opts.release_name()
+            ),
+            EventAction::RolledBackCP,
+        )
+        .await
+}
+
+/// This verifies that the upgrade has left no io-engine Pods behind, other than those on the
+/// Nodes skipped for being in CrashLoopBackOff or for their drain timing out, that the volumes and
+/// the io-engine Pods are healthy, and that the PersistentVolumeClaims have stayed Bound, if those
+/// are to be verified.
+async fn verify(
+    opts: &CliArgs,
+    event: &EventRecorder,
+    to_version: &str,
+    pvc_snapshot: Option<&PvcBindingSnapshot>,
+    skipped_nodes: &[String],
+) -> Result<()> {
+    let k8s_client = KubeClientSet::builder()
+        .with_namespace(opts.namespace())
+        .build()
         .await?;
 
-    // Data plane containers are updated in this step.
     if !opts.skip_data_plane_restart() {
-        event
-            .publish_normal(
-                format!("Upgrading {PRODUCT} data-plane"),
-                EventAction::UpgradingDP,
+        let rest_client = RestClientSet::new_with_url(opts.rest_endpoint()?)?;
+        let data_plane_is_upgraded = if skipped_nodes.is_empty() {
+            !data_plane_upgrade_is_required(
+                &k8s_client,
+                Some(&rest_client),
+                opts.namespace().as_str(),
+                to_version,
             )
-            .await?;
+            .await?
+        } else {
+            outdated_data_plane_nodes(
+                &k8s_client,
+                Some(&rest_client),
+                opts.namespace().as_str(),
+                to_version,
+            )
+            .await?
+            .iter()
+            .all(|node| skipped_nodes.contains(node))
+        };
+        ensure!(
+            data_plane_is_upgraded,
+            DataPlaneUpgradeIncomplete { to_version }
+        );
+    }
 
-        if let Err(error) =
-            upgrade_data_plane(opts.namespace(), opts.rest_endpoint(), to_version).await
+    // The volumes' health is read from the REST API, which may be left out if the io-engine Pods
+    // are not restarted.
+    if let Some(rest_endpoint) = opts.rest_endpoint_opt().filter(|_| opts.verify_health()) {
+        let rest_client = RestClientSet::new_with_url(rest_endpoint)?;
+        if let Err(error) = verify_cluster_health(
+            &k8s_client,
+            &rest_client,
+            opts.namespace().as_str(),
+            opts.volume_list_parallelism(),
+            skipped_nodes,
+        )
+        .await
         {
-            event.publish_unrecoverable(&error, false).await;
+            if let Err(event_error) = event
+                .publish_warning(error.to_string(), EventAction::UnhealthyAfterUpgrade)
+                .await
+            {
+                warn!(error = %event_error, "Failed to publish the post-upgrade health Event");
+            }
             return Err(error);
         }
-
-        event
-            .publish_normal(
-                format!("Upgraded {PRODUCT} data-plane"),
-                EventAction::UpgradedDP,
-            )
-            .await?;
     }
 
-    event
-        .publish_normal(
-            format!("Successfully upgraded {PRODUCT}"),
-            EventAction::Successful,
+    if let Some(pvc_snapshot) = pvc_snapshot {
+        verify_pvc_binding(
+            &k8s_client,
+            opts.namespace().as_str(),
+            opts.pvc_binding_scope(),
+            pvc_snapshot,
+            opts.strict(),
         )
         .await?;
+    }
 
     Ok(())
 }
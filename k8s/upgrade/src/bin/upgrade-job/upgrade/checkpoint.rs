@@ -0,0 +1,167 @@
+use crate::common::error::{
+    ReadingFile, Result, SerializeCheckpoint, WritingFile, YamlParseFromFile,
+};
+use serde::{Deserialize, Serialize};
+use snafu::ResultExt;
+use std::{
+    fs,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+    sync::{Mutex, MutexGuard, PoisonError},
+};
+use tracing::{info, warn};
+
+/// This is the progress of an upgrade, as it is written to the state file.
+#[derive(Debug, Default, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+struct CheckpointState {
+    /// This is the version of the Core helm chart being upgraded to.
+    to_version: String,
+    /// This is true if the helm upgrade has completed.
+    control_plane_upgraded: bool,
+    /// These are the names of the Nodes whose io-engine Pods have been upgraded.
+    upgraded_nodes: Vec<String>,
+}
+
+/// This records the progress of the upgrade to the state file, so that an upgrade which is
+/// interrupted, e.g. by the eviction of the upgrade Job's Pod, continues from where it left off
+/// when it is run again. Nothing is recorded if there is no state file.
+pub(crate) struct UpgradeCheckpoint {
+    path: Option<PathBuf>,
+    state: Mutex<CheckpointState>,
+}
+
+impl UpgradeCheckpoint {
+    /// This reads the progress of the upgrade to 'to_version' from the state file. The progress is
+    /// started afresh if there is no state file yet, if it is corrupt, or if it records an upgrade
+    /// to a different version.
+    pub(crate) fn load(path: Option<PathBuf>, to_version: &str) -> Self {
+        let fresh_state = || CheckpointState {
+            to_version: to_version.to_string(),
+            ..Default::default()
+        };
+
+        let state = match path.as_deref() {
+            Some(path) => match read_state(path) {
+                Ok(Some(state)) if state.to_version.eq(to_version) => {
+                    info!(
+                        path = %path.display(),
+                        control_plane_upgraded = state.control_plane_upgraded,
+                        upgraded_nodes = ?state.upgraded_nodes,
+                        "Continuing the upgrade from the state file"
+                    );
+                    state
+                }
+                Ok(Some(state)) => {
+                    warn!(
+                        path = %path.display(),
+                        recorded_version = %state.to_version,
+                        version = %to_version,
+                        "The state file records an upgrade to another version, running the full \
+                        upgrade"
+                    );
+                    fresh_state()
+                }
+                Ok(None) => fresh_state(),
+                Err(error) => {
+                    warn!(
+                        %error,
+                        "Failed to read the state file, running the full upgrade"
+                    );
+                    fresh_state()
+                }
+            },
+            None => fresh_state(),
+        };
+
+        Self {
+            path,
+            state: Mutex::new(state),
+        }
+    }
+
+    /// This is a predicate which is true if a previous run recorded the helm upgrade as complete.
+    pub(crate) fn control_plane_is_upgraded(&self) -> bool {
+        self.lock().control_plane_upgraded
+    }
+
+    /// This is a predicate which is true if a previous run recorded the io-engine Pod of the Node
+    /// as upgraded.
+    pub(crate) fn node_is_upgraded(&self, node_name: &str) -> bool {
+        self.lock()
+            .upgraded_nodes
+            .iter()
+            .any(|upgraded_node| upgraded_node.eq(node_name))
+    }
+
+    /// This records the helm upgrade as complete.
+    pub(crate) fn record_control_plane_upgraded(&self) {
+        let mut state = self.lock();
+        state.control_plane_upgraded = true;
+        self.save(&state);
+    }
+
+    /// This records the io-engine Pod of the Node as upgraded.
+    pub(crate) fn record_node_upgraded(&self, node_name: &str) {
+        let mut state = self.lock();
+        if !state.upgraded_nodes.iter().any(|node| node.eq(node_name)) {
+            state.upgraded_nodes.push(node_name.to_string());
+        }
+        self.save(&state);
+    }
+
+    /// This forgets the recorded progress, e.g. after the helm release is rolled back.
+    pub(crate) fn clear(&self) {
+        let mut state = self.lock();
+        state.control_plane_upgraded = false;
+        state.upgraded_nodes.clear();
+        self.save(&state);
+    }
+
+    fn lock(&self) -> MutexGuard<'_, CheckpointState> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Writes the state to the state file. Failing to write the state file does not fail the
+    /// upgrade, it is only logged.
+    fn save(&self, state: &CheckpointState) {
+        if let Some(path) = self.path.as_deref() {
+            if let Err(error) = write_state(path, state) {
+                warn!(%error, "Failed to write the upgrade progress to the state file");
+            }
+        }
+    }
+}
+
+/// Reads the state file, if it exists.
+fn read_state(path: &Path) -> Result<Option<CheckpointState>> {
+    let state_yaml = match fs::read(path) {
+        Ok(state_yaml) => state_yaml,
+        Err(error) if error.kind() == ErrorKind::NotFound => return Ok(None),
+        Err(error) => {
+            return Err(error).context(ReadingFile {
+                filepath: path.to_path_buf(),
+            })
+        }
+    };
+    let state = serde_yaml::from_slice(state_yaml.as_slice()).context(YamlParseFromFile {
+        filepath: path.to_path_buf(),
+    })?;
+    Ok(Some(state))
+}
+
+/// Writes the state file. The state is written to a temporary file next to the state file first,
+/// which is then renamed over the state file, so that the state file is never partially written.
+fn write_state(path: &Path, state: &CheckpointState) -> Result<()> {
+    let state_yaml = serde_yaml::to_string(state).context(SerializeCheckpoint)?;
+    let mut temp_path = path.as_os_str().to_owned();
+    temp_path.push(".tmp");
+    let temp_path = PathBuf::from(temp_path);
+
+    fs::write(temp_path.as_path(), state_yaml).context(WritingFile {
+        filepath: temp_path.clone(),
+    })?;
+    fs::rename(temp_path.as_path(), path).context(WritingFile {
+        filepath: path.to_path_buf(),
+    })
+}
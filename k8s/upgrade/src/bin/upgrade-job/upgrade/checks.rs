@@ -0,0 +1,237 @@
+use crate::{
+    common::{
+        error::{PreflightCheckUnmet, PreflightChecksFailed, RestEndpointAbsent, Result},
+        kube_client::KubeClientSet,
+        rest_client::RestClientSet,
+    },
+    opts::{
+        validators::{
+            validate_helm_binary, validate_helm_chart_dir, validate_helm_release,
+            validate_helmv3_in_path, validate_namespace, validate_rbac, validate_rest_endpoint,
+        },
+        CliArgs,
+    },
+    upgrade::{
+        data_plane::{control_plane_unready_pods, draining_storage_nodes},
+        utils::{list_unhealthy_volumes, rebuild_ignored_volumes, replica_rebuild_count},
+    },
+};
+use std::collections::HashSet;
+
+/// This is the outcome of one preflight check.
+struct CheckOutcome {
+    name: &'static str,
+    passed: bool,
+    details: String,
+}
+
+impl CheckOutcome {
+    /// The check passes if the 'result' is Ok, with its details. It fails with the error otherwise.
+    fn new(name: &'static str, result: Result<String>) -> Self {
+        match result {
+            Ok(details) => Self {
+                name,
+                passed: true,
+                details,
+            },
+            Err(error) => Self {
+                name,
+                passed: false,
+                details: error.to_string(),
+            },
+        }
+    }
+}
+
+/// This runs the validations of the upgrade's inputs, and checks that the cluster is in a state
+/// which the upgrade may start from, and prints a report with the outcome of each check. This
+/// does not make any changes to the cluster, i.e. no storage Node is drained and no helm upgrade
+/// is run. This fails if any of the checks fails.
+pub(crate) async fn run_preflight_checks(opts: &mut CliArgs) -> Result<()> {
+    let mut outcomes: Vec<CheckOutcome> = Vec::new();
+
+    outcomes.push(CheckOutcome::new(
+        "helm binary",
+        validate_helm_binary(opts.helm_binary().as_str())
+            .and_then(|()| validate_helmv3_in_path())
+            .map(|()| format!("'{}' is helm v3", opts.helm_binary())),
+    ));
+
+    let namespace_result = match validate_namespace(opts.namespace()).await {
+        Ok(()) if opts.control_plane_namespace().ne(&opts.namespace()) => {
+            validate_namespace(opts.control_plane_namespace()).await
+        }
+        result => result,
+    };
+    outcomes.push(CheckOutcome::new(
+        "namespaces",
+        namespace_result.map(|()| "the namespaces exist".to_string()),
+    ));
+
+    outcomes.push(CheckOutcome::new(
+        "permissions",
+        validate_rbac(opts)
+            .await
+            .map(|()| "the required RBAC permissions are granted".to_string()),
+    ));
+
+    outcomes.push(CheckOutcome::new(
+        "helm release",
+        validate_helm_release(opts.release_name(), opts.namespace())
+            .map(|()| format!("found release '{}'", opts.release_name())),
+    ));
+
+    let chart_result = if opts.image_tag().is_some() && opts.core_chart_dir().is_none() {
+        Ok("not required, '--image-tag' is set".to_string())
+    } else {
+        opts.pull_core_chart()
+            .and_then(|()| validate_helm_chart_dir(opts.core_chart_dir()))
+            .map(|()| "found the core helm chart".to_string())
+    };
+    outcomes.push(CheckOutcome::new("helm chart", chart_result));
+
+    let rest_client = match opts.rest_endpoint_opt() {
+        Some(rest_endpoint) => {
+            let result = validate_rest_endpoint(rest_endpoint.clone(), opts.namespace())
+                .await
+                .and_then(|()| RestClientSet::new_with_url(rest_endpoint));
+            let (client, result) = match result {
+                Ok(client) => (Some(client), Ok("reachable".to_string())),
+                Err(error) => (None, Err(error)),
+            };
+            outcomes.push(CheckOutcome::new("storage REST API", result));
+            client
+        }
+        None if opts.skip_data_plane_restart() => {
+            outcomes.push(CheckOutcome::new(
+                "storage REST API",
+                Ok("not used, the data-plane restart is skipped".to_string()),
+            ));
+            None
+        }
+        None => {
+            outcomes.push(CheckOutcome::new(
+                "storage REST API",
+                RestEndpointAbsent.fail(),
+            ));
+            None
+        }
+    };
+
+    outcomes.push(CheckOutcome::new(
+        "control-plane pods",
+        control_plane_pods_check(opts).await,
+    ));
+
+    if let Some(rest_client) = rest_client.as_ref() {
+        outcomes.push(CheckOutcome::new(
+            "volume rebuilds",
+            volume_rebuilds_check(opts, rest_client).await,
+        ));
+        outcomes.push(CheckOutcome::new(
+            "storage node drains",
+            storage_node_drains_check(rest_client).await,
+        ));
+    }
+
+    print_report(outcomes.as_slice());
+
+    let failed_checks: Vec<String> = outcomes
+        .iter()
+        .filter(|outcome| !outcome.passed)
+        .map(|outcome| outcome.name.to_string())
+        .collect();
+    if !failed_checks.is_empty() {
+        return PreflightChecksFailed {
+            checks: failed_checks,
+        }
+        .fail();
+    }
+    Ok(())
+}
+
+/// Checks that all of the Pods of the control-plane components are Ready.
+async fn control_plane_pods_check(opts: &CliArgs) -> Result<String> {
+    let control_plane_namespace = opts.control_plane_namespace();
+    let k8s_client = KubeClientSet::builder()
+        .with_namespace(control_plane_namespace.as_str())
+        .build()
+        .await?;
+    let unready_pods = control_plane_unready_pods(
+        control_plane_namespace.as_str(),
+        &k8s_client,
+        opts.control_plane_components().as_slice(),
+    )
+    .await?;
+
+    if unready_pods.is_empty() {
+        return Ok("all control-plane pods are ready".to_string());
+    }
+    fail_with_reason(format!(
+        "control-plane pods are not ready: {}",
+        unready_pods.join(", ")
+    ))
+}
+
+/// Checks that none of the volumes are rebuilding, other than those whose rebuilds are not waited
+/// on as per the rebuild wait policy. Unlike the upgrade, this does not wait for rebuilds to start.
+async fn volume_rebuilds_check(opts: &CliArgs, rest_client: &RestClientSet) -> Result<String> {
+    let ignored_volumes = match opts.rebuild_wait_policy_file() {
+        Some(policy_file) => rebuild_ignored_volumes(policy_file.as_path())?,
+        None => HashSet::new(),
+    };
+
+    let mut rebuilding_volumes: Vec<String> = Vec::new();
+    for volume in list_unhealthy_volumes(rest_client, &[], opts.volume_list_parallelism()).await? {
+        let uuid = volume.spec.uuid.to_string();
+        if !ignored_volumes.contains(&uuid) && replica_rebuild_count(volume).await > 0 {
+            rebuilding_volumes.push(uuid);
+        }
+    }
+
+    if rebuilding_volumes.is_empty() {
+        return Ok("no volumes are rebuilding".to_string());
+    }
+    fail_with_reason(format!(
+        "volumes are rebuilding: {}",
+        rebuilding_volumes.join(", ")
+    ))
+}
+
+/// Checks that none of the storage Nodes are being drained.
+async fn storage_node_drains_check(rest_client: &RestClientSet) -> Result<String> {
+    let draining_nodes = draining_storage_nodes(rest_client).await?;
+
+    if draining_nodes.is_empty() {
+        return Ok("no storage nodes are draining".to_string());
+    }
+    fail_with_reason(format!(
+        "storage nodes are draining: {}",
+        draining_nodes.join(", ")
+    ))
+}
+
+/// Fails a check with the reason, where the check ran without errors but its condition is not met.
+fn fail_with_reason(reason: String) -> Result<String> {
+    PreflightCheckUnmet { reason }.fail()
+}
+
+/// Prints a table with the outcome of each of the checks.
+fn print_report(outcomes: &[CheckOutcome]) {
+    let width = outcomes
+        .iter()
+        .map(|outcome| outcome.name.len())
+        .max()
+        .unwrap_or_default()
+        .max("CHECK".len());
+
+    println!("{:<width$}  {:<6}  DETAILS", "CHECK", "RESULT");
+    for outcome in outcomes {
+        println!(
+            "{:<width$}  {:<6}  {}",
+            outcome.name,
+            if outcome.passed { "pass" } else { "FAIL" },
+            outcome.details
+        );
+    }
+}
@@ -0,0 +1,188 @@
+use crate::{
+    common::{
+        error::{BreakingCrdChanges, DryRunApplyCrd, GetCrd, Result, SerializeCrd},
+        kube_client::KubeClientSet,
+    },
+    helm::client::crds_from_dir,
+};
+use k8s_openapi::apiextensions_apiserver::pkg::apis::apiextensions::v1::CustomResourceDefinitionSpec as CrdSpec;
+use kube::{
+    api::{Patch, PatchParams},
+    ResourceExt,
+};
+use serde_json::Value;
+use snafu::ResultExt;
+use std::{
+    collections::BTreeSet,
+    fmt::{Display, Formatter},
+    path::Path,
+};
+use tracing::{info, warn};
+
+/// This is the field manager which the dry-run server-side applies are made as.
+const FIELD_MANAGER: &str = "upgrade-job";
+
+/// This is the kind of change which applying a CRD from the helm chart would make.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum CrdChange {
+    /// The CRD is not in the cluster, and would be created.
+    Create,
+    /// The CRD would be unchanged.
+    NoOp,
+    /// The CRD would only gain versions or schema fields.
+    Additive,
+    /// The CRD would lose versions or schema fields, or change its identity.
+    Breaking,
+}
+
+impl Display for CrdChange {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let change = match self {
+            Self::Create => "create",
+            Self::NoOp => "no-op",
+            Self::Additive => "additive",
+            Self::Breaking => "breaking",
+        };
+        write!(f, "{change}")
+    }
+}
+
+/// Runs a dry-run server-side apply of each of the CRDs in the helm chart's 'crds' directory,
+/// and reports the kind of change each of them would make. Helm does not upgrade CRDs, so these
+/// are the changes which a manual apply of the chart's CRDs would make. Breaking changes fail the
+/// upgrade if 'strict' is set, and are logged as warnings otherwise.
+pub(crate) async fn dry_run_apply_crds(
+    chart_dir: &Path,
+    k8s_client: &KubeClientSet,
+    strict: bool,
+) -> Result<()> {
+    let mut breaking_changes: Vec<String> = Vec::new();
+
+    for crd in crds_from_dir(chart_dir.join("crds"))? {
+        let name = crd.name_any();
+        let Some(existing_crd) = k8s_client
+            .crd_api()
+            .get_opt(name.as_str())
+            .await
+            .context(GetCrd { name: name.clone() })?
+        else {
+            info!(crd.name = %name, change = %CrdChange::Create, "CRD dry-run apply");
+            continue;
+        };
+
+        let applied_crd = k8s_client
+            .crd_api()
+            .patch(
+                name.as_str(),
+                &PatchParams::apply(FIELD_MANAGER).dry_run().force(),
+                &Patch::Apply(&crd),
+            )
+            .await
+            .context(DryRunApplyCrd { name: name.clone() })?;
+
+        let (change, reasons) = crd_change(&existing_crd.spec, &applied_crd.spec, name.as_str())?;
+        match change {
+            CrdChange::Breaking => {
+                warn!(crd.name = %name, %change, ?reasons, "CRD dry-run apply");
+                breaking_changes.push(format!("{name}: {}", reasons.join(", ")));
+            }
+            _ => info!(crd.name = %name, %change, "CRD dry-run apply"),
+        }
+    }
+
+    if strict && !breaking_changes.is_empty() {
+        return BreakingCrdChanges {
+            changes: breaking_changes,
+        }
+        .fail();
+    }
+
+    Ok(())
+}
+
+/// Classifies the change from the 'existing' CRD spec to the 'applied' CRD spec. The reasons for
+/// a breaking change are returned along with it.
+fn crd_change(
+    existing: &CrdSpec,
+    applied: &CrdSpec,
+    name: &str,
+) -> Result<(CrdChange, Vec<String>)> {
+    let existing_value = serde_json::to_value(existing).context(SerializeCrd { name })?;
+    let applied_value = serde_json::to_value(applied).context(SerializeCrd { name })?;
+    if existing_value.eq(&applied_value) {
+        return Ok((CrdChange::NoOp, Vec::new()));
+    }
+
+    let mut reasons: Vec<String> = Vec::new();
+    if existing.group.ne(&applied.group)
+        || existing.scope.ne(&applied.scope)
+        || existing.names.kind.ne(&applied.names.kind)
+    {
+        reasons.push("group, scope or kind changed".to_string());
+    }
+
+    for existing_version in existing.versions.iter() {
+        let Some(applied_version) = applied
+            .versions
+            .iter()
+            .find(|version| version.name.eq(&existing_version.name))
+        else {
+            reasons.push(format!("version {} removed", existing_version.name));
+            continue;
+        };
+
+        if existing_version.served && !applied_version.served {
+            reasons.push(format!(
+                "version {} no longer served",
+                existing_version.name
+            ));
+        }
+
+        let existing_fields = schema_fields(&existing_value, existing_version.name.as_str());
+        let applied_fields = schema_fields(&applied_value, existing_version.name.as_str());
+        for field in existing_fields.difference(&applied_fields) {
+            reasons.push(format!(
+                "field {field} removed from version {}",
+                existing_version.name
+            ));
+        }
+    }
+
+    if reasons.is_empty() {
+        Ok((CrdChange::Additive, reasons))
+    } else {
+        Ok((CrdChange::Breaking, reasons))
+    }
+}
+
+/// Returns the paths of all of the fields in the OpenAPI v3 schema of a version of the serialized
+/// CRD spec.
+fn schema_fields(spec: &Value, version: &str) -> BTreeSet<String> {
+    let mut fields = BTreeSet::new();
+    let schema = spec["versions"]
+        .as_array()
+        .and_then(|versions| {
+            versions
+                .iter()
+                .find(|v| v["name"].as_str().map_or(false, |name| name.eq(version)))
+        })
+        .map(|v| &v["schema"]["openAPIV3Schema"]);
+    if let Some(schema) = schema {
+        collect_fields(schema, "", &mut fields);
+    }
+    fields
+}
+
+/// Walks the schema, collecting the paths of the fields under 'properties' and array 'items'.
+fn collect_fields(schema: &Value, prefix: &str, fields: &mut BTreeSet<String>) {
+    if let Some(properties) = schema["properties"].as_object() {
+        for (key, property) in properties {
+            let path = format!("{prefix}.{key}");
+            collect_fields(property, path.as_str(), fields);
+            fields.insert(path);
+        }
+    }
+    if schema["items"].is_object() {
+        collect_fields(&schema["items"], format!("{prefix}[]").as_str(), fields);
+    }
+}
@@ -1,55 +1,286 @@
 use crate::{
     common::{
         constants::{
-            AGENT_CORE_LABEL, CHART_VERSION_LABEL_KEY, DRAIN_FOR_UPGRADE, IO_ENGINE_LABEL, PRODUCT,
+            AGENT_CORE_LABEL, CHART_VERSION_LABEL_KEY, CORDON_FOR_UPGRADE, DRAIN_FOR_UPGRADE,
+            IO_ENGINE_CONTAINER_NAME, IO_ENGINE_LABEL, PRODUCT, UPDATE_STRATEGY_ANNOTATION_KEY,
         },
         error::{
-            DrainStorageNode, EmptyPodNodeName, EmptyPodSpec, EmptyStorageNodeSpec, GetStorageNode,
-            ListPodsWithLabel, ListPodsWithLabelAndField, ListStorageNodes, PodDelete, Result,
-            StorageNodeUncordon, TooManyIoEnginePods,
+            CanaryResumeSignal, ControlPlaneReadyTimeout, ControlPlaneRolledBack,
+            DataPlanePodReadyTimeout, DrainStorageNode, DrainTimedOut, EmptyContainerImage,
+            EmptyPodNodeName, EmptyPodSpec, EmptyStorageNodeSpec, Error, GetConfigMap,
+            GetDaemonSet, GetKubernetesNode, GetPod, GetStorageNode, InsufficientHugepages,
+            InsufficientSpareCapacity, IoEnginePodCrashLoopBackOff, ListKubernetesNodesWithLabel,
+            ListPodDisruptionBudgets, ListPodsWithLabel, ListPodsWithLabelAndField,
+            ListStatefulSets, ListStorageNodes, ListStoragePools, ListStorageReplicas,
+            MinHealthyReplicas, MultipleAgentCoreLeaders, NoAgentCoreLeader, NodeVersionMismatch,
+            ParseUpdateStrategy, PatchConfigMap, PatchStatefulSetUpdateStrategy, PdbWaitTimeout,
+            PodDelete, PodRescheduledUnexpectedly, RebuildStalled, ReplacementPodUnschedulable,
+            RestoreStatefulSetUpdateStrategy, Result, SerializeUpdateStrategy, StorageNodeCordon,
+            StorageNodeInMaintenance, StorageNodeNotSchedulable, StorageNodeUncordon,
+            TooManyDegradedVolumes, TooManyIoEnginePods, UnknownIoEnginePodController,
+            UnschedulableDataPlaneBatch, UpgradeInterrupted,
         },
         kube_client::KubeClientSet,
         rest_client::RestClientSet,
     },
-    upgrade::utils::{all_pods_are_ready, data_plane_is_upgraded, rebuild_result, RebuildResult},
+    events::event_recorder::{EventAction, EventRecorder},
+    helm::client::HelmReleaseClient,
+    metrics::metrics,
+    opts::{
+        CanaryResume, CliArgs, ControlPlaneComponent, ControlPlaneUnhealthyAction, CordonStrategy,
+    },
+    progress::progress,
+    shutdown::shutdown,
+    upgrade::{
+        checkpoint::UpgradeCheckpoint,
+        phase::{NodeTimingRecord, UpgradePhase},
+        registry::verify_image_exists,
+        utils::{
+            data_plane_is_upgraded, degraded_volume_count, enough_pods_are_ready,
+            io_engine_version_matches, label_selector_matches, pod_affinity_selects_node,
+            pod_container_image_tag, pod_insufficient_resources_reason, pod_is_crashlooping,
+            pod_is_ready, pod_is_scheduled, pod_ready, pod_spec_unschedulable_reason,
+            pod_targets_node, quantity_to_units, rebuild_ignored_volumes, rebuild_result,
+            storage_node_volumes, volume_rebuild_progress, volumes_below_min_healthy_replicas,
+            volumes_targeted_on_node, with_rest_retries, RebuildResult,
+        },
+    },
+};
+use futures::stream::{self, StreamExt};
+use k8s_openapi::{
+    api::{apps::v1::DaemonSet, core::v1::Pod},
+    apimachinery::pkg::apis::meta::v1::LabelSelector,
+    serde_json,
 };
-use k8s_openapi::api::core::v1::Pod;
 use kube::{
-    api::{DeleteParams, ListParams, ObjectList},
+    api::{DeleteParams, ListParams, ObjectList, Patch, PatchParams},
     ResourceExt,
 };
 use openapi::models::CordonDrainState;
-use snafu::ResultExt;
-use std::time::Duration;
-use tracing::info;
+use snafu::{ensure, ResultExt};
+use std::{
+    collections::{hash_map::RandomState, HashMap, HashSet},
+    hash::{BuildHasher, Hasher},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex, OnceLock, PoisonError,
+    },
+    time::{Duration, Instant},
+};
+use tokio::{
+    signal::unix::{signal, SignalKind},
+    sync::Notify,
+};
+use tracing::{error, info, warn};
 use utils::{API_REST_LABEL, ETCD_LABEL};
 
-/// Upgrade data plane by controlled restart of io-engine pods
+/// This is the time between the first two checks of a Node drain, if '--poll-interval' is not set.
+const DRAIN_POLL_INITIAL: Duration = Duration::from_secs(2);
+
+/// This is the maximum time between the checks of a Node drain, if it is not set up.
+const DEFAULT_DRAIN_POLL_MAX: Duration = Duration::from_secs(30);
+
+/// This is the maximum time between the checks of a Node drain, set up once the CLI arguments are
+/// parsed.
+static DRAIN_POLL_MAX: OnceLock<Duration> = OnceLock::new();
+
+/// This sets up the maximum time between the checks of a Node drain.
+pub(crate) fn init_drain_poll_max(drain_poll_max: Duration) {
+    let _ = DRAIN_POLL_MAX.set(drain_poll_max);
+}
+
+/// This returns the maximum time between the checks of a Node drain.
+fn drain_poll_max() -> Duration {
+    DRAIN_POLL_MAX
+        .get()
+        .copied()
+        .unwrap_or(DEFAULT_DRAIN_POLL_MAX)
+}
+
+/// This resumes the data-plane upgrade after the canary Node, when the upgrade-job process
+/// receives SIGUSR1 while the upgrade is paused.
+#[derive(Default)]
+struct CanaryResumeWaiter {
+    paused: AtomicBool,
+    notify: Notify,
+}
+
+/// This is the canary resume waiter, which the SIGUSR1 handler wakes up.
+static CANARY_RESUME_WAITER: OnceLock<CanaryResumeWaiter> = OnceLock::new();
+
+/// This returns the canary resume waiter.
+fn canary_resume_waiter() -> &'static CanaryResumeWaiter {
+    CANARY_RESUME_WAITER.get_or_init(CanaryResumeWaiter::default)
+}
+
+/// This sets up the handling of SIGUSR1, which resumes the data-plane upgrade after the canary
+/// Node. This is set up at startup, so that a SIGUSR1 which is sent before the upgrade pauses
+/// does not terminate the upgrade-job process. Such a SIGUSR1 is ignored.
+pub(crate) fn init_canary_resume_handler() -> Result<()> {
+    let mut resume_signal = signal(SignalKind::user_defined1()).context(CanaryResumeSignal)?;
+    tokio::spawn(async move {
+        while resume_signal.recv().await.is_some() {
+            let waiter = canary_resume_waiter();
+            if waiter.paused.load(Ordering::SeqCst) {
+                waiter.notify.notify_one();
+            } else {
+                warn!(
+                    "Received SIGUSR1 before the data-plane upgrade paused after the canary Node, \
+                    ignoring it"
+                );
+            }
+        }
+    });
+    Ok(())
+}
+
+/// This is the outcome of the data-plane upgrade.
+#[derive(Default)]
+pub(crate) struct DataPlaneUpgradeOutcome {
+    /// These are the names of the Nodes whose io-engine Pods were restarted, one for each
+    /// restarted Pod.
+    pub(crate) restarted_nodes: Vec<String>,
+    /// These are the names of the Nodes which were skipped, because their io-engine Pods were in
+    /// CrashLoopBackOff.
+    pub(crate) crashlooping_nodes: Vec<String>,
+    /// These are the names of the Nodes which were skipped, because their volumes did not move
+    /// off of them within the volume move timeout of the drain.
+    pub(crate) drain_timed_out_nodes: Vec<String>,
+    /// These are the names of the Nodes which were skipped, because their io-engine Pods already
+    /// run the upgrade target's image tag.
+    pub(crate) up_to_date_nodes: Vec<String>,
+    /// These are the names of the Nodes which were skipped, because they do not match the Node
+    /// label selector.
+    pub(crate) unselected_nodes: Vec<String>,
+    /// These are the names of the Nodes which were skipped, because they were cordoned or drained
+    /// other than by the upgrade, e.g. for maintenance.
+    pub(crate) maintenance_nodes: Vec<String>,
+    /// These are the times spent in each step of the restart, for each restarted Pod.
+    pub(crate) node_timings: Vec<NodeTimingRecord>,
+}
+
+/// This is the progress of the data-plane upgrade, which is kept apart from the upgrade so that
+/// it can be reported if the upgrade is cut short.
+#[derive(Default)]
+pub(crate) struct DataPlaneUpgradeTracker {
+    upgrading_nodes: Mutex<Vec<String>>,
+    upgraded_nodes: Mutex<Vec<String>>,
+    batch_cordoned_nodes: Mutex<Vec<String>>,
+}
+
+impl DataPlaneUpgradeTracker {
+    /// Notes that the upgrade of the Node has started.
+    fn node_started(&self, node_name: &str) {
+        let mut upgrading_nodes = self
+            .upgrading_nodes
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        upgrading_nodes.push(node_name.to_string());
+    }
+
+    /// Notes that the upgrade of the Node has ended, and whether the Node was upgraded.
+    fn node_ended(&self, node_name: &str, upgraded: bool) {
+        let mut upgrading_nodes = self
+            .upgrading_nodes
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        upgrading_nodes.retain(|upgrading_node| upgrading_node.ne(node_name));
+        if upgraded {
+            let mut upgraded_nodes = self
+                .upgraded_nodes
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner);
+            upgraded_nodes.push(node_name.to_string());
+        }
+    }
+
+    /// This returns the Nodes which are being upgraded.
+    pub(crate) fn upgrading_nodes(&self) -> Vec<String> {
+        self.upgrading_nodes
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+
+    /// This returns the Nodes which have been upgraded.
+    pub(crate) fn upgraded_nodes(&self) -> Vec<String> {
+        self.upgraded_nodes
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+
+    /// Notes that the Node was cordoned up front, with the batch cordon strategy.
+    fn node_batch_cordoned(&self, node_name: &str) {
+        let mut batch_cordoned_nodes = self
+            .batch_cordoned_nodes
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        batch_cordoned_nodes.push(node_name.to_string());
+    }
+
+    /// This returns the Nodes which may be left drained or cordoned if the upgrade is cut short,
+    /// i.e. the Nodes which are being upgraded and the Nodes which were cordoned up front.
+    pub(crate) fn cordoned_nodes(&self) -> Vec<String> {
+        let mut nodes = self.upgrading_nodes();
+        for node_name in self
+            .batch_cordoned_nodes
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .iter()
+        {
+            if !nodes.contains(node_name) {
+                nodes.push(node_name.clone());
+            }
+        }
+        nodes
+    }
+}
+
+/// Upgrade data plane by controlled restart of io-engine pods. Up to 'max_parallel_nodes' Nodes
+/// are upgraded concurrently, and Nodes which share a volume are not drained at the same time.
+/// Nodes whose io-engine Pods are in CrashLoopBackOff are not drained, and Nodes whose drain
+/// times out are uncordoned. These are skipped, or fail the upgrade if 'strict' is set. If a Node
+/// fails to upgrade, then no more Nodes are started, and the Nodes already being upgraded are
+/// waited on. If the helm release is rolled back, the restarted io-engine Pods are restarted
+/// again, back to the 'upgrade_from_version'. Nodes whose io-engine Pods already run the
+/// 'upgrade_to_image_tag' are skipped. The progress of the upgrade is noted in the 'tracker'.
 pub(crate) async fn upgrade_data_plane(
-    namespace: String,
-    rest_endpoint: String,
+    opts: &CliArgs,
     upgrade_to_version: String,
-) -> Result<()> {
+    upgrade_from_version: String,
+    upgrade_to_image_tag: Option<String>,
+    tracker: &DataPlaneUpgradeTracker,
+    event: &EventRecorder,
+    checkpoint: &UpgradeCheckpoint,
+) -> Result<DataPlaneUpgradeOutcome> {
+    let namespace = opts.namespace();
+
     // Generate k8s clients.
     let k8s_client = KubeClientSet::builder()
         .with_namespace(namespace.clone())
         .build()
         .await?;
+    let control_plane_namespace = opts.control_plane_namespace();
+    let control_plane_k8s_client = KubeClientSet::builder()
+        .with_namespace(control_plane_namespace.clone())
+        .build()
+        .await?;
+
+    // Generate storage REST API client.
+    let rest_client = RestClientSet::new_with_url(opts.rest_endpoint()?)?;
 
     // This makes data-plane upgrade idempotent.
-    let io_engine_label = format!("{IO_ENGINE_LABEL},{CHART_VERSION_LABEL_KEY}");
-    let io_engine_listparams = ListParams::default().labels(io_engine_label.as_str());
-    let io_engine_pod_list = k8s_client
-        .pods_api()
-        .list(&io_engine_listparams)
-        .await
-        .context(ListPodsWithLabel {
-            label: io_engine_label,
-            namespace: namespace.clone(),
-        })?;
-    if data_plane_is_upgraded(&upgrade_to_version, &io_engine_pod_list).await? {
+    if !data_plane_upgrade_is_required(
+        &k8s_client,
+        Some(&rest_client),
+        namespace.as_str(),
+        &upgrade_to_version,
+    )
+    .await?
+    {
         info!("Skipping data-plane upgrade: All data-plane Pods are already upgraded");
-        return Ok(());
+        return Ok(DataPlaneUpgradeOutcome::default());
     }
 
     // If here, then there is a need to proceed to data-plane upgrade.
@@ -60,9 +291,6 @@ pub(crate) async fn upgrade_data_plane(
         ListParams::default().labels(yet_to_upgrade_io_engine_label_selector.as_str());
     let namespace = namespace.clone();
 
-    // Generate storage REST API client.
-    let rest_client = RestClientSet::new_with_url(rest_endpoint)?;
-
     info!("Starting data-plane upgrade...");
 
     info!(
@@ -70,219 +298,2573 @@ pub(crate) async fn upgrade_data_plane(
         if any left over from previous upgrade attempts..."
     );
 
-    let storage_nodes_resp = rest_client
-        .nodes_api()
-        .get_nodes(None)
+    let storage_nodes_resp = with_rest_retries(|| rest_client.nodes_api().get_nodes(None))
         .await
         .context(ListStorageNodes)?;
     let storage_nodes = storage_nodes_resp.body();
     for storage_node in storage_nodes {
-        uncordon_node(storage_node.id.as_str(), &rest_client).await?;
+        remove_upgrade_cordons(storage_node.id.as_str(), &rest_client).await?;
     }
 
-    loop {
-        let initial_io_engine_pod_list: ObjectList<Pod> = k8s_client
-            .pods_api()
-            .list(&io_engine_listparams)
-            .await
-            .context(ListPodsWithLabel {
-                label: yet_to_upgrade_io_engine_label_selector.clone(),
-                namespace: namespace.clone(),
-            })?;
+    // The io-engine Pods of a StatefulSet are replaced one Node at a time, as those of a DaemonSet,
+    // instead of the StatefulSet controller rolling all of them. The strategy is set before the
+    // helm upgrade, and is set again here in case the helm upgrade rendered the chart's strategy.
+    set_on_delete_update_strategy(
+        yet_to_upgrade_io_engine_label_selector.as_str(),
+        namespace.as_str(),
+        &k8s_client,
+    )
+    .await?;
 
-        // Infinite loop exit.
-        if initial_io_engine_pod_list.items.is_empty() {
-            break;
-        }
+    // Only the io-engine Pods on the Nodes which match the Node label selector are upgraded.
+    let node_selector = opts.node_selector();
+    let selected_nodes = selected_node_names(node_selector.as_deref(), &k8s_client).await?;
 
-        for pod in initial_io_engine_pod_list.iter() {
-            // Validate the control plane pod is up and running before we start.
-            verify_control_plane_is_running(namespace.clone(), &k8s_client, &upgrade_to_version)
-                .await?;
+    // Read the volumes whose rebuilds are not to be waited on.
+    let ignored_volumes = match opts.rebuild_wait_policy_file() {
+        Some(policy_file) => rebuild_ignored_volumes(policy_file.as_path())?,
+        None => HashSet::new(),
+    };
 
-            // Fetch the node name on which the io-engine pod is running
-            let node_name = pod
-                .spec
-                .as_ref()
-                .ok_or(
-                    EmptyPodSpec {
-                        name: pod.name_any(),
-                        namespace: namespace.clone(),
+    let context = DataPlaneUpgradeContext {
+        opts,
+        namespace: namespace.as_str(),
+        control_plane_namespace: control_plane_namespace.as_str(),
+        upgrade_to_version: &upgrade_to_version,
+        k8s_client: &k8s_client,
+        control_plane_k8s_client: &control_plane_k8s_client,
+        rest_client: &rest_client,
+        event,
+        checkpoint,
+        rebuild_ignored_volumes: &ignored_volumes,
+        node_volumes: None,
+        control_plane_unhealthy_action: opts.on_control_plane_unhealthy(),
+    };
+
+    let cordon_strategy = opts.cordon_strategy();
+    let mut batch_cordoned_nodes: Vec<String> = Vec::new();
+    let mut restarted_nodes: Vec<String> = Vec::new();
+    let mut crashlooping_nodes: Vec<String> = Vec::new();
+    let mut drain_timed_out_nodes: Vec<String> = Vec::new();
+    let mut up_to_date_nodes: Vec<String> = Vec::new();
+    let mut unselected_nodes: Vec<String> = Vec::new();
+    let mut maintenance_nodes: Vec<String> = Vec::new();
+    let mut node_timings: Vec<NodeTimingRecord> = Vec::new();
+    let mut image_is_verified = false;
+    let mut canary_is_approved = false;
+
+    let result = async {
+        loop {
+            let initial_io_engine_pod_list: ObjectList<Pod> = k8s_client
+                .list_pods(&io_engine_listparams)
+                .await
+                .context(ListPodsWithLabel {
+                    label: yet_to_upgrade_io_engine_label_selector.clone(),
+                    namespace: namespace.clone(),
+                })?;
+
+            // Draining a Node whose io-engine Pod is already crashing would only degrade it
+            // further.
+            let mut io_engine_pods: Vec<&Pod> = Vec::new();
+            for pod in initial_io_engine_pod_list.iter() {
+                let node_name = pod_node_name(pod, namespace.as_str())?;
+                if crashlooping_nodes.contains(&node_name)
+                    || drain_timed_out_nodes.contains(&node_name)
+                    || up_to_date_nodes.contains(&node_name)
+                    || unselected_nodes.contains(&node_name)
+                    || maintenance_nodes.contains(&node_name)
+                {
+                    continue;
+                }
+                if checkpoint.node_is_upgraded(node_name.as_str()) {
+                    info!(
+                        pod.name = %pod.name_any(),
+                        node.name = %node_name,
+                        "Skipping Node, the state file records it as upgraded"
+                    );
+                    continue;
+                }
+                if let Some(selected_nodes) = selected_nodes.as_ref() {
+                    if !selected_nodes.contains(&node_name) {
+                        unselected_nodes.push(node_name);
+                        continue;
                     }
-                    .build(),
-                )?
-                .node_name
-                .as_ref()
-                .ok_or(
-                    EmptyPodNodeName {
-                        name: pod.name_any(),
-                        namespace: namespace.clone(),
+                }
+                if pod_is_crashlooping(pod) {
+                    ensure!(
+                        !opts.strict(),
+                        IoEnginePodCrashLoopBackOff {
+                            pod_name: pod.name_any(),
+                            namespace: namespace.clone(),
+                            node_name,
+                        }
+                    );
+                    warn!(
+                        pod.name = %pod.name_any(),
+                        node.name = %node_name,
+                        "Skipping Node, its io-engine Pod is in CrashLoopBackOff"
+                    );
+                    crashlooping_nodes.push(node_name);
+                    continue;
+                }
+                // The io-engine Pods which already run the target image are not restarted again,
+                // e.g. when the upgrade is run again after a partial failure.
+                if let Some(to_image_tag) = upgrade_to_image_tag.as_deref() {
+                    if pod_container_image_tag(pod, IO_ENGINE_CONTAINER_NAME).as_deref()
+                        == Some(to_image_tag)
+                    {
+                        info!(
+                            pod.name = %pod.name_any(),
+                            node.name = %node_name,
+                            image.tag = %to_image_tag,
+                            "Skipping Node, its io-engine Pod already runs the target image"
+                        );
+                        up_to_date_nodes.push(node_name);
+                        continue;
+                    }
+                }
+                io_engine_pods.push(pod);
+            }
+
+            // Infinite loop exit.
+            if io_engine_pods.is_empty() {
+                break;
+            }
+
+            progress().set_nodes_total(restarted_nodes.len() + io_engine_pods.len());
+            metrics().set_upgrade_nodes_total(restarted_nodes.len() + io_engine_pods.len());
+
+            // Look up the upgraded io-engine image before the first Node is drained.
+            if opts.verify_image_exists() && !image_is_verified {
+                if let Some(pod) = io_engine_pods.first() {
+                    verify_io_engine_image_exists(pod, namespace.as_str(), &k8s_client).await?;
+                }
+                image_is_verified = true;
+            }
+
+            // Cordon all of the Nodes up front, when upgrading with the batch strategy.
+            if cordon_strategy.eq(&CordonStrategy::Batch) {
+                // Validate the restarted io-engine Pods of the batch can be scheduled back onto
+                // their Nodes, before any of the Nodes are cordoned.
+                if opts.verify_batch_scheduling() {
+                    let mut batch_pods: Vec<&Pod> = Vec::new();
+                    for &pod in io_engine_pods.iter() {
+                        let node_name = pod_node_name(pod, namespace.as_str())?;
+                        if !batch_cordoned_nodes.contains(&node_name) {
+                            batch_pods.push(pod);
+                        }
                     }
-                    .build(),
-                )?
-                .as_str();
+                    verify_batch_is_schedulable(
+                        batch_pods.as_slice(),
+                        namespace.as_str(),
+                        &k8s_client,
+                    )
+                    .await?;
+                }
+
+                for pod in io_engine_pods.iter() {
+                    let node_name = pod_node_name(pod, namespace.as_str())?;
+                    if !batch_cordoned_nodes.contains(&node_name) {
+                        cordon_storage_node(node_name.as_str(), &rest_client).await?;
+                        tracker.node_batch_cordoned(node_name.as_str());
+                        batch_cordoned_nodes.push(node_name);
+                    }
+                }
+            }
+
+            // Restart the canary Node on its own, and hold back the rest of the restarts until the
+            // canary Node is approved.
+            if opts.canary_first() && !canary_is_approved {
+                match restarted_nodes.first() {
+                    Some(canary_node_name) => {
+                        wait_for_canary_approval(
+                            canary_node_name.as_str(),
+                            opts,
+                            namespace.as_str(),
+                            &k8s_client,
+                        )
+                        .await?;
+                        canary_is_approved = true;
+                    }
+                    None => io_engine_pods.truncate(1),
+                }
+            }
+
+            // Note the volumes on each of the Nodes, so that the Nodes which share a volume are not
+            // drained at the same time.
+            let max_parallel_nodes = opts.max_parallel_nodes();
+            let node_volumes = if max_parallel_nodes > 1 {
+                Some(storage_node_volumes(&rest_client, opts.volume_list_parallelism()).await?)
+            } else {
+                None
+            };
+            let node_context = DataPlaneUpgradeContext {
+                node_volumes: node_volumes.as_ref(),
+                ..context
+            };
+            let volume_locks = VolumeLocks::default();
+            let aborted = AtomicBool::new(false);
+
+            let mut node_pods: Vec<(&Pod, String)> = Vec::new();
+            for pod in io_engine_pods {
+                // Fetch the node name on which the io-engine pod is running
+                node_pods.push((pod, pod_node_name(pod, namespace.as_str())?));
+            }
+            let results: Vec<Option<(String, Result<NodeTimingRecord>)>> = stream::iter(node_pods)
+                .map(|(pod, node_name)| {
+                    upgrade_data_plane_node(
+                        &node_context,
+                        pod,
+                        node_name,
+                        &volume_locks,
+                        &aborted,
+                        tracker,
+                    )
+                })
+                .buffer_unordered(max_parallel_nodes)
+                .collect()
+                .await;
+
+            let mut failures: Vec<(String, Error)> = Vec::new();
+            for (node_name, result) in results.into_iter().flatten() {
+                match result {
+                    Ok(node_timing) => {
+                        if opts.node_timing_metrics() {
+                            for (step, duration) in node_timing.steps() {
+                                metrics().observe_data_plane_node_step(
+                                    node_name.as_str(),
+                                    step,
+                                    duration,
+                                );
+                            }
+                        }
+                        node_timings.push(node_timing);
+                        restarted_nodes.push(node_name);
+                    }
+                    Err(error @ Error::DrainTimedOut { .. }) if !opts.strict() => {
+                        warn!(
+                            node.name = %node_name,
+                            %error,
+                            "Skipping Node, its drain timed out"
+                        );
+                        drain_timed_out_nodes.push(node_name);
+                    }
+                    Err(error @ Error::StorageNodeInMaintenance { .. }) => {
+                        warn!(
+                            node.name = %node_name,
+                            %error,
+                            "Skipping Node, it is cordoned or drained for maintenance"
+                        );
+                        maintenance_nodes.push(node_name);
+                    }
+                    Err(error) => failures.push((node_name, error)),
+                }
+            }
+
+            // Stop, without starting any more Nodes, if the upgrade Job is asked to terminate.
+            if shutdown().is_cancelled() {
+                return Err(UpgradeInterrupted
+                    .build()
+                    .in_phase(UpgradePhase::DataPlane, None));
+            }
+
+            // The restarted Nodes are rolled back, if the helm release was rolled back while
+            // upgrading any of the Nodes.
+            if let Some(index) = failures
+                .iter()
+                .position(|(_, error)| matches!(error, Error::ControlPlaneRolledBack { .. }))
+            {
+                let (node_name, error) = failures.swap_remove(index);
+                rollback_data_plane(&context, restarted_nodes.as_slice(), &upgrade_from_version)
+                    .await?;
+                return Err(error.in_phase(UpgradePhase::DataPlane, Some(node_name.as_str())));
+            }
+            if let Some((node_name, error)) = failures.into_iter().next() {
+                return Err(error.in_phase(UpgradePhase::DataPlane, Some(node_name.as_str())));
+            }
 
             info!(
-                pod.name = %pod.name_any(),
-                node.name = %node_name,
-                "Starting upgrade for the data-plane pod"
+                "Checking to see if new {PRODUCT} Nodes have been added to the cluster, which \
+                require upgrade"
+            );
+        }
+        Ok::<(), Error>(())
+    }
+    .await;
+
+    // Uncordon all of the Nodes at the end, when upgrading with the batch strategy, whether or not
+    // the upgrade of the Nodes succeeded.
+    let uncordon_result = remove_batch_cordons(batch_cordoned_nodes.as_slice(), &rest_client).await;
+    result?;
+    uncordon_result?;
+
+    if !crashlooping_nodes.is_empty() {
+        warn!(
+            nodes = ?crashlooping_nodes,
+            "Skipped the upgrade of Nodes whose io-engine Pods are in CrashLoopBackOff"
+        );
+    }
+    if !drain_timed_out_nodes.is_empty() {
+        warn!(
+            nodes = ?drain_timed_out_nodes,
+            "Skipped the upgrade of Nodes whose drain timed out"
+        );
+    }
+    if !maintenance_nodes.is_empty() {
+        warn!(
+            nodes = ?maintenance_nodes,
+            "Skipped the upgrade of Nodes which are cordoned or drained for maintenance"
+        );
+    }
+
+    if let Some(selector) = node_selector {
+        let no_node_is_selected = restarted_nodes.is_empty()
+            && crashlooping_nodes.is_empty()
+            && drain_timed_out_nodes.is_empty()
+            && up_to_date_nodes.is_empty()
+            && maintenance_nodes.is_empty();
+        if no_node_is_selected && !unselected_nodes.is_empty() {
+            warn!(
+                node.selector = %selector,
+                "The Node label selector matched none of the io-engine Pods left to upgrade"
             );
+        }
+    }
+
+    info!("Successfully upgraded data-plane!");
 
-            // Wait for any rebuild to complete
-            wait_for_rebuild(node_name, &rest_client).await?;
+    Ok(DataPlaneUpgradeOutcome {
+        restarted_nodes,
+        crashlooping_nodes,
+        drain_timed_out_nodes,
+        up_to_date_nodes,
+        unselected_nodes,
+        maintenance_nodes,
+        node_timings,
+    })
+}
 
-            // Issue node drain command
-            drain_storage_node(node_name, &rest_client).await?;
+/// Waits for the control-plane to settle after the helm upgrade. After the settle delay, the
+/// control-plane is verified to be running, and the volume rebuilds (if any) are waited on, so
+/// that no Node is drained while the control-plane is still re-establishing the volume targets.
+pub(crate) async fn settle_after_helm_upgrade(
+    opts: &CliArgs,
+    upgrade_to_version: &str,
+) -> Result<()> {
+    let settle_delay = opts.post_helm_settle();
+    info!(
+        delay = ?settle_delay,
+        "Waiting for the control-plane to settle after the helm upgrade"
+    );
+    tokio::time::sleep(settle_delay).await;
 
-            // restart the data plane pod
-            delete_data_plane_pod(node_name, pod, &k8s_client).await?;
+    let control_plane_namespace = opts.control_plane_namespace();
+    let control_plane_k8s_client = KubeClientSet::builder()
+        .with_namespace(control_plane_namespace.clone())
+        .build()
+        .await?;
+    verify_control_plane_is_running(
+        control_plane_namespace,
+        &control_plane_k8s_client,
+        &upgrade_to_version.to_string(),
+        opts.control_plane_components().as_slice(),
+        opts.min_ready_fraction(),
+        opts.control_plane_ready_timeout(),
+    )
+    .await?;
 
-            // validate the new pod is up and running
-            verify_data_plane_pod_is_running(
-                node_name,
-                namespace.clone(),
-                &upgrade_to_version,
-                &k8s_client,
+    let rest_client = RestClientSet::new_with_url(opts.rest_endpoint()?)?;
+    let ignored_volumes = match opts.rebuild_wait_policy_file() {
+        Some(policy_file) => rebuild_ignored_volumes(policy_file.as_path())?,
+        None => HashSet::new(),
+    };
+    let mut result = RebuildResult::default();
+    loop {
+        let rebuild = rebuild_result(
+            &rest_client,
+            &mut result.discarded_volumes,
+            opts.volume_list_parallelism(),
+            &ignored_volumes,
+            None,
+        )
+        .await?;
+        if !rebuild.rebuilding {
+            break;
+        }
+        info!("Waiting for volume rebuilds to complete, before starting the data-plane upgrade");
+        tokio::time::sleep(Duration::from_secs(10_u64)).await;
+    }
+
+    info!("Control-plane has settled after the helm upgrade");
+    Ok(())
+}
+
+/// These are the options and the clients used to upgrade the io-engine Pod on each Node.
+struct DataPlaneUpgradeContext<'a> {
+    opts: &'a CliArgs,
+    namespace: &'a str,
+    control_plane_namespace: &'a str,
+    upgrade_to_version: &'a String,
+    k8s_client: &'a KubeClientSet,
+    control_plane_k8s_client: &'a KubeClientSet,
+    rest_client: &'a RestClientSet,
+    event: &'a EventRecorder,
+    checkpoint: &'a UpgradeCheckpoint,
+    rebuild_ignored_volumes: &'a HashSet<String>,
+    /// These are the volumes on each of the Nodes, when the Nodes are upgraded concurrently.
+    node_volumes: Option<&'a HashMap<String, HashSet<String>>>,
+    control_plane_unhealthy_action: ControlPlaneUnhealthyAction,
+}
+
+/// These are the volumes on the Nodes which are being upgraded concurrently.
+#[derive(Default)]
+struct VolumeLocks {
+    locked: Mutex<HashSet<String>>,
+}
+
+impl VolumeLocks {
+    /// Waits until none of the volumes are locked for the upgrade of another Node, and locks them.
+    /// This returns 'false' without locking the volumes, if the upgrade is aborted while waiting.
+    async fn lock(&self, node_name: &str, volumes: &HashSet<String>, aborted: &AtomicBool) -> bool {
+        loop {
+            {
+                let mut locked = self.locked.lock().unwrap_or_else(PoisonError::into_inner);
+                if locked.is_disjoint(volumes) {
+                    locked.extend(volumes.iter().cloned());
+                    return true;
+                }
+            }
+            if aborted.load(Ordering::SeqCst) {
+                return false;
+            }
+            info!(
+                node.name = %node_name,
+                "Waiting for the upgrade of the Nodes which share volumes with this Node"
+            );
+            tokio::time::sleep(Duration::from_secs(10_u64)).await;
+        }
+    }
+
+    /// Unlocks the volumes, once the upgrade of the Node is done.
+    fn unlock(&self, volumes: &HashSet<String>) {
+        let mut locked = self.locked.lock().unwrap_or_else(PoisonError::into_inner);
+        locked.retain(|volume_uuid| !volumes.contains(volume_uuid));
+    }
+}
+
+/// Upgrade the io-engine Pod on a Node, as one of the Nodes upgraded concurrently. The volumes on
+/// the Node are locked while it is upgraded. This returns None if the upgrade of the Node is not
+/// started, because the upgrade of another Node has failed. Any failure other than a skipped
+/// drain timeout or a Node in maintenance stops the upgrades which are yet to start.
+async fn upgrade_data_plane_node(
+    context: &DataPlaneUpgradeContext<'_>,
+    pod: &Pod,
+    node_name: String,
+    volume_locks: &VolumeLocks,
+    aborted: &AtomicBool,
+    tracker: &DataPlaneUpgradeTracker,
+) -> Option<(String, Result<NodeTimingRecord>)> {
+    let opts = context.opts;
+    let volumes = context
+        .node_volumes
+        .and_then(|node_volumes| node_volumes.get(&node_name))
+        .cloned()
+        .unwrap_or_default();
+    if !volume_locks
+        .lock(node_name.as_str(), &volumes, aborted)
+        .await
+    {
+        return None;
+    }
+    // No more Nodes are started once the upgrade Job is asked to terminate.
+    if aborted.load(Ordering::SeqCst) || shutdown().is_cancelled() {
+        volume_locks.unlock(&volumes);
+        return None;
+    }
+    tracker.node_started(node_name.as_str());
+
+    // Hold back the restart while too many volumes are Degraded.
+    let degraded_volumes_wait = match opts.max_degraded_volumes() {
+        Some(max_degraded_volumes) => {
+            wait_for_degraded_volumes_below(
+                max_degraded_volumes,
+                opts.degraded_volumes_timeout(),
+                context.rest_client,
+                opts.volume_list_parallelism(),
             )
-            .await?;
+            .await
+        }
+        None => Ok(()),
+    };
+    let result = match degraded_volumes_wait {
+        Ok(()) => {
+            tokio::select! {
+                result = upgrade_data_plane_pod_with_retries(context, pod, node_name.as_str()) => {
+                    result
+                }
+                _ = shutdown().cancelled() => {
+                    interrupt_node_upgrade(node_name.as_str(), context.rest_client).await
+                }
+            }
+        }
+        Err(error) => Err(error),
+    };
+    volume_locks.unlock(&volumes);
+    tracker.node_ended(node_name.as_str(), result.is_ok());
 
-            // Uncordon the drained node
-            uncordon_node(node_name, &rest_client).await?;
+    match &result {
+        Ok(_) => {
+            context.checkpoint.record_node_upgraded(node_name.as_str());
+            progress().node_done();
+            metrics().inc_upgrade_nodes_completed();
         }
+        Err(Error::DrainTimedOut { .. }) if !opts.strict() => {}
+        Err(Error::StorageNodeInMaintenance { .. }) => {}
+        Err(_) => aborted.store(true, Ordering::SeqCst),
+    }
+    Some((node_name, result))
+}
 
-        info!("Checking to see if new {PRODUCT} Nodes have been added to the cluster, which require upgrade");
+/// Stops the upgrade of the Node, when the upgrade Job is asked to terminate. The upgrade drain
+/// and cordon labels are removed from the Node, so that it is not left unschedulable. Failing to
+/// remove them is only logged, as the upgrade Job is terminating either way.
+async fn interrupt_node_upgrade(
+    node_name: &str,
+    rest_client: &RestClientSet,
+) -> Result<NodeTimingRecord> {
+    warn!(node.name = %node_name, "Stopping the upgrade of the Node");
+    match remove_upgrade_cordons(node_name, rest_client).await {
+        Ok(()) => info!(node.name = %node_name, "Uncordoned the Node after stopping its upgrade"),
+        Err(error) => error!(
+            node.name = %node_name,
+            %error,
+            "Failed to uncordon the Node after stopping its upgrade"
+        ),
     }
+    UpgradeInterrupted.fail()
+}
 
-    info!("Successfully upgraded data-plane!");
+/// Restarts the io-engine Pods on the Nodes which were upgraded, back to the 'rollback_to_version'
+/// of the helm release, after the helm release is rolled back because the data-plane upgrade
+/// failed. This sets up the clients and the options of the data-plane upgrade, to restart the
+/// Pods with.
+pub(crate) async fn rollback_upgraded_data_plane(
+    opts: &CliArgs,
+    upgraded_nodes: &[String],
+    rollback_to_version: &String,
+    event: &EventRecorder,
+    checkpoint: &UpgradeCheckpoint,
+) -> Result<()> {
+    if upgraded_nodes.is_empty() {
+        return Ok(());
+    }
+
+    let namespace = opts.namespace();
+    let k8s_client = KubeClientSet::builder()
+        .with_namespace(namespace.clone())
+        .build()
+        .await?;
+    let control_plane_namespace = opts.control_plane_namespace();
+    let control_plane_k8s_client = KubeClientSet::builder()
+        .with_namespace(control_plane_namespace.clone())
+        .build()
+        .await?;
+    let rest_client = RestClientSet::new_with_url(opts.rest_endpoint()?)?;
+    let ignored_volumes = match opts.rebuild_wait_policy_file() {
+        Some(policy_file) => rebuild_ignored_volumes(policy_file.as_path())?,
+        None => HashSet::new(),
+    };
+
+    let context = DataPlaneUpgradeContext {
+        opts,
+        namespace: namespace.as_str(),
+        control_plane_namespace: control_plane_namespace.as_str(),
+        upgrade_to_version: rollback_to_version,
+        k8s_client: &k8s_client,
+        control_plane_k8s_client: &control_plane_k8s_client,
+        rest_client: &rest_client,
+        event,
+        checkpoint,
+        rebuild_ignored_volumes: &ignored_volumes,
+        node_volumes: None,
+        control_plane_unhealthy_action: ControlPlaneUnhealthyAction::Abort,
+    };
+    rollback_data_plane(&context, upgraded_nodes, rollback_to_version).await
+}
+
+/// Restarts the io-engine Pods on the Nodes which were restarted by the upgrade, in the reverse of
+/// the order they were upgraded in, so that they converge back to the 'rollback_to_version' of
+/// the rolled back helm release. The Pods are restarted with the same drain and rebuild waits as
+/// the upgrade.
+async fn rollback_data_plane(
+    context: &DataPlaneUpgradeContext<'_>,
+    restarted_nodes: &[String],
+    rollback_to_version: &String,
+) -> Result<()> {
+    // The helm release is already rolled back, it is not to be rolled back again.
+    let rollback_context = DataPlaneUpgradeContext {
+        upgrade_to_version: rollback_to_version,
+        node_volumes: None,
+        control_plane_unhealthy_action: ControlPlaneUnhealthyAction::Abort,
+        ..*context
+    };
+
+    let label = format!("{IO_ENGINE_LABEL},{CHART_VERSION_LABEL_KEY}!={rollback_to_version}");
+    // The helm rollback may have rendered the chart's update strategy on the io-engine
+    // StatefulSets, if any.
+    set_on_delete_update_strategy(label.as_str(), context.namespace, context.k8s_client).await?;
+    let pod_list = context
+        .k8s_client
+        .list_pods(&ListParams::default().labels(label.as_str()))
+        .await
+        .context(ListPodsWithLabel {
+            label,
+            namespace: context.namespace.to_string(),
+        })?;
+
+    info!(
+        version = %rollback_to_version,
+        nodes = ?restarted_nodes,
+        "Rolling back the data-plane"
+    );
 
+    let mut rolled_back_nodes: Vec<&String> = Vec::new();
+    for node_name in restarted_nodes.iter().rev() {
+        if rolled_back_nodes.contains(&node_name) {
+            continue;
+        }
+        let Some(pod) = pod_list.iter().find(|pod| {
+            pod.spec
+                .as_ref()
+                .and_then(|spec| spec.node_name.as_ref())
+                .map_or(false, |pod_node_name| pod_node_name.eq(node_name))
+        }) else {
+            continue;
+        };
+
+        upgrade_data_plane_pod_with_retries(&rollback_context, pod, node_name.as_str())
+            .await
+            .map_err(|error| error.in_phase(UpgradePhase::DataPlane, Some(node_name.as_str())))?;
+        rolled_back_nodes.push(node_name);
+    }
+
+    info!("Rolled back the data-plane");
     Ok(())
 }
 
-/// Uncordon storage Node.
-async fn uncordon_node(node_id: &str, rest_client: &RestClientSet) -> Result<()> {
-    let drain_label_for_upgrade: String = DRAIN_FOR_UPGRADE.to_string();
-    let sleep_duration = Duration::from_secs(1_u64);
+/// Upgrade the io-engine Pod on a Node, retrying the whole of the upgrade sequence for the Node up
+/// to 'node_retries' times. The Node is uncordoned between attempts, unless it is cordoned with the
+/// rest of its batch. The Node's io-engine Pod is read again before each retry, as the failed
+/// attempt may have already replaced it. A timed out drain is retried too, e.g. when a brief
+/// rebuild held the drain back, and the Node is only skipped once the retries are used up. A
+/// rolled back helm release and a Node in maintenance are not retried.
+async fn upgrade_data_plane_pod_with_retries(
+    context: &DataPlaneUpgradeContext<'_>,
+    pod: &Pod,
+    node_name: &str,
+) -> Result<NodeTimingRecord> {
+    let retries = context.opts.node_retries();
+    let backoff = Duration::from_secs(10_u64);
+    let mut attempt = 0_u32;
+    let mut pod = pod.clone();
+
     loop {
-        let storage_node =
-            rest_client
-                .nodes_api()
-                .get_node(node_id)
-                .await
-                .context(GetStorageNode {
-                    node_id: node_id.to_string(),
-                })?;
+        match upgrade_data_plane_pod(context, &pod, node_name).await {
+            Ok(node_timing) => return Ok(node_timing),
+            Err(error)
+                if attempt < retries
+                    && !matches!(
+                        error,
+                        Error::ControlPlaneRolledBack { .. }
+                            | Error::StorageNodeInMaintenance { .. }
+                    ) =>
+            {
+                attempt += 1;
+                warn!(
+                    node.name = %node_name,
+                    %error,
+                    attempt,
+                    retries,
+                    "Failed to upgrade data-plane pod, retrying"
+                );
+                // The batch cordon stays on the Node until the rest of its batch is upgraded.
+                if context.opts.cordon_strategy().ne(&CordonStrategy::Batch) {
+                    uncordon_node(node_name, context.rest_client).await?;
+                }
+                tokio::time::sleep(backoff).await;
 
-        match storage_node
-            .into_body()
-            .spec
-            .ok_or(
-                EmptyStorageNodeSpec {
-                    node_id: node_id.to_string(),
+                if let Some(current_pod) =
+                    node_io_engine_pod(node_name, context.namespace, context.k8s_client).await?
+                {
+                    pod = current_pod;
                 }
-                .build(),
-            )?
-            .cordondrainstate
-        {
-            Some(CordonDrainState::drainedstate(drain_state))
-                if drain_state.drainlabels.contains(&drain_label_for_upgrade) =>
-            {
-                rest_client
-                    .nodes_api()
-                    .delete_node_cordon(node_id, DRAIN_FOR_UPGRADE)
+            }
+            Err(error) => return Err(error),
+        }
+    }
+}
+
+/// Returns the io-engine Pod which is on the Node, if any. A terminating Pod is only returned if
+/// there is no other io-engine Pod on the Node.
+async fn node_io_engine_pod(
+    node_name: &str,
+    namespace: &str,
+    k8s_client: &KubeClientSet,
+) -> Result<Option<Pod>> {
+    let node_name_pod_field = format!("spec.nodeName={node_name}");
+    let io_engine_listparams = ListParams::default()
+        .labels(IO_ENGINE_LABEL)
+        .fields(node_name_pod_field.as_str());
+    let pod_list: ObjectList<Pod> =
+        k8s_client
+            .list_pods(&io_engine_listparams)
+            .await
+            .context(ListPodsWithLabelAndField {
+                label: IO_ENGINE_LABEL.to_string(),
+                field: node_name_pod_field,
+                namespace: namespace.to_string(),
+            })?;
+
+    let (terminating_pods, pods): (Vec<Pod>, Vec<Pod>) = pod_list
+        .into_iter()
+        .partition(|pod| pod.metadata.deletion_timestamp.is_some());
+    Ok(pods.into_iter().chain(terminating_pods).next())
+}
+
+/// Upgrade the io-engine Pod on a Node. The Node is drained before the Pod is restarted, and is
+/// uncordoned after the new Pod is Ready. This returns the time spent in each of the steps.
+async fn upgrade_data_plane_pod(
+    context: &DataPlaneUpgradeContext<'_>,
+    pod: &Pod,
+    node_name: &str,
+) -> Result<NodeTimingRecord> {
+    let opts = context.opts;
+
+    // Validate the control plane pod is up and running before we start.
+    ensure_control_plane_is_running(context, node_name).await?;
+
+    info!(
+        pod.name = %pod.name_any(),
+        node.name = %node_name,
+        "Starting upgrade for the data-plane pod"
+    );
+
+    // Wait for any rebuild to complete
+    progress().set_node_step(node_name, "waiting for rebuilds");
+    let rebuild_wait_started_at = Instant::now();
+    wait_for_rebuild(node_name, context).await?;
+    let rebuild_wait = rebuild_wait_started_at.elapsed();
+
+    // Validate the node has the hugepages the new pod requests
+    if opts.verify_hugepages() {
+        verify_hugepages(
+            node_name,
+            pod,
+            context.namespace,
+            context.k8s_client,
+            opts.strict(),
+        )
+        .await?;
+    }
+
+    // Wait for the volumes on the node to have enough healthy replicas elsewhere
+    if let Some(min_healthy_replicas) = opts.min_healthy_replicas() {
+        wait_for_min_healthy_replicas(
+            node_name,
+            min_healthy_replicas,
+            opts.min_healthy_replicas_timeout(),
+            context.rest_client,
+            opts.volume_list_parallelism(),
+        )
+        .await?;
+    }
+
+    // Validate the pools on the other nodes can take on the replicas on the node
+    if !opts.skip_capacity_check() {
+        verify_spare_capacity(node_name, context.rest_client).await?;
+    }
+
+    // Note the cordon and drain labels which were on the node before the drain, so that only the
+    // labels added since are treated as unexpected after the uncordon.
+    let prior_cordon_drain_labels =
+        storage_node_cordon_drain_labels(node_name, context.rest_client).await?;
+
+    // Issue node drain command
+    progress().set_node_step(node_name, "draining");
+    let drain_started_at = Instant::now();
+    drain_storage_node(
+        node_name,
+        opts.benign_drain_errors().as_slice(),
+        opts.volume_move_timeout(),
+        opts.poll_interval(),
+        context.rest_client,
+        opts.volume_list_parallelism(),
+    )
+    .await?;
+    let drain = drain_started_at.elapsed();
+    context
+        .event
+        .publish_progress(
+            format!("Drained {PRODUCT} Node {node_name}"),
+            EventAction::DrainedNode,
+        )
+        .await;
+
+    // Wait for the PodDisruptionBudgets to allow the restart
+    if opts.respect_pdb() {
+        wait_for_pdb_disruptions_allowed(
+            pod,
+            opts.pdb_wait_timeout(),
+            context.namespace,
+            context.k8s_client,
+        )
+        .await?;
+    }
+
+    // restart the data plane pod
+    progress().set_node_step(node_name, "restarting");
+    let restart_started_at = Instant::now();
+    let deleted_pod_uid = delete_data_plane_pod(
+        node_name,
+        pod,
+        context.namespace,
+        context.upgrade_to_version,
+        context.k8s_client,
+    )
+    .await?;
+
+    // wait for the deleted pod to be replaced
+    if let Some(deleted_pod_uid) = deleted_pod_uid.as_deref() {
+        wait_for_replacement_pod(
+            node_name,
+            deleted_pod_uid,
+            context.namespace,
+            context.k8s_client,
+            opts.pod_settle_delay(),
+        )
+        .await?;
+    }
+
+    // validate the new pod can be scheduled onto the node
+    verify_data_plane_pod_is_scheduled(
+        node_name,
+        context.namespace,
+        context.upgrade_to_version,
+        context.k8s_client,
+        opts.unschedulable_grace_period(),
+    )
+    .await?;
+
+    // validate the new pod is up and running
+    verify_data_plane_pod_is_running(
+        node_name,
+        deleted_pod_uid.as_deref(),
+        context.namespace.to_string(),
+        context.upgrade_to_version,
+        context.k8s_client,
+        opts.data_plane_ready_timeout(),
+        opts.poll_interval(),
+    )
+    .await?;
+    let restart = restart_started_at.elapsed();
+    context
+        .event
+        .publish_progress(
+            format!("Restarted io-engine Pod on Node {node_name}"),
+            EventAction::RestartedPod,
+        )
+        .await;
+
+    // Validate the io-engine reports the target version.
+    if opts.verify_io_engine_version() {
+        verify_io_engine_version(
+            node_name,
+            context.upgrade_to_version,
+            context.rest_client,
+            opts.strict(),
+        )
+        .await?;
+    }
+
+    // Uncordon the drained node
+    progress().set_node_step(node_name, "uncordoning");
+    uncordon_node(node_name, context.rest_client).await?;
+
+    // Validate the node is schedulable again
+    verify_storage_node_is_schedulable(node_name, &prior_cordon_drain_labels, context.rest_client)
+        .await?;
+
+    Ok(NodeTimingRecord::new(
+        node_name,
+        rebuild_wait,
+        drain,
+        restart,
+    ))
+}
+
+/// Checks to see if any of the io-engine Pods are yet to be upgraded to the upgrade target version.
+/// The io-engine versions reported by the storage REST API are compared, if the REST API is given
+/// and all of the storage Nodes report one. The io-engine Pods' chart version labels are compared
+/// otherwise.
+pub(crate) async fn data_plane_upgrade_is_required(
+    k8s_client: &KubeClientSet,
+    rest_client: Option<&RestClientSet>,
+    namespace: &str,
+    upgrade_to_version: &str,
+) -> Result<bool> {
+    if let Some(rest_client) = rest_client {
+        if let Some(outdated_nodes) =
+            outdated_storage_nodes(rest_client, upgrade_to_version).await?
+        {
+            return Ok(!outdated_nodes.is_empty());
+        }
+    }
+
+    let io_engine_label = format!("{IO_ENGINE_LABEL},{CHART_VERSION_LABEL_KEY}");
+    let io_engine_listparams = ListParams::default().labels(io_engine_label.as_str());
+    let io_engine_pod_list =
+        k8s_client
+            .list_pods(&io_engine_listparams)
+            .await
+            .context(ListPodsWithLabel {
+                label: io_engine_label,
+                namespace: namespace.to_string(),
+            })?;
+
+    Ok(!data_plane_is_upgraded(upgrade_to_version, &io_engine_pod_list).await?)
+}
+
+/// Returns the names of the Kubernetes Nodes which match the Node label selector, if one is set.
+/// This is None if there is no Node label selector, i.e. if all of the Nodes are selected.
+pub(crate) async fn selected_node_names(
+    node_selector: Option<&str>,
+    k8s_client: &KubeClientSet,
+) -> Result<Option<HashSet<String>>> {
+    let Some(selector) = node_selector else {
+        return Ok(None);
+    };
+
+    let node_names = k8s_client
+        .nodes_api()
+        .list(&ListParams::default().labels(selector))
+        .await
+        .context(ListKubernetesNodesWithLabel {
+            label: selector.to_string(),
+        })?
+        .into_iter()
+        .map(ResourceExt::name_any)
+        .collect();
+    Ok(Some(node_names))
+}
+
+/// Returns the names of the Nodes whose io-engine Pods are yet to be upgraded to the upgrade
+/// target version. As with data_plane_upgrade_is_required(), the io-engine versions reported by
+/// the storage REST API are preferred over the io-engine Pods' chart version labels.
+pub(crate) async fn outdated_data_plane_nodes(
+    k8s_client: &KubeClientSet,
+    rest_client: Option<&RestClientSet>,
+    namespace: &str,
+    upgrade_to_version: &str,
+) -> Result<Vec<String>> {
+    if let Some(rest_client) = rest_client {
+        if let Some(outdated_nodes) =
+            outdated_storage_nodes(rest_client, upgrade_to_version).await?
+        {
+            return Ok(outdated_nodes);
+        }
+    }
+
+    let io_engine_label =
+        format!("{IO_ENGINE_LABEL},{CHART_VERSION_LABEL_KEY}!={upgrade_to_version}");
+    let io_engine_pod_list = k8s_client
+        .list_pods(&ListParams::default().labels(io_engine_label.as_str()))
+        .await
+        .context(ListPodsWithLabel {
+            label: io_engine_label,
+            namespace: namespace.to_string(),
+        })?;
+
+    let mut node_names: Vec<String> = io_engine_pod_list
+        .iter()
+        .filter_map(|pod| pod.spec.as_ref().and_then(|spec| spec.node_name.clone()))
+        .collect();
+    node_names.sort();
+    node_names.dedup();
+    Ok(node_names)
+}
+
+/// Returns the ids of the storage Nodes whose io-engine reports a version other than the upgrade
+/// target version. Unlike the container image tags, the reported version does not depend on how
+/// the images are tagged. This is None if any of the storage Nodes does not report a version.
+async fn outdated_storage_nodes(
+    rest_client: &RestClientSet,
+    upgrade_to_version: &str,
+) -> Result<Option<Vec<String>>> {
+    let storage_nodes = with_rest_retries(|| rest_client.nodes_api().get_nodes(None))
+        .await
+        .context(ListStorageNodes)?
+        .into_body();
+
+    let mut outdated_nodes: Vec<String> = Vec::new();
+    for storage_node in storage_nodes {
+        let Some(version) = storage_node.state.and_then(|state| state.version) else {
+            info!(
+                node.id = %storage_node.id,
+                "{PRODUCT} Node does not report an io-engine version, comparing Pod labels instead"
+            );
+            return Ok(None);
+        };
+        if !io_engine_version_matches(version.as_str(), upgrade_to_version) {
+            outdated_nodes.push(storage_node.id);
+        }
+    }
+
+    outdated_nodes.sort();
+    Ok(Some(outdated_nodes))
+}
+
+/// Returns the name of the Node the Pod is scheduled on.
+fn pod_node_name(pod: &Pod, namespace: &str) -> Result<String> {
+    pod.spec
+        .as_ref()
+        .ok_or(
+            EmptyPodSpec {
+                name: pod.name_any(),
+                namespace: namespace.to_string(),
+            }
+            .build(),
+        )?
+        .node_name
+        .clone()
+        .ok_or(
+            EmptyPodNodeName {
+                name: pod.name_any(),
+                namespace: namespace.to_string(),
+            }
+            .build(),
+        )
+}
+
+/// Lists the storage Nodes which carry the upgrade drain or cordon labels. These may have been left
+/// behind by a previous upgrade attempt which did not run to completion.
+pub(crate) async fn nodes_with_upgrade_cordons(rest_client: &RestClientSet) -> Result<Vec<String>> {
+    let storage_nodes = with_rest_retries(|| rest_client.nodes_api().get_nodes(None))
+        .await
+        .context(ListStorageNodes)?
+        .into_body();
+
+    Ok(storage_nodes
+        .into_iter()
+        .filter(|storage_node| {
+            storage_node
+                .spec
+                .as_ref()
+                .and_then(|spec| spec.cordondrainstate.as_ref())
+                .map_or(false, |state| {
+                    let drain_labels: &[String] = match state {
+                        CordonDrainState::cordonedstate(_) => &[],
+                        CordonDrainState::drainingstate(state)
+                        | CordonDrainState::drainedstate(state) => state.drainlabels.as_slice(),
+                    };
+                    drain_labels.contains(&DRAIN_FOR_UPGRADE.to_string())
+                        || cordon_labels(state).contains(&CORDON_FOR_UPGRADE.to_string())
+                })
+        })
+        .map(|storage_node| storage_node.id)
+        .collect())
+}
+
+/// Lists the storage Nodes which are being drained, for the upgrade or otherwise.
+pub(crate) async fn draining_storage_nodes(rest_client: &RestClientSet) -> Result<Vec<String>> {
+    let storage_nodes = with_rest_retries(|| rest_client.nodes_api().get_nodes(None))
+        .await
+        .context(ListStorageNodes)?
+        .into_body();
+
+    Ok(storage_nodes
+        .into_iter()
+        .filter(|storage_node| {
+            matches!(
+                storage_node
+                    .spec
+                    .as_ref()
+                    .and_then(|spec| spec.cordondrainstate.as_ref()),
+                Some(CordonDrainState::drainingstate(_))
+            )
+        })
+        .map(|storage_node| storage_node.id)
+        .collect())
+}
+
+/// Removes the upgrade drain and cordon labels from the storage Node, if present.
+pub(crate) async fn remove_upgrade_cordons(
+    node_id: &str,
+    rest_client: &RestClientSet,
+) -> Result<()> {
+    uncordon_node(node_id, rest_client).await?;
+    remove_upgrade_cordon(node_id, rest_client).await
+}
+
+/// Returns the cordon labels on a storage Node, across all of the cordon and drain states.
+fn cordon_labels(cordon_drain_state: &CordonDrainState) -> &[String] {
+    match cordon_drain_state {
+        CordonDrainState::cordonedstate(state) => state.cordonlabels.as_slice(),
+        CordonDrainState::drainingstate(state) | CordonDrainState::drainedstate(state) => {
+            state.cordonlabels.as_slice()
+        }
+    }
+}
+
+/// This is a predicate which is true if the storage Node has the upgrade cordon label.
+async fn has_upgrade_cordon(node_id: &str, rest_client: &RestClientSet) -> Result<bool> {
+    let storage_node = with_rest_retries(|| rest_client.nodes_api().get_node(node_id))
+        .await
+        .context(GetStorageNode {
+            node_id: node_id.to_string(),
+        })?;
+
+    Ok(storage_node
+        .into_body()
+        .spec
+        .ok_or(
+            EmptyStorageNodeSpec {
+                node_id: node_id.to_string(),
+            }
+            .build(),
+        )?
+        .cordondrainstate
+        .as_ref()
+        .map_or(false, |state| {
+            cordon_labels(state).contains(&CORDON_FOR_UPGRADE.to_string())
+        }))
+}
+
+/// Cordon storage Node with the upgrade cordon label, if it isn't already.
+async fn cordon_storage_node(node_id: &str, rest_client: &RestClientSet) -> Result<()> {
+    if has_upgrade_cordon(node_id, rest_client).await? {
+        return Ok(());
+    }
+
+    rest_client
+        .nodes_api()
+        .put_node_cordon(node_id, CORDON_FOR_UPGRADE)
+        .await
+        .context(StorageNodeCordon {
+            node_id: node_id.to_string(),
+        })?;
+
+    info!(node.id = %node_id,
+        label = %CORDON_FOR_UPGRADE,
+        "Cordoned {PRODUCT} Node"
+    );
+    Ok(())
+}
+
+/// Remove the upgrade cordon label from each of the storage Nodes which were cordoned up front,
+/// with the batch cordon strategy. All of the Nodes are tried, and the first failure is returned.
+async fn remove_batch_cordons(node_ids: &[String], rest_client: &RestClientSet) -> Result<()> {
+    let mut result = Ok(());
+    for node_id in node_ids {
+        if let Err(error) = remove_upgrade_cordon(node_id.as_str(), rest_client).await {
+            error!(node.id = %node_id, %error, "Failed to uncordon the Node");
+            if result.is_ok() {
+                result = Err(error);
+            }
+        }
+    }
+    result
+}
+
+/// Remove the upgrade cordon label from the storage Node, if present.
+async fn remove_upgrade_cordon(node_id: &str, rest_client: &RestClientSet) -> Result<()> {
+    if !has_upgrade_cordon(node_id, rest_client).await? {
+        return Ok(());
+    }
+
+    rest_client
+        .nodes_api()
+        .delete_node_cordon(node_id, CORDON_FOR_UPGRADE)
+        .await
+        .context(StorageNodeUncordon {
+            node_id: node_id.to_string(),
+        })?;
+
+    info!(node.id = %node_id,
+        label = %CORDON_FOR_UPGRADE,
+        "Removed cordon label from {PRODUCT} Node"
+    );
+    Ok(())
+}
+
+/// Uncordon storage Node.
+async fn uncordon_node(node_id: &str, rest_client: &RestClientSet) -> Result<()> {
+    let drain_label_for_upgrade: String = DRAIN_FOR_UPGRADE.to_string();
+    let sleep_duration = Duration::from_secs(1_u64);
+    loop {
+        let storage_node = with_rest_retries(|| rest_client.nodes_api().get_node(node_id))
+            .await
+            .context(GetStorageNode {
+                node_id: node_id.to_string(),
+            })?;
+
+        match storage_node
+            .into_body()
+            .spec
+            .ok_or(
+                EmptyStorageNodeSpec {
+                    node_id: node_id.to_string(),
+                }
+                .build(),
+            )?
+            .cordondrainstate
+        {
+            Some(CordonDrainState::drainedstate(drain_state))
+                if drain_state.drainlabels.contains(&drain_label_for_upgrade) =>
+            {
+                rest_client
+                    .nodes_api()
+                    .delete_node_cordon(node_id, DRAIN_FOR_UPGRADE)
+                    .await
+                    .context(StorageNodeUncordon {
+                        node_id: node_id.to_string(),
+                    })?;
+
+                info!(node.id = %node_id,
+                    label = %DRAIN_FOR_UPGRADE,
+                    "Removed drain label from {PRODUCT} Node"
+                );
+            }
+            _ => return Ok(()),
+        }
+        tokio::time::sleep(sleep_duration).await;
+    }
+}
+
+/// Returns all of the cordon and drain labels on the storage Node.
+async fn storage_node_cordon_drain_labels(
+    node_id: &str,
+    rest_client: &RestClientSet,
+) -> Result<HashSet<String>> {
+    let storage_node = with_rest_retries(|| rest_client.nodes_api().get_node(node_id))
+        .await
+        .context(GetStorageNode {
+            node_id: node_id.to_string(),
+        })?;
+
+    let Some(state) = storage_node
+        .into_body()
+        .spec
+        .ok_or(
+            EmptyStorageNodeSpec {
+                node_id: node_id.to_string(),
+            }
+            .build(),
+        )?
+        .cordondrainstate
+    else {
+        return Ok(HashSet::new());
+    };
+
+    let drain_labels: &[String] = match &state {
+        CordonDrainState::cordonedstate(_) => &[],
+        CordonDrainState::drainingstate(state) | CordonDrainState::drainedstate(state) => {
+            state.drainlabels.as_slice()
+        }
+    };
+    Ok(cordon_labels(&state)
+        .iter()
+        .chain(drain_labels.iter())
+        .cloned()
+        .collect())
+}
+
+/// Confirms that the uncordon took effect, i.e. the storage Node has none of the cordon or drain
+/// labels other than the ones which were on it before the drain, and the upgrade cordon label of
+/// the batch cordon strategy. A competing controller may cordon the Node again right after the
+/// uncordon, so the uncordon is retried a few times before giving up.
+async fn verify_storage_node_is_schedulable(
+    node_id: &str,
+    prior_labels: &HashSet<String>,
+    rest_client: &RestClientSet,
+) -> Result<()> {
+    let retries = 5_u32;
+    let backoff = Duration::from_secs(2_u64);
+    let mut attempt = 0_u32;
+
+    loop {
+        let mut unexpected_labels: Vec<String> =
+            storage_node_cordon_drain_labels(node_id, rest_client)
+                .await?
+                .into_iter()
+                .filter(|label| !prior_labels.contains(label) && label.ne(CORDON_FOR_UPGRADE))
+                .collect();
+        if unexpected_labels.is_empty() {
+            info!(node.id = %node_id, "Verified {PRODUCT} Node is schedulable after uncordon");
+            return Ok(());
+        }
+        unexpected_labels.sort();
+
+        ensure!(
+            attempt < retries,
+            StorageNodeNotSchedulable {
+                node_id: node_id.to_string(),
+                labels: unexpected_labels,
+            }
+        );
+        attempt += 1;
+        warn!(
+            node.id = %node_id,
+            labels = ?unexpected_labels,
+            attempt,
+            retries,
+            "{PRODUCT} Node is still cordoned or drained after uncordon, retrying"
+        );
+        uncordon_node(node_id, rest_client).await?;
+        tokio::time::sleep(backoff).await;
+    }
+}
+
+/// Returns the DaemonSet which owns the Pod, if any.
+async fn owner_daemonset(
+    pod: &Pod,
+    namespace: &str,
+    k8s_client: &KubeClientSet,
+) -> Result<Option<DaemonSet>> {
+    let Some(daemonset_name) = pod
+        .owner_references()
+        .iter()
+        .find(|owner| owner.kind.eq("DaemonSet"))
+        .map(|owner| owner.name.clone())
+    else {
+        return Ok(None);
+    };
+
+    k8s_client
+        .daemonsets_api()
+        .get(daemonset_name.as_str())
+        .await
+        .map(Some)
+        .context(GetDaemonSet {
+            name: daemonset_name,
+            namespace: namespace.to_string(),
+        })
+}
+
+/// Simulates the scheduling of the restarted io-engine Pods of a batch of Nodes, and verifies that
+/// each of them can be scheduled back onto its Node. The upgraded Pod template of the Pod's owner
+/// DaemonSet is checked against the Node's taints and labels.
+async fn verify_batch_is_schedulable(
+    pods: &[&Pod],
+    namespace: &str,
+    k8s_client: &KubeClientSet,
+) -> Result<()> {
+    let mut reasons: Vec<String> = Vec::new();
+
+    for pod in pods {
+        let node_name = pod_node_name(pod, namespace)?;
+        let pod_spec = match owner_daemonset(pod, namespace, k8s_client).await? {
+            Some(daemonset) => daemonset.spec.and_then(|spec| spec.template.spec),
+            None => pod.spec.clone(),
+        };
+        let Some(pod_spec) = pod_spec else {
+            continue;
+        };
+        let node = k8s_client
+            .nodes_api()
+            .get(node_name.as_str())
+            .await
+            .context(GetKubernetesNode {
+                node_name: node_name.clone(),
+            })?;
+
+        if let Some(reason) = pod_spec_unschedulable_reason(&pod_spec, &node) {
+            warn!(
+                pod.name = %pod.name_any(),
+                node.name = %node_name,
+                %reason,
+                "Restarted io-engine Pod would not be schedulable onto its Node"
+            );
+            reasons.push(format!("{node_name}: {reason}"));
+        }
+    }
+
+    ensure!(reasons.is_empty(), UnschedulableDataPlaneBatch { reasons });
+
+    info!(
+        nodes = pods.len(),
+        "Verified the restarted io-engine Pods of the batch are schedulable"
+    );
+    Ok(())
+}
+
+/// Verify that the io-engine container image of the Pod's owner DaemonSet exists in its registry.
+/// The helm upgrade has already updated the DaemonSet, so this is the image the restarted Pods
+/// are going to pull.
+async fn verify_io_engine_image_exists(
+    pod: &Pod,
+    namespace: &str,
+    k8s_client: &KubeClientSet,
+) -> Result<()> {
+    let Some(daemonset) = owner_daemonset(pod, namespace, k8s_client).await? else {
+        warn!(
+            pod.name = %pod.name_any(),
+            "Pod is not owned by a DaemonSet, skipping container image verification"
+        );
+        return Ok(());
+    };
+
+    let daemonset_name = daemonset.name_any();
+    let image = daemonset
+        .spec
+        .and_then(|spec| spec.template.spec)
+        .and_then(|spec| {
+            spec.containers
+                .into_iter()
+                .find(|container| container.name.eq(IO_ENGINE_CONTAINER_NAME))
+        })
+        .and_then(|container| container.image)
+        .ok_or(
+            EmptyContainerImage {
+                name: daemonset_name,
+                container: IO_ENGINE_CONTAINER_NAME.to_string(),
+            }
+            .build(),
+        )?;
+
+    verify_image_exists(image.as_str()).await
+}
+
+/// Verify that the Node's allocatable hugepages can satisfy the hugepages requests of the io-engine
+/// Pod which replaces this one. The requests are read from the Pod's owner DaemonSet, which the
+/// helm upgrade has already updated. A shortfall fails the upgrade if 'strict' is set, and is
+/// logged as a warning otherwise.
+async fn verify_hugepages(
+    node_name: &str,
+    pod: &Pod,
+    namespace: &str,
+    k8s_client: &KubeClientSet,
+    strict: bool,
+) -> Result<()> {
+    let Some(daemonset) = owner_daemonset(pod, namespace, k8s_client).await? else {
+        warn!(
+            pod.name = %pod.name_any(),
+            "Pod is not owned by a DaemonSet, skipping hugepages verification"
+        );
+        return Ok(());
+    };
+    let node = k8s_client
+        .nodes_api()
+        .get(node_name)
+        .await
+        .context(GetKubernetesNode {
+            node_name: node_name.to_string(),
+        })?;
+
+    // Sum up the hugepages requests of all of the containers, per hugepage size.
+    let mut requests: HashMap<String, u64> = HashMap::new();
+    let containers = daemonset
+        .spec
+        .and_then(|spec| spec.template.spec)
+        .map(|spec| spec.containers)
+        .unwrap_or_default();
+    for container in containers {
+        let container_requests = container
+            .resources
+            .and_then(|resources| resources.requests)
+            .unwrap_or_default();
+        for (resource, quantity) in container_requests {
+            if resource.starts_with("hugepages-") {
+                *requests.entry(resource).or_default() +=
+                    quantity_to_units(&quantity).unwrap_or_default();
+            }
+        }
+    }
+
+    let allocatable = node
+        .status
+        .and_then(|status| status.allocatable)
+        .unwrap_or_default();
+    for (resource, requested) in requests {
+        let allocatable = allocatable
+            .get(&resource)
+            .and_then(quantity_to_units)
+            .unwrap_or_default();
+        if allocatable >= requested {
+            continue;
+        }
+
+        if strict {
+            return InsufficientHugepages {
+                node_name,
+                resource,
+                requested,
+                allocatable,
+            }
+            .fail();
+        }
+        warn!(
+            node.name = %node_name,
+            %resource,
+            requested,
+            allocatable,
+            shortfall = requested - allocatable,
+            "Node does not have enough allocatable hugepages for the io-engine Pod"
+        );
+    }
+
+    Ok(())
+}
+
+/// Wait for all of the PodDisruptionBudgets which select the io-engine Pod to allow disruptions.
+/// A PodDisruptionBudget selects the Pod if its label selector, i.e. both the matchLabels and the
+/// matchExpressions, matches the labels of the Pod. This fails if the PodDisruptionBudgets do not
+/// allow disruptions within the timeout.
+async fn wait_for_pdb_disruptions_allowed(
+    pod: &Pod,
+    timeout: Duration,
+    namespace: &str,
+    k8s_client: &KubeClientSet,
+) -> Result<()> {
+    let sleep_duration = Duration::from_secs(5_u64);
+    let pod_labels = pod.labels();
+    let started_at = Instant::now();
+
+    loop {
+        let pdb_list = k8s_client
+            .pdbs_api()
+            .list(&ListParams::default())
+            .await
+            .context(ListPodDisruptionBudgets {
+                namespace: namespace.to_string(),
+            })?;
+
+        let blocking_pdbs: Vec<String> = pdb_list
+            .iter()
+            .filter(|pdb| {
+                // A PodDisruptionBudget without a selector selects no Pods.
+                serde_json::from_value::<LabelSelector>(pdb.data["spec"]["selector"].clone())
+                    .map_or(false, |selector| {
+                        label_selector_matches(&selector, pod_labels)
+                    })
+            })
+            .filter(|pdb| {
+                pdb.data["status"]["disruptionsAllowed"]
+                    .as_i64()
+                    .unwrap_or_default()
+                    <= 0
+            })
+            .map(ResourceExt::name_any)
+            .collect();
+
+        if blocking_pdbs.is_empty() {
+            return Ok(());
+        }
+        ensure!(
+            started_at.elapsed() < timeout,
+            PdbWaitTimeout {
+                pdbs: blocking_pdbs,
+                timeout
+            }
+        );
+
+        info!(
+            pdbs = ?blocking_pdbs,
+            "Waiting for PodDisruptionBudgets to allow disruptions"
+        );
+        tokio::time::sleep(sleep_duration).await;
+    }
+}
+
+/// This is the controller of an io-engine Pod.
+enum IoEnginePodController {
+    /// The Pod is controlled by a DaemonSet.
+    DaemonSet,
+    /// The Pod is controlled by the StatefulSet of this name.
+    StatefulSet(String),
+}
+
+/// Returns the controller of the Pod, from its owner references. The owner reference which is
+/// marked as the controller is preferred, and the only owner reference is used otherwise.
+fn io_engine_pod_controller(pod: &Pod, namespace: &str) -> Result<IoEnginePodController> {
+    let owners = pod.owner_references();
+    let controller = match owners.iter().find(|owner| owner.controller == Some(true)) {
+        Some(owner) => Some(owner),
+        None if owners.len() == 1 => owners.first(),
+        None => None,
+    };
+
+    match controller {
+        Some(owner) if owner.kind.eq("DaemonSet") => Ok(IoEnginePodController::DaemonSet),
+        Some(owner) if owner.kind.eq("StatefulSet") => {
+            Ok(IoEnginePodController::StatefulSet(owner.name.clone()))
+        }
+        Some(owner) => UnknownIoEnginePodController {
+            pod_name: pod.name_any(),
+            namespace: namespace.to_string(),
+            controller: format!("{} '{}'", owner.kind, owner.name),
+        }
+        .fail(),
+        None => UnknownIoEnginePodController {
+            pod_name: pod.name_any(),
+            namespace: namespace.to_string(),
+            controller: "no controller".to_string(),
+        }
+        .fail(),
+    }
+}
+
+/// Issue delete command on dataplane pods. The Pod is read again right before the delete, and the
+/// delete is skipped if the Pod has been replaced or is already on the upgrade target version.
+/// This returns the UID of the Pod which is to be replaced, if any.
+async fn delete_data_plane_pod(
+    node_name: &str,
+    pod: &Pod,
+    namespace: &str,
+    upgrade_to_version: &str,
+    k8s_client: &KubeClientSet,
+) -> Result<Option<String>> {
+    let pod_name = pod.name_any();
+
+    // The Pod may have been recreated since it was listed.
+    let current_pod = k8s_client
+        .get_pod_opt(pod_name.as_str())
+        .await
+        .context(GetPod {
+            pod_name: pod_name.clone(),
+            pod_namespace: namespace.to_string(),
+        })?;
+    let pod_uid = match pod_delete_decision(current_pod, upgrade_to_version) {
+        PodDeleteDecision::AlreadyReplaced => {
+            info!(
+                pod.name = %pod_name,
+                node.name = %node_name,
+                "Skipping Pod delete: Pod has already been replaced"
+            );
+            return Ok(None);
+        }
+        PodDeleteDecision::AlreadyOnTarget => {
+            info!(
+                pod.name = %pod_name,
+                node.name = %node_name,
+                "Skipping Pod delete: Pod is already on target"
+            );
+            return Ok(None);
+        }
+        PodDeleteDecision::Delete { pod_uid } => pod_uid,
+    };
+
+    // Deleting the io-engine pod
+    info!(
+        pod.name = pod_name.clone(),
+        node.name = node_name,
+        "Deleting the pod"
+    );
+    k8s_client
+        .delete_pod(pod_name.as_str(), &DeleteParams::default())
+        .await
+        .context(PodDelete {
+            name: pod_name,
+            node: node_name.to_string(),
+        })?;
+    info!(node.name = %node_name, "Pod delete command issued");
+    Ok(pod_uid)
+}
+
+/// This is what is to be done with an io-engine Pod which is to be restarted, as per its current
+/// state.
+#[derive(Debug, PartialEq)]
+enum PodDeleteDecision {
+    /// The Pod no longer exists, i.e. it has already been replaced.
+    AlreadyReplaced,
+    /// The Pod is already on the upgrade target version.
+    AlreadyOnTarget,
+    /// The Pod is to be deleted. This carries the UID of the Pod, if any.
+    Delete { pod_uid: Option<String> },
+}
+
+/// Decides whether the io-engine Pod is to be deleted, given the Pod as it was read right before
+/// the delete. The Pod is None if it does not exist anymore.
+fn pod_delete_decision(current_pod: Option<Pod>, upgrade_to_version: &str) -> PodDeleteDecision {
+    let Some(current_pod) = current_pod else {
+        return PodDeleteDecision::AlreadyReplaced;
+    };
+    if current_pod
+        .labels()
+        .get(CHART_VERSION_LABEL_KEY)
+        .map_or(false, |version| version.eq(upgrade_to_version))
+    {
+        return PodDeleteDecision::AlreadyOnTarget;
+    }
+    PodDeleteDecision::Delete {
+        pod_uid: current_pod.uid(),
+    }
+}
+
+/// Sets the 'OnDelete' update strategy on the io-engine StatefulSets, before the helm upgrade
+/// updates their Pod templates, so that the StatefulSet controller does not restart the io-engine
+/// Pods by ordinal, without draining their Nodes, while helm waits on the release.
+pub(crate) async fn set_io_engine_on_delete_update_strategy(opts: &CliArgs) -> Result<()> {
+    let namespace = opts.namespace();
+    let k8s_client = KubeClientSet::builder()
+        .with_namespace(namespace.clone())
+        .build()
+        .await?;
+    set_on_delete_update_strategy(IO_ENGINE_LABEL, namespace.as_str(), &k8s_client).await
+}
+
+/// Restores the update strategy which the io-engine StatefulSets had before the upgrade. A
+/// StatefulSet with io-engine Pods which are not on the version, e.g. on a skipped Node, keeps the
+/// 'OnDelete' strategy, so that these Pods are not restarted without their Nodes being drained.
+/// The original strategy stays recorded on the StatefulSet, and is restored by a later upgrade.
+pub(crate) async fn restore_io_engine_update_strategy(opts: &CliArgs, version: &str) -> Result<()> {
+    let namespace = opts.namespace();
+    let k8s_client = KubeClientSet::builder()
+        .with_namespace(namespace.clone())
+        .build()
+        .await?;
+
+    let outdated_label = format!("{IO_ENGINE_LABEL},{CHART_VERSION_LABEL_KEY}!={version}");
+    let outdated_statefulsets =
+        io_engine_statefulsets(outdated_label.as_str(), namespace.as_str(), &k8s_client).await?;
+    let statefulsets = k8s_client
+        .statefulsets_api()
+        .list(&ListParams::default())
+        .await
+        .context(ListStatefulSets {
+            namespace: namespace.clone(),
+        })?;
+
+    for statefulset in statefulsets {
+        let name = statefulset.name_any();
+        let Some(update_strategy) = statefulset
+            .annotations()
+            .get(UPDATE_STRATEGY_ANNOTATION_KEY)
+        else {
+            continue;
+        };
+        if outdated_statefulsets.contains(&name) {
+            warn!(
+                statefulset.name = %name,
+                %version,
+                "Keeping the OnDelete update strategy on the io-engine StatefulSet, as some of its \
+                Pods are not on the version"
+            );
+            continue;
+        }
+
+        let update_strategy: serde_json::Value = serde_json::from_str(update_strategy)
+            .context(ParseUpdateStrategy { name: name.clone() })?;
+        let patch = serde_json::json!({
+            "metadata": {
+                "annotations": {
+                    UPDATE_STRATEGY_ANNOTATION_KEY: null,
+                }
+            },
+            "spec": {
+                "updateStrategy": update_strategy,
+            }
+        });
+        k8s_client
+            .statefulsets_api()
+            .patch(
+                name.as_str(),
+                &PatchParams::default(),
+                &Patch::Merge(&patch),
+            )
+            .await
+            .context(RestoreStatefulSetUpdateStrategy {
+                name: name.clone(),
+                namespace: namespace.clone(),
+            })?;
+        info!(
+            statefulset.name = %name,
+            "Restored the update strategy of the io-engine StatefulSet"
+        );
+    }
+    Ok(())
+}
+
+/// Returns the names of the StatefulSets which control any of the io-engine Pods with the labels.
+async fn io_engine_statefulsets(
+    io_engine_label_selector: &str,
+    namespace: &str,
+    k8s_client: &KubeClientSet,
+) -> Result<HashSet<String>> {
+    let pods = k8s_client
+        .list_pods(&ListParams::default().labels(io_engine_label_selector))
+        .await
+        .context(ListPodsWithLabel {
+            label: io_engine_label_selector.to_string(),
+            namespace: namespace.to_string(),
+        })?;
+    let mut statefulsets: HashSet<String> = HashSet::new();
+    for pod in pods.iter() {
+        if let IoEnginePodController::StatefulSet(name) = io_engine_pod_controller(pod, namespace)?
+        {
+            statefulsets.insert(name);
+        }
+    }
+    Ok(statefulsets)
+}
+
+/// Sets the 'OnDelete' update strategy on the StatefulSets which control any of the io-engine
+/// Pods, before any of the Pods are restarted. A StatefulSet then recreates a Pod with the
+/// upgraded Pod template only once the Pod is deleted, i.e. once its Node is drained, instead of
+/// rolling all of its Pods by ordinal. The io-engine Pods of a DaemonSet are left as they are.
+/// The StatefulSet's original strategy is recorded in an annotation, unless already recorded by an
+/// earlier attempt, so that it is restored once the upgrade is done.
+async fn set_on_delete_update_strategy(
+    io_engine_label_selector: &str,
+    namespace: &str,
+    k8s_client: &KubeClientSet,
+) -> Result<()> {
+    let names = io_engine_statefulsets(io_engine_label_selector, namespace, k8s_client).await?;
+    if names.is_empty() {
+        return Ok(());
+    }
+    let statefulsets = k8s_client
+        .statefulsets_api()
+        .list(&ListParams::default())
+        .await
+        .context(ListStatefulSets {
+            namespace: namespace.to_string(),
+        })?;
+
+    for statefulset in statefulsets {
+        let name = statefulset.name_any();
+        if !names.contains(&name) {
+            continue;
+        }
+
+        let mut patch = serde_json::json!({
+            "spec": {
+                "updateStrategy": {
+                    "type": "OnDelete",
+                    "rollingUpdate": null,
+                }
+            }
+        });
+        if !statefulset
+            .annotations()
+            .contains_key(UPDATE_STRATEGY_ANNOTATION_KEY)
+        {
+            let update_strategy = serde_json::to_string(
+                &statefulset
+                    .spec
+                    .as_ref()
+                    .and_then(|spec| spec.update_strategy.as_ref()),
+            )
+            .context(SerializeUpdateStrategy { name: name.clone() })?;
+            patch["metadata"] = serde_json::json!({
+                "annotations": {
+                    UPDATE_STRATEGY_ANNOTATION_KEY: update_strategy,
+                }
+            });
+        }
+
+        k8s_client
+            .statefulsets_api()
+            .patch(
+                name.as_str(),
+                &PatchParams::default(),
+                &Patch::Merge(&patch),
+            )
+            .await
+            .context(PatchStatefulSetUpdateStrategy {
+                name: name.clone(),
+                namespace: namespace.to_string(),
+            })?;
+        info!(
+            statefulset.name = %name,
+            "Set the OnDelete update strategy on the io-engine StatefulSet"
+        );
+    }
+    Ok(())
+}
+
+/// Wait for the deleted io-engine Pod to be replaced by a new Pod on the Node. The new Pod is told
+/// apart from the deleted one by its UID, so that the deleted Pod, while it is terminating, or the
+/// absence of any Pod, is not mistaken for the replacement.
+async fn wait_for_replacement_pod(
+    node_name: &str,
+    deleted_pod_uid: &str,
+    namespace: &str,
+    k8s_client: &KubeClientSet,
+    settle_delay: Duration,
+) -> Result<()> {
+    let sleep_duration = Duration::from_secs(2_u64);
+    let max_attempts = 60_u32;
+    let io_engine_listparams = ListParams::default().labels(IO_ENGINE_LABEL);
+
+    tokio::time::sleep(settle_delay).await;
+
+    info!(node.name = %node_name, "Waiting for the replacement data-plane Pod to be created");
+    for _ in 0 .. max_attempts {
+        let pod_list: ObjectList<Pod> =
+            k8s_client
+                .list_pods(&io_engine_listparams)
+                .await
+                .context(ListPodsWithLabel {
+                    label: IO_ENGINE_LABEL.to_string(),
+                    namespace: namespace.to_string(),
+                })?;
+
+        if let Some(pod) = pod_list.iter().find(|pod| {
+            pod_targets_node(pod, node_name)
+                && pod.uid().map_or(false, |uid| uid.ne(deleted_pod_uid))
+        }) {
+            info!(
+                pod.name = %pod.name_any(),
+                node.name = %node_name,
+                "Replacement Pod has been created"
+            );
+            return Ok(());
+        }
+
+        tokio::time::sleep(sleep_duration).await;
+    }
+
+    warn!(
+        node.name = %node_name,
+        "Replacement data-plane Pod is not yet created, waiting for it to come to Ready state"
+    );
+    Ok(())
+}
+
+/// Wait for the replacement io-engine Pod to be scheduled onto the Node. If the Pod stays Pending
+/// for the 'unschedulable_grace_period' because the Node cannot satisfy its resource requests
+/// (e.g. hugepages, memory), this fails instead of waiting for the Pod to become Ready.
+async fn verify_data_plane_pod_is_scheduled(
+    node_name: &str,
+    namespace: &str,
+    upgrade_to_version: &str,
+    k8s_client: &KubeClientSet,
+    unschedulable_grace_period: Duration,
+) -> Result<()> {
+    let sleep_duration = Duration::from_secs(5_u64);
+    let wait_timeout = Duration::from_secs(300_u64);
+    let started_at = Instant::now();
+    let pod_label = format!("{IO_ENGINE_LABEL},{CHART_VERSION_LABEL_KEY}={upgrade_to_version}");
+    let io_engine_listparams = ListParams::default().labels(pod_label.as_str());
+
+    info!(node.name = %node_name, "Waiting for data-plane Pod to be scheduled");
+    // This is when the Pod was first seen to be unschedulable for lack of resources, since it was
+    // last seen otherwise. A Pod which stays unschedulable for lack of resources is waited on
+    // until the grace period runs out, even past the wait timeout.
+    let mut insufficient_since: Option<Instant> = None;
+    while started_at.elapsed() < wait_timeout || insufficient_since.is_some() {
+        let pod_list: ObjectList<Pod> =
+            k8s_client
+                .list_pods(&io_engine_listparams)
+                .await
+                .context(ListPodsWithLabel {
+                    label: pod_label.clone(),
+                    namespace: namespace.to_string(),
+                })?;
+
+        match pod_list.iter().find(|pod| pod_targets_node(pod, node_name)) {
+            Some(pod) if pod_is_scheduled(pod) => {
+                info!(pod.name = %pod.name_any(), node.name = %node_name, "Pod is scheduled");
+                return Ok(());
+            }
+            Some(pod) => match pod_insufficient_resources_reason(pod) {
+                Some(reason) => {
+                    let since = insufficient_since.get_or_insert_with(Instant::now);
+                    ensure!(
+                        since.elapsed() < unschedulable_grace_period,
+                        ReplacementPodUnschedulable {
+                            pod: pod.name_any(),
+                            reason
+                        }
+                    );
+                }
+                None => insufficient_since = None,
+            },
+            None => insufficient_since = None,
+        }
+
+        tokio::time::sleep(sleep_duration).await;
+    }
+
+    warn!(
+        node.name = %node_name,
+        "Data-plane Pod is not yet scheduled, waiting for it to come to Ready state"
+    );
+    Ok(())
+}
+
+/// Wait for the new io-engine Pod on the Node to be Ready, for up to 'timeout', checking every
+/// 'poll_interval' or every 5 seconds. If the Pod was deleted, a replacement Pod which is
+/// scheduled onto a different Node fails the wait.
+async fn verify_data_plane_pod_is_running(
+    node_name: &str,
+    deleted_pod_uid: Option<&str>,
+    namespace: String,
+    upgrade_to_version: &String,
+    k8s_client: &KubeClientSet,
+    timeout: Duration,
+    poll_interval: Option<Duration>,
+) -> Result<()> {
+    let duration = poll_interval.unwrap_or(Duration::from_secs(5_u64));
+    let started_at = Instant::now();
+    // Validate the new pod is up and running
+    info!(node.name = %node_name, "Waiting for data-plane Pod to come to Ready state");
+    while !data_plane_pod_is_running(node_name, namespace.clone(), upgrade_to_version, k8s_client)
+        .await?
+    {
+        if let Some(deleted_pod_uid) = deleted_pod_uid {
+            verify_replacement_pod_node(node_name, deleted_pod_uid, namespace.as_str(), k8s_client)
+                .await?;
+        }
+        ensure!(
+            started_at.elapsed() < timeout,
+            DataPlanePodReadyTimeout { node_name, timeout }
+        );
+        tokio::time::sleep(duration).await;
+    }
+    Ok(())
+}
+
+/// Verify that the replacement of the deleted io-engine Pod, i.e. a Pod other than the deleted one
+/// whose NodeAffinity selects the Node, is not bound to a different Node. The restarts are
+/// accounted for per Node, so a Pod which lands elsewhere would leave the accounting wrong.
+async fn verify_replacement_pod_node(
+    node_name: &str,
+    deleted_pod_uid: &str,
+    namespace: &str,
+    k8s_client: &KubeClientSet,
+) -> Result<()> {
+    let pod_list: ObjectList<Pod> = k8s_client
+        .list_pods(&ListParams::default().labels(IO_ENGINE_LABEL))
+        .await
+        .context(ListPodsWithLabel {
+            label: IO_ENGINE_LABEL.to_string(),
+            namespace: namespace.to_string(),
+        })?;
+
+    verify_replacement_pods_node(pod_list.items.as_slice(), node_name, deleted_pod_uid)
+}
+
+/// Fails if any of the io-engine Pods, other than the deleted one, is bound to a Node other than
+/// the one its NodeAffinity selects.
+fn verify_replacement_pods_node(
+    pods: &[Pod],
+    node_name: &str,
+    deleted_pod_uid: &str,
+) -> Result<()> {
+    for pod in pods.iter().filter(|pod| {
+        pod.uid().map_or(false, |uid| uid.ne(deleted_pod_uid))
+            && pod_affinity_selects_node(pod, node_name)
+    }) {
+        if let Some(actual_node) = pod.spec.as_ref().and_then(|spec| spec.node_name.as_ref()) {
+            ensure!(
+                actual_node.eq(node_name),
+                PodRescheduledUnexpectedly {
+                    pod: pod.name_any(),
+                    expected_node: node_name,
+                    actual_node
+                }
+            );
+        }
+    }
+
+    Ok(())
+}
+
+/// Verify that the io-engine version reported by the storage REST API for the Node matches the
+/// upgrade target version. The REST API may take a while to register the restarted io-engine,
+/// so the version is polled for a bounded number of times before concluding.
+async fn verify_io_engine_version(
+    node_id: &str,
+    upgrade_to_version: &str,
+    rest_client: &RestClientSet,
+    strict: bool,
+) -> Result<()> {
+    let sleep_duration = Duration::from_secs(5_u64);
+    let max_attempts = 12_u32;
+    let mut reported_version = String::new();
+
+    for attempt in 1 ..= max_attempts {
+        let storage_node = with_rest_retries(|| rest_client.nodes_api().get_node(node_id))
+            .await
+            .context(GetStorageNode {
+                node_id: node_id.to_string(),
+            })?;
+
+        let Some(version) = storage_node
+            .into_body()
+            .state
+            .and_then(|state| state.version)
+        else {
+            warn!(
+                node.id = %node_id,
+                "{PRODUCT} Node does not report an io-engine version, skipping version verification"
+            );
+            return Ok(());
+        };
+
+        if io_engine_version_matches(version.as_str(), upgrade_to_version) {
+            info!(node.id = %node_id, version = %version, "Verified io-engine version");
+            return Ok(());
+        }
+
+        reported_version = version;
+        if attempt < max_attempts {
+            tokio::time::sleep(sleep_duration).await;
+        }
+    }
+
+    if strict {
+        return NodeVersionMismatch {
+            node_id: node_id.to_string(),
+            reported_version,
+            expected_version: upgrade_to_version.to_string(),
+        }
+        .fail();
+    }
+
+    warn!(
+        node.id = %node_id,
+        reported_version = %reported_version,
+        expected_version = %upgrade_to_version,
+        "{PRODUCT} Node reports an unexpected io-engine version"
+    );
+    Ok(())
+}
+
+/// Wait for the rebuild to complete if any, after the rebuild grace period. If there is a quiet
+/// period, then no volume may be rebuilding for all of the quiet period. If there is a stall
+/// timeout, then the rebuild progress of each volume is tracked across the polls, and a rebuild
+/// which makes no progress for the stall timeout is logged as a warning, or fails the wait if
+/// 'strict' is set. When the Nodes are upgraded concurrently, only the rebuilds of the volumes on
+/// the Node are waited on, as the volumes of the Nodes being upgraded alongside keep rebuilding
+/// until those are done.
+async fn wait_for_rebuild(node_name: &str, context: &DataPlaneUpgradeContext<'_>) -> Result<()> {
+    let opts = context.opts;
+    let rest_client = context.rest_client;
+    let list_parallelism = opts.volume_list_parallelism();
+    let quiet_period = opts.rebuild_quiet_period();
+    let node_volumes = context
+        .node_volumes
+        .map(|node_volumes| node_volumes.get(node_name).cloned().unwrap_or_default());
+
+    // Wait for any rebuilds to kick in.
+    tokio::time::sleep(opts.rebuild_grace_period()).await;
+
+    let poll_interval = opts.poll_interval().unwrap_or(Duration::from_secs(10_u64));
+    let mut result = RebuildResult::default();
+    let mut quiet = RebuildQuietPeriod::new(quiet_period);
+    let mut rebuild_stalls: HashMap<String, RebuildStall> = HashMap::new();
+    loop {
+        let rebuild = rebuild_result(
+            rest_client,
+            &mut result.discarded_volumes,
+            list_parallelism,
+            context.rebuild_ignored_volumes,
+            node_volumes.as_ref(),
+        )
+        .await?;
+
+        match quiet.observe(rebuild.rebuilding, Instant::now()) {
+            QuietPeriodState::Passed => break,
+            QuietPeriodState::Quiet { remaining } => {
+                info!(
+                    node.name = %node_name,
+                    quiet_period = ?quiet_period,
+                    "Waiting for the volume rebuild quiet period to pass"
+                );
+                tokio::time::sleep(poll_interval.min(remaining)).await;
+            }
+            QuietPeriodState::Rebuilding { restarted } => {
+                if restarted {
+                    info!(
+                        node.name = %node_name,
+                        "A volume rebuild started during the quiet period, restarting the quiet \
+                        period"
+                    );
+                }
+                if let Some(stall_timeout) = opts.rebuild_stall_timeout() {
+                    check_rebuild_stalls(
+                        &mut rebuild_stalls,
+                        stall_timeout,
+                        opts.strict(),
+                        node_volumes.as_ref(),
+                        rest_client,
+                        list_parallelism,
+                    )
+                    .await?;
+                }
+                info!(node.name = %node_name, "Waiting for volume rebuilds to complete");
+                tokio::time::sleep(poll_interval).await;
+            }
+        }
+    }
+    info!(node.name = %node_name, "No volume rebuilds in progress");
+    Ok(())
+}
+
+/// This tracks the rebuild quiet period, i.e. how long no volume has been rebuilding for, across
+/// the rebuild polls.
+struct RebuildQuietPeriod {
+    quiet_period: Duration,
+    quiet_since: Option<Instant>,
+}
+
+/// This is the state of the rebuild quiet period, as of a rebuild poll.
+#[derive(Debug, PartialEq)]
+enum QuietPeriodState {
+    /// A volume is rebuilding. This is 'restarted' if the volume rebuild cut the quiet period
+    /// short.
+    Rebuilding { restarted: bool },
+    /// No volume is rebuilding, but the quiet period is yet to pass.
+    Quiet { remaining: Duration },
+    /// No volume has been rebuilding for all of the quiet period.
+    Passed,
+}
+
+impl RebuildQuietPeriod {
+    fn new(quiet_period: Duration) -> Self {
+        Self {
+            quiet_period,
+            quiet_since: None,
+        }
+    }
+
+    /// Records the outcome of a rebuild poll made at 'now', and returns the state of the quiet
+    /// period. A volume rebuild restarts the quiet period.
+    fn observe(&mut self, rebuilding: bool, now: Instant) -> QuietPeriodState {
+        if rebuilding {
+            return QuietPeriodState::Rebuilding {
+                restarted: self.quiet_since.take().is_some(),
+            };
+        }
+
+        let quiet_for = now.saturating_duration_since(*self.quiet_since.get_or_insert(now));
+        if quiet_for >= self.quiet_period {
+            return QuietPeriodState::Passed;
+        }
+        QuietPeriodState::Quiet {
+            remaining: self.quiet_period - quiet_for,
+        }
+    }
+}
+
+/// This is the last seen rebuild progress of a volume.
+struct RebuildStall {
+    progress: i64,
+    since: Instant,
+    warned: bool,
+}
+
+/// Compares the rebuild progress of each of the rebuilding volumes against the last seen progress.
+/// A volume whose rebuild has not progressed for the 'stall_timeout' is logged as a warning once,
+/// or fails the check if 'strict' is set. If 'only_volumes' is given, then only those volumes are
+/// checked.
+async fn check_rebuild_stalls(
+    rebuild_stalls: &mut HashMap<String, RebuildStall>,
+    stall_timeout: Duration,
+    strict: bool,
+    only_volumes: Option<&HashSet<String>>,
+    rest_client: &RestClientSet,
+    list_parallelism: usize,
+) -> Result<()> {
+    let mut rebuild_progress = volume_rebuild_progress(rest_client, list_parallelism).await?;
+    if let Some(only_volumes) = only_volumes {
+        rebuild_progress.retain(|volume_uuid, _| only_volumes.contains(volume_uuid));
+    }
+    rebuild_stalls.retain(|volume_uuid, _| rebuild_progress.contains_key(volume_uuid));
+
+    for (volume_uuid, progress) in rebuild_progress {
+        let stall = rebuild_stalls
+            .entry(volume_uuid.clone())
+            .or_insert_with(|| RebuildStall {
+                progress,
+                since: Instant::now(),
+                warned: false,
+            });
+        if stall.progress.ne(&progress) {
+            *stall = RebuildStall {
+                progress,
+                since: Instant::now(),
+                warned: false,
+            };
+            continue;
+        }
+
+        if stall.warned || stall.since.elapsed() < stall_timeout {
+            continue;
+        }
+        ensure!(
+            !strict,
+            RebuildStalled {
+                volume_uuid,
+                progress,
+                timeout: stall_timeout
+            }
+        );
+        warn!(
+            volume.uuid = %volume_uuid,
+            progress,
+            timeout = ?stall_timeout,
+            "Volume rebuild is making no progress"
+        );
+        stall.warned = true;
+    }
+
+    Ok(())
+}
+
+/// Wait for approval to continue the data-plane upgrade after the io-engine Pod on the canary Node
+/// has been restarted and verified. An approval which is left over from before the pause, i.e. a
+/// 'resume' key already set to "true" or a SIGUSR1 received earlier, does not count.
+async fn wait_for_canary_approval(
+    canary_node_name: &str,
+    opts: &CliArgs,
+    namespace: &str,
+    k8s_client: &KubeClientSet,
+) -> Result<()> {
+    match opts.canary_resume() {
+        CanaryResume::ConfigMap => {
+            let configmap_name = opts.canary_configmap();
+            reset_canary_resume_key(configmap_name.as_str(), namespace, k8s_client).await?;
+            info!(
+                node.name = %canary_node_name,
+                configmap.name = %configmap_name,
+                "Paused the data-plane upgrade after the canary Node, set the key 'resume' to \
+                \"true\" in the ConfigMap to continue"
+            );
+            let sleep_duration = Duration::from_secs(10_u64);
+            loop {
+                let configmap = k8s_client
+                    .configmaps_api()
+                    .get_opt(configmap_name.as_str())
                     .await
-                    .context(StorageNodeUncordon {
-                        node_id: node_id.to_string(),
+                    .context(GetConfigMap {
+                        name: configmap_name.clone(),
+                        namespace: namespace.to_string(),
                     })?;
-
-                info!(node.id = %node_id,
-                    label = %DRAIN_FOR_UPGRADE,
-                    "Removed drain label from {PRODUCT} Node"
-                );
+                let resume = configmap
+                    .and_then(|configmap| configmap.data)
+                    .and_then(|data| data.get("resume").cloned());
+                if resume.as_deref().eq(&Some("true")) {
+                    break;
+                }
+                tokio::time::sleep(sleep_duration).await;
             }
-            _ => return Ok(()),
         }
-        tokio::time::sleep(sleep_duration).await;
+        CanaryResume::Signal => {
+            let waiter = canary_resume_waiter();
+            waiter.paused.store(true, Ordering::SeqCst);
+            info!(
+                node.name = %canary_node_name,
+                "Paused the data-plane upgrade after the canary Node, send SIGUSR1 to the \
+                upgrade-job to continue"
+            );
+            waiter.notify.notified().await;
+            waiter.paused.store(false, Ordering::SeqCst);
+        }
     }
+
+    info!(
+        node.name = %canary_node_name,
+        "Canary Node approved, continuing the data-plane upgrade"
+    );
+    Ok(())
 }
 
-/// Issue delete command on dataplane pods.
-async fn delete_data_plane_pod(
-    node_name: &str,
-    pod: &Pod,
+/// Removes the 'resume' key from the canary ConfigMap, if it is set to "true", so that a ConfigMap
+/// left over from an earlier upgrade does not approve the canary Node right away.
+async fn reset_canary_resume_key(
+    configmap_name: &str,
+    namespace: &str,
     k8s_client: &KubeClientSet,
 ) -> Result<()> {
-    // Deleting the io-engine pod
-    let pod_name = pod.name_any();
-    info!(
-        pod.name = pod_name.clone(),
-        node.name = node_name,
-        "Deleting the pod"
-    );
+    let configmap = k8s_client
+        .configmaps_api()
+        .get_opt(configmap_name)
+        .await
+        .context(GetConfigMap {
+            name: configmap_name.to_string(),
+            namespace: namespace.to_string(),
+        })?;
+    let resume = configmap
+        .and_then(|configmap| configmap.data)
+        .and_then(|data| data.get("resume").cloned());
+    if resume.as_deref().ne(&Some("true")) {
+        return Ok(());
+    }
+
+    let patch = serde_json::json!({
+        "data": {
+            "resume": null,
+        }
+    });
     k8s_client
-        .pods_api()
-        .delete(pod_name.as_str(), &DeleteParams::default())
+        .configmaps_api()
+        .patch(
+            configmap_name,
+            &PatchParams::default(),
+            &Patch::Merge(&patch),
+        )
         .await
-        .context(PodDelete {
-            name: pod_name,
-            node: node_name.to_string(),
+        .context(PatchConfigMap {
+            name: configmap_name.to_string(),
+            namespace: namespace.to_string(),
         })?;
-    info!(node.name = %node_name, "Pod delete command issued");
+    info!(
+        configmap.name = %configmap_name,
+        "Reset the canary resume key left over in the ConfigMap"
+    );
     Ok(())
 }
 
-/// Wait for all the node drain process to complete.
-async fn verify_data_plane_pod_is_running(
-    node_name: &str,
-    namespace: String,
-    upgrade_to_version: &String,
-    k8s_client: &KubeClientSet,
+/// Wait for the number of Degraded volumes to be at most 'max', for up to 'timeout'.
+async fn wait_for_degraded_volumes_below(
+    max: usize,
+    timeout: Duration,
+    rest_client: &RestClientSet,
+    list_parallelism: usize,
 ) -> Result<()> {
-    let duration = Duration::from_secs(5_u64);
-    // Validate the new pod is up and running
-    info!(node.name = %node_name, "Waiting for data-plane Pod to come to Ready state");
-    while !data_plane_pod_is_running(node_name, namespace.clone(), upgrade_to_version, k8s_client)
-        .await?
-    {
-        tokio::time::sleep(duration).await;
+    let sleep_duration = Duration::from_secs(30_u64);
+    let started_at = Instant::now();
+    loop {
+        let count = degraded_volume_count(rest_client, list_parallelism).await?;
+        if count <= max {
+            return Ok(());
+        }
+
+        ensure!(
+            started_at.elapsed() < timeout,
+            TooManyDegradedVolumes {
+                count,
+                max,
+                timeout
+            }
+        );
+        info!(
+            degraded_volumes = count,
+            max_degraded_volumes = max,
+            "Waiting for Degraded volumes to recover before restarting the next data-plane pod"
+        );
+        tokio::time::sleep(sleep_duration).await;
     }
-    Ok(())
 }
 
-/// Wait for the rebuild to complete if any.
-async fn wait_for_rebuild(node_name: &str, rest_client: &RestClientSet) -> Result<()> {
-    // Wait for 60 seconds for any rebuilds to kick in.
-    tokio::time::sleep(Duration::from_secs(60_u64)).await;
-
-    let mut result = RebuildResult::default();
+/// Wait for each of the volumes with replicas on the storage Node to have at least 'min' healthy
+/// replicas on the other Nodes, for up to 'timeout'.
+async fn wait_for_min_healthy_replicas(
+    node_id: &str,
+    min: usize,
+    timeout: Duration,
+    rest_client: &RestClientSet,
+    list_parallelism: usize,
+) -> Result<()> {
+    let sleep_duration = Duration::from_secs(30_u64);
+    let started_at = Instant::now();
     loop {
-        let rebuild = rebuild_result(rest_client, &mut result.discarded_volumes).await?;
-
-        if rebuild.rebuilding {
-            info!(node.name = %node_name, "Waiting for volume rebuilds to complete");
-            tokio::time::sleep(Duration::from_secs(10_u64)).await;
-        } else {
-            break;
+        let volumes =
+            volumes_below_min_healthy_replicas(node_id, min, rest_client, list_parallelism).await?;
+        if volumes.is_empty() {
+            return Ok(());
         }
+
+        ensure!(
+            started_at.elapsed() < timeout,
+            MinHealthyReplicas {
+                node_id,
+                volumes,
+                min,
+                timeout
+            }
+        );
+        info!(
+            node.id = %node_id,
+            ?volumes,
+            min_healthy_replicas = min,
+            "Waiting for volumes to have enough healthy replicas before the {PRODUCT} Node drain"
+        );
+        tokio::time::sleep(sleep_duration).await;
     }
-    info!(node.name = %node_name, "No volume rebuilds in progress");
+}
+
+/// Verify that the pools on the storage Nodes other than this one have enough free space, in
+/// total, for the replicas on this Node, so that the rebuilds which follow its drain do not stall
+/// for want of space.
+async fn verify_spare_capacity(node_id: &str, rest_client: &RestClientSet) -> Result<()> {
+    let required: u64 = with_rest_retries(|| rest_client.replicas_api().get_replicas())
+        .await
+        .context(ListStorageReplicas)?
+        .into_body()
+        .into_iter()
+        .filter(|replica| replica.node.eq(node_id))
+        .map(|replica| replica.size)
+        .sum();
+    if required == 0 {
+        return Ok(());
+    }
+
+    let spare: u64 = with_rest_retries(|| rest_client.pools_api().get_pools())
+        .await
+        .context(ListStoragePools)?
+        .into_body()
+        .into_iter()
+        .filter_map(|pool| pool.state)
+        .filter(|pool_state| pool_state.node.ne(node_id))
+        .map(|pool_state| pool_state.capacity.saturating_sub(pool_state.used))
+        .sum();
+
+    ensure!(
+        spare >= required,
+        InsufficientSpareCapacity {
+            node_id,
+            required,
+            spare,
+            shortfall: required - spare,
+        }
+    );
+    info!(
+        node.id = %node_id,
+        required_bytes = required,
+        spare_bytes = spare,
+        "Verified spare capacity for the {PRODUCT} Node drain"
+    );
+
     Ok(())
 }
 
-/// Issue the node drain command on the node.
-async fn drain_storage_node(node_id: &str, rest_client: &RestClientSet) -> Result<()> {
+/// Issue the node drain command on the node. A drain request which fails with any of the
+/// 'benign_errors' is not treated as a failure, the drain state of the node is checked again.
+/// A drain which does not complete within the 'volume_move_timeout' is cancelled, and the volumes
+/// which did not move off of the node are reported. The drain state is checked with an exponential
+/// backoff, which starts at 'poll_interval', or at 2 seconds, and is capped by '--drain-poll-max'.
+/// A node which is cordoned or drained other than by the upgrade is not drained.
+async fn drain_storage_node(
+    node_id: &str,
+    benign_errors: &[String],
+    volume_move_timeout: Option<Duration>,
+    poll_interval: Option<Duration>,
+    rest_client: &RestClientSet,
+    list_parallelism: usize,
+) -> Result<()> {
+    // The upgrade's drain is not layered over an operator's cordon or drain, e.g. for
+    // maintenance, so that the upgrade does not uncordon the node from under the operator.
+    ensure_storage_node_not_in_maintenance(node_id, rest_client).await?;
+
     let drain_label_for_upgrade: String = DRAIN_FOR_UPGRADE.to_string();
-    let sleep_duration = Duration::from_secs(5_u64);
+    let mut backoff = DrainPollBackoff::new(
+        poll_interval.unwrap_or(DRAIN_POLL_INITIAL),
+        drain_poll_max(),
+    );
+    let mut last_state: Option<&str> = None;
+    let started_at = Instant::now();
     loop {
-        let storage_node =
-            rest_client
-                .nodes_api()
-                .get_node(node_id)
-                .await
-                .context(GetStorageNode {
-                    node_id: node_id.to_string(),
-                })?;
+        let storage_node = with_rest_retries(|| rest_client.nodes_api().get_node(node_id))
+            .await
+            .context(GetStorageNode {
+                node_id: node_id.to_string(),
+            })?;
 
-        match storage_node
+        let cordon_drain_state = storage_node
             .into_body()
             .spec
             .ok_or(
@@ -291,14 +2873,41 @@ async fn drain_storage_node(node_id: &str, rest_client: &RestClientSet) -> Resul
                 }
                 .build(),
             )?
-            .cordondrainstate
-        {
+            .cordondrainstate;
+
+        // The checks are frequent again when the drain moves along, e.g. once it starts.
+        let state = cordon_drain_state_name(cordon_drain_state.as_ref());
+        if last_state.replace(state) != Some(state) {
+            backoff.reset();
+        }
+
+        match cordon_drain_state {
             Some(CordonDrainState::drainingstate(drain_state))
                 if drain_state.drainlabels.contains(&drain_label_for_upgrade) =>
             {
-                info!(node.id = %node_id, "Waiting for {PRODUCT} Node drain to complete");
+                if let Some(timeout) = volume_move_timeout {
+                    if started_at.elapsed() >= timeout {
+                        let volumes =
+                            volumes_targeted_on_node(node_id, rest_client, list_parallelism)
+                                .await?;
+                        cancel_drain(node_id, rest_client).await?;
+                        return DrainTimedOut {
+                            node_id: node_id.to_string(),
+                            volumes,
+                            timeout,
+                        }
+                        .fail();
+                    }
+                }
+
+                let delay = backoff.next_delay();
+                info!(
+                    node.id = %node_id,
+                    next_check_in = ?delay,
+                    "Waiting for {PRODUCT} Node drain to complete"
+                );
                 // Wait for node drain to complete.
-                tokio::time::sleep(sleep_duration).await;
+                tokio::time::sleep(delay).await;
             }
             Some(CordonDrainState::drainedstate(drain_state))
                 if drain_state.drainlabels.contains(&drain_label_for_upgrade) =>
@@ -307,18 +2916,163 @@ async fn drain_storage_node(node_id: &str, rest_client: &RestClientSet) -> Resul
                 return Ok(());
             }
             _ => {
-                rest_client
+                match rest_client
                     .nodes_api()
                     .put_node_drain(node_id, DRAIN_FOR_UPGRADE)
                     .await
-                    .context(DrainStorageNode {
-                        node_id: node_id.to_string(),
-                    })?;
+                {
+                    Ok(_) => info!(node.id = %node_id, "Drain started for {PRODUCT} Node"),
+                    Err(error) if drain_error_is_benign(&error, benign_errors) => {
+                        info!(
+                            node.id = %node_id,
+                            %error,
+                            "Ignoring benign drain error for {PRODUCT} Node"
+                        );
+                        tokio::time::sleep(backoff.next_delay()).await;
+                    }
+                    Err(error) => {
+                        return Err(error).context(DrainStorageNode {
+                            node_id: node_id.to_string(),
+                        })
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// This is the time between the checks of a Node drain. It doubles after each check, up to the
+/// maximum, and goes back to the initial time when the drain state of the Node changes. A small
+/// random jitter is added, so that the checks of concurrent drains do not happen in lockstep.
+struct DrainPollBackoff {
+    initial: Duration,
+    max: Duration,
+    current: Duration,
+}
+
+impl DrainPollBackoff {
+    /// Creates the backoff, with the 'initial' time between checks, capped by 'max'.
+    fn new(initial: Duration, max: Duration) -> Self {
+        Self {
+            initial,
+            max: max.max(initial),
+            current: initial,
+        }
+    }
+
+    /// Goes back to the initial time between checks.
+    fn reset(&mut self) {
+        self.current = self.initial;
+    }
+
+    /// Returns the time to wait for before the next check, with up to 10% of it added as jitter.
+    fn next_delay(&mut self) -> Duration {
+        let delay = self.current;
+        self.current = self.current.saturating_mul(2).min(self.max);
+
+        let max_jitter_millis = (delay / 10).as_millis() as u64;
+        if max_jitter_millis == 0 {
+            return delay;
+        }
+        // A RandomState is seeded randomly, so the hash of an empty input is a random number.
+        let random = RandomState::new().build_hasher().finish();
+        delay + Duration::from_millis(random % (max_jitter_millis + 1))
+    }
+}
+
+/// Returns the name of the cordon or drain state of a storage Node.
+fn cordon_drain_state_name(state: Option<&CordonDrainState>) -> &'static str {
+    match state {
+        None => "schedulable",
+        Some(CordonDrainState::cordonedstate(_)) => "cordoned",
+        Some(CordonDrainState::drainingstate(_)) => "draining",
+        Some(CordonDrainState::drainedstate(_)) => "drained",
+    }
+}
 
-                info!(node.id = %node_id, "Drain started for {PRODUCT} Node");
+/// Fails if the storage Node has any cordon or drain labels other than those of the upgrade, i.e.
+/// it was cordoned or drained by someone else, e.g. by an operator for maintenance.
+async fn ensure_storage_node_not_in_maintenance(
+    node_id: &str,
+    rest_client: &RestClientSet,
+) -> Result<()> {
+    let storage_node = with_rest_retries(|| rest_client.nodes_api().get_node(node_id))
+        .await
+        .context(GetStorageNode {
+            node_id: node_id.to_string(),
+        })?;
+
+    let Some(state) = storage_node
+        .into_body()
+        .spec
+        .ok_or(
+            EmptyStorageNodeSpec {
+                node_id: node_id.to_string(),
             }
+            .build(),
+        )?
+        .cordondrainstate
+    else {
+        return Ok(());
+    };
+
+    let (state_name, drain_labels): (&str, &[String]) = match &state {
+        CordonDrainState::cordonedstate(_) => ("cordoned", &[]),
+        CordonDrainState::drainingstate(state) => ("draining", state.drainlabels.as_slice()),
+        CordonDrainState::drainedstate(state) => ("drained", state.drainlabels.as_slice()),
+    };
+    let labels: Vec<String> = cordon_labels(&state)
+        .iter()
+        .chain(drain_labels.iter())
+        .filter(|label| label.as_str() != CORDON_FOR_UPGRADE && label.as_str() != DRAIN_FOR_UPGRADE)
+        .cloned()
+        .collect();
+
+    ensure!(
+        labels.is_empty(),
+        StorageNodeInMaintenance {
+            node_id: node_id.to_string(),
+            state: state_name,
+            labels,
         }
+    );
+    Ok(())
+}
+
+/// Removes the upgrade drain label from a storage Node which is still draining, which stops the
+/// drain and makes the Node schedulable again.
+async fn cancel_drain(node_id: &str, rest_client: &RestClientSet) -> Result<()> {
+    rest_client
+        .nodes_api()
+        .delete_node_cordon(node_id, DRAIN_FOR_UPGRADE)
+        .await
+        .context(StorageNodeUncordon {
+            node_id: node_id.to_string(),
+        })?;
+
+    warn!(
+        node.id = %node_id,
+        label = %DRAIN_FOR_UPGRADE,
+        "Cancelled the drain of {PRODUCT} Node"
+    );
+    Ok(())
+}
+
+/// This is a predicate which is true if the storage Node drain request failed with an error
+/// response which contains any of the 'benign_errors', case-insensitively. Failures to make the
+/// request at all are never benign.
+fn drain_error_is_benign(
+    error: &openapi::tower::client::Error<openapi::models::RestJsonError>,
+    benign_errors: &[String],
+) -> bool {
+    if !matches!(error, openapi::tower::client::Error::Response(_)) {
+        return false;
     }
+
+    let error = error.to_string().to_lowercase();
+    benign_errors
+        .iter()
+        .any(|benign_error| error.contains(benign_error.to_lowercase().as_str()))
 }
 
 /// Validate if io-engine DaemonSet Pod is running.
@@ -328,85 +3082,437 @@ async fn data_plane_pod_is_running(
     upgrade_to_version: &String,
     k8s_client: &KubeClientSet,
 ) -> Result<bool> {
-    let node_name_pod_field = format!("spec.nodeName={node}");
-    let pod_label = format!("{IO_ENGINE_LABEL},{CHART_VERSION_LABEL_KEY}={upgrade_to_version}");
-    let io_engine_listparam = ListParams::default()
-        .labels(pod_label.as_str())
-        .fields(node_name_pod_field.as_str());
+    Ok(
+        nodes_io_engine_ready(&[node], namespace, upgrade_to_version, k8s_client)
+            .await?
+            .remove(node)
+            .unwrap_or(false),
+    )
+}
 
-    let pod_list: ObjectList<Pod> = k8s_client
-        .pods_api()
-        .list(&io_engine_listparam)
-        .await
-        .context(ListPodsWithLabelAndField {
-            label: pod_label,
-            field: node_name_pod_field,
-            namespace: namespace.clone(),
-        })?;
+/// Checks if the io-engine Pod of the upgrade target version on each of the Nodes is Ready. The
+/// Pods of all of the Nodes are listed together, in one request. A Node which does not have such a
+/// Pod is not ready, and a Node which has more than one is an error.
+pub(crate) async fn nodes_io_engine_ready(
+    nodes: &[&str],
+    namespace: String,
+    upgrade_to_version: &String,
+    k8s_client: &KubeClientSet,
+) -> Result<HashMap<String, bool>> {
+    let pod_label = format!("{IO_ENGINE_LABEL},{CHART_VERSION_LABEL_KEY}={upgrade_to_version}");
+    let pod_list: ObjectList<Pod> = match nodes {
+        // A single Node's Pods are selected by the Node's name.
+        [node] => {
+            let node_name_pod_field = format!("spec.nodeName={node}");
+            let io_engine_listparam = ListParams::default()
+                .labels(pod_label.as_str())
+                .fields(node_name_pod_field.as_str());
+            k8s_client
+                .list_pods(&io_engine_listparam)
+                .await
+                .context(ListPodsWithLabelAndField {
+                    label: pod_label,
+                    field: node_name_pod_field,
+                    namespace,
+                })?
+        }
+        _ => k8s_client
+            .list_pods(&ListParams::default().labels(pod_label.as_str()))
+            .await
+            .context(ListPodsWithLabel {
+                label: pod_label,
+                namespace,
+            })?,
+    };
 
-    if pod_list.items.is_empty() {
-        return Ok(false);
+    let mut node_pods: HashMap<&str, Vec<&Pod>> = HashMap::new();
+    for pod in pod_list.iter() {
+        let Some(node_name) = pod.spec.as_ref().and_then(|spec| spec.node_name.as_deref()) else {
+            continue;
+        };
+        node_pods.entry(node_name).or_default().push(pod);
     }
 
-    if pod_list.items.len() != 1 {
-        return TooManyIoEnginePods { node_name: node }.fail();
+    let mut nodes_ready: HashMap<String, bool> = HashMap::new();
+    for node in nodes {
+        let ready = match node_pods.get(node).map(Vec::as_slice) {
+            None | Some([]) => false,
+            Some([pod]) => pod_ready(pod, None),
+            Some(_) => return TooManyIoEnginePods { node_name: *node }.fail(),
+        };
+        nodes_ready.insert(node.to_string(), ready);
     }
 
-    Ok(all_pods_are_ready(pod_list))
+    Ok(nodes_ready)
+}
+
+/// Verify the control-plane is running before the io-engine Pod on the Node is restarted. If it
+/// does not come to be running within the timeout, the 'on_control_plane_unhealthy' action is
+/// taken.
+async fn ensure_control_plane_is_running(
+    context: &DataPlaneUpgradeContext<'_>,
+    node_name: &str,
+) -> Result<()> {
+    let opts = context.opts;
+    let components = opts.control_plane_components();
+    let verify = || {
+        verify_control_plane_is_running(
+            context.control_plane_namespace.to_string(),
+            context.control_plane_k8s_client,
+            context.upgrade_to_version,
+            components.as_slice(),
+            opts.min_ready_fraction(),
+            opts.control_plane_ready_timeout(),
+        )
+    };
+
+    let error = match verify().await {
+        Err(error @ Error::ControlPlaneReadyTimeout { .. }) => error,
+        result => return result,
+    };
+
+    match context.control_plane_unhealthy_action {
+        ControlPlaneUnhealthyAction::Abort => Err(error),
+        ControlPlaneUnhealthyAction::Pause => {
+            warn!(
+                node.name = %node_name,
+                %error,
+                "Pausing the data-plane upgrade until the control-plane is running"
+            );
+            remove_upgrade_cordons(node_name, context.rest_client).await?;
+            loop {
+                match verify().await {
+                    Ok(()) => break,
+                    Err(Error::ControlPlaneReadyTimeout { .. }) => {
+                        warn!("The control-plane is not running yet, the upgrade remains paused")
+                    }
+                    Err(error) => return Err(error),
+                }
+            }
+            if opts.cordon_strategy().eq(&CordonStrategy::Batch) {
+                cordon_storage_node(node_name, context.rest_client).await?;
+            }
+            info!(node.name = %node_name, "Resuming the data-plane upgrade");
+            Ok(())
+        }
+        ControlPlaneUnhealthyAction::Rollback => {
+            warn!(
+                node.name = %node_name,
+                %error,
+                "Rolling back the helm release, the control-plane is not running"
+            );
+            remove_upgrade_cordons(node_name, context.rest_client).await?;
+            HelmReleaseClient::builder()
+                .with_namespace(context.namespace)
+                .with_redacted_value_keys(opts.redact_value_keys())
+                .build()?
+                .rollback(opts.release_name(), None)
+                .await?;
+            ControlPlaneRolledBack {
+                namespace: context.control_plane_namespace,
+                release_name: opts.release_name(),
+            }
+            .fail()
+        }
+    }
 }
 
 async fn verify_control_plane_is_running(
     namespace: String,
     k8s_client: &KubeClientSet,
     upgrade_to_version: &String,
+    components: &[ControlPlaneComponent],
+    min_ready_fraction: f64,
+    timeout: Duration,
 ) -> Result<()> {
     let duration = Duration::from_secs(3_u64);
-    while !control_plane_is_running(namespace.clone(), k8s_client, upgrade_to_version).await? {
+    let started_at = Instant::now();
+    while !control_plane_is_running(
+        namespace.clone(),
+        k8s_client,
+        upgrade_to_version,
+        components,
+        min_ready_fraction,
+    )
+    .await?
+    {
+        ensure!(
+            started_at.elapsed() < timeout,
+            ControlPlaneReadyTimeout { namespace, timeout }
+        );
         tokio::time::sleep(duration).await;
     }
 
     Ok(())
 }
 
-/// Validate if control-plane pods are running -- etcd, agent-core, api-rest.
-async fn control_plane_is_running(
-    namespace: String,
+/// Verify that exactly one agent-core Pod is Ready. Pods which are being deleted are not counted.
+pub(crate) async fn verify_agent_core_leader(
+    namespace: &str,
     k8s_client: &KubeClientSet,
-    upgrade_to_version: &String,
-) -> Result<bool> {
-    let agent_core_selector_label =
-        format!("{AGENT_CORE_LABEL},{CHART_VERSION_LABEL_KEY}={upgrade_to_version}");
+) -> Result<()> {
     let pod_list: ObjectList<Pod> = k8s_client
-        .pods_api()
-        .list(&ListParams::default().labels(agent_core_selector_label.as_str()))
+        .list_pods(&ListParams::default().labels(AGENT_CORE_LABEL))
         .await
         .context(ListPodsWithLabel {
             label: AGENT_CORE_LABEL.to_string(),
-            namespace: namespace.clone(),
+            namespace: namespace.to_string(),
         })?;
-    let core_is_ready = all_pods_are_ready(pod_list);
 
-    let api_rest_selector_label =
-        format!("{API_REST_LABEL},{CHART_VERSION_LABEL_KEY}={upgrade_to_version}");
-    let pod_list: ObjectList<Pod> = k8s_client
-        .pods_api()
-        .list(&ListParams::default().labels(api_rest_selector_label.as_str()))
-        .await
-        .context(ListPodsWithLabel {
-            label: API_REST_LABEL.to_string(),
-            namespace: namespace.clone(),
-        })?;
-    let rest_is_ready = all_pods_are_ready(pod_list);
+    let ready_pods: Vec<String> = pod_list
+        .iter()
+        .filter(|pod| pod.metadata.deletion_timestamp.is_none() && pod_is_ready(pod))
+        .map(ResourceExt::name_any)
+        .collect();
 
-    let pod_list: ObjectList<Pod> = k8s_client
-        .pods_api()
-        .list(&ListParams::default().labels(ETCD_LABEL))
-        .await
-        .context(ListPodsWithLabel {
-            label: ETCD_LABEL.to_string(),
-            namespace: namespace.clone(),
-        })?;
-    let etcd_is_ready = all_pods_are_ready(pod_list);
+    ensure!(!ready_pods.is_empty(), NoAgentCoreLeader { namespace });
+    ensure!(
+        ready_pods.len() == 1,
+        MultipleAgentCoreLeaders {
+            namespace,
+            pods: ready_pods
+        }
+    );
+
+    info!(pod.name = %ready_pods[0], "Verified a single agent-core leader");
+    Ok(())
+}
+
+/// Returns the names of the Pods of the control-plane components which are not Ready, whichever
+/// version they are of.
+pub(crate) async fn control_plane_unready_pods(
+    namespace: &str,
+    k8s_client: &KubeClientSet,
+    components: &[ControlPlaneComponent],
+) -> Result<Vec<String>> {
+    let mut unready_pods: Vec<String> = Vec::new();
+    for component in components {
+        let label = match component {
+            ControlPlaneComponent::AgentCore => AGENT_CORE_LABEL,
+            ControlPlaneComponent::Rest => API_REST_LABEL,
+            ControlPlaneComponent::Etcd => ETCD_LABEL,
+        };
+        let pod_list: ObjectList<Pod> = k8s_client
+            .list_pods(&ListParams::default().labels(label))
+            .await
+            .context(ListPodsWithLabel {
+                label: label.to_string(),
+                namespace: namespace.to_string(),
+            })?;
+
+        unready_pods.extend(
+            pod_list
+                .iter()
+                .filter(|pod| !pod_is_ready(pod))
+                .map(ResourceExt::name_any),
+        );
+    }
+
+    Ok(unready_pods)
+}
+
+/// Validate if the control-plane pods of the components are running -- any of etcd, agent-core,
+/// api-rest. Each of the components is considered to be running if at least 'min_ready_fraction'
+/// of its Pods are Ready.
+async fn control_plane_is_running(
+    namespace: String,
+    k8s_client: &KubeClientSet,
+    upgrade_to_version: &String,
+    components: &[ControlPlaneComponent],
+    min_ready_fraction: f64,
+) -> Result<bool> {
+    for component in components {
+        let (label, selector_label) = match component {
+            ControlPlaneComponent::AgentCore => (
+                AGENT_CORE_LABEL,
+                format!("{AGENT_CORE_LABEL},{CHART_VERSION_LABEL_KEY}={upgrade_to_version}"),
+            ),
+            ControlPlaneComponent::Rest => (
+                API_REST_LABEL,
+                format!("{API_REST_LABEL},{CHART_VERSION_LABEL_KEY}={upgrade_to_version}"),
+            ),
+            ControlPlaneComponent::Etcd => (ETCD_LABEL, ETCD_LABEL.to_string()),
+        };
+        let pod_list: ObjectList<Pod> = k8s_client
+            .list_pods(&ListParams::default().labels(selector_label.as_str()))
+            .await
+            .context(ListPodsWithLabel {
+                label: label.to_string(),
+                namespace: namespace.clone(),
+            })?;
+
+        if !enough_pods_are_ready(pod_list, min_ready_fraction) {
+            return Ok(false);
+        }
+    }
+
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use k8s_openapi::{
+        api::core::v1::{
+            Affinity, NodeAffinity, NodeSelector, NodeSelectorRequirement, NodeSelectorTerm,
+            PodSpec,
+        },
+        apimachinery::pkg::apis::meta::v1::ObjectMeta,
+    };
+    use std::collections::BTreeMap;
+
+    fn io_engine_pod(chart_version: &str) -> Pod {
+        Pod {
+            metadata: ObjectMeta {
+                name: Some("io-engine-abcde".to_string()),
+                uid: Some("pod-uid".to_string()),
+                labels: Some(BTreeMap::from([(
+                    CHART_VERSION_LABEL_KEY.to_string(),
+                    chart_version.to_string(),
+                )])),
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    }
+
+    /// This is an io-engine Pod whose NodeAffinity selects 'affinity_node' by name, as it would
+    /// for a DaemonSet Pod, but which is bound to 'bound_node'.
+    fn replacement_pod(uid: &str, affinity_node: &str, bound_node: &str) -> Pod {
+        Pod {
+            metadata: ObjectMeta {
+                name: Some(format!("io-engine-{uid}")),
+                uid: Some(uid.to_string()),
+                ..Default::default()
+            },
+            spec: Some(PodSpec {
+                node_name: Some(bound_node.to_string()),
+                affinity: Some(Affinity {
+                    node_affinity: Some(NodeAffinity {
+                        required_during_scheduling_ignored_during_execution: Some(NodeSelector {
+                            node_selector_terms: vec![NodeSelectorTerm {
+                                match_fields: Some(vec![NodeSelectorRequirement {
+                                    key: "metadata.name".to_string(),
+                                    operator: "In".to_string(),
+                                    values: Some(vec![affinity_node.to_string()]),
+                                }]),
+                                ..Default::default()
+                            }],
+                        }),
+                        ..Default::default()
+                    }),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn replacement_pod_on_another_node_is_rejected() {
+        let pod = replacement_pod("new", "node-a", "node-b");
+        assert!(pod_affinity_selects_node(&pod, "node-a"));
+        assert!(!pod_affinity_selects_node(&pod, "node-b"));
+
+        let result = verify_replacement_pods_node(&[pod], "node-a", "old");
+        assert!(matches!(
+            result,
+            Err(Error::PodRescheduledUnexpectedly {
+                ref expected_node,
+                ref actual_node,
+                ..
+            }) if expected_node == "node-a" && actual_node == "node-b"
+        ));
+    }
+
+    #[test]
+    fn replacement_pod_on_its_node_is_accepted() {
+        let pods = [
+            replacement_pod("new", "node-a", "node-a"),
+            // The deleted Pod is not the replacement.
+            replacement_pod("old", "node-a", "node-b"),
+        ];
+        assert!(verify_replacement_pods_node(&pods, "node-a", "old").is_ok());
+    }
+
+    #[test]
+    fn rebuild_during_the_quiet_period_restarts_it() {
+        let quiet_period = Duration::from_secs(60);
+        let start = Instant::now();
+        let mut quiet = RebuildQuietPeriod::new(quiet_period);
+
+        assert_eq!(
+            quiet.observe(false, start),
+            QuietPeriodState::Quiet {
+                remaining: quiet_period
+            }
+        );
+        assert_eq!(
+            quiet.observe(false, start + Duration::from_secs(30)),
+            QuietPeriodState::Quiet {
+                remaining: Duration::from_secs(30)
+            }
+        );
+        assert_eq!(
+            quiet.observe(true, start + Duration::from_secs(40)),
+            QuietPeriodState::Rebuilding { restarted: true }
+        );
+        assert_eq!(
+            quiet.observe(true, start + Duration::from_secs(50)),
+            QuietPeriodState::Rebuilding { restarted: false }
+        );
+        // The quiet period is counted again from the first poll without a rebuild.
+        assert_eq!(
+            quiet.observe(false, start + Duration::from_secs(70)),
+            QuietPeriodState::Quiet {
+                remaining: quiet_period
+            }
+        );
+        assert_eq!(
+            quiet.observe(false, start + Duration::from_secs(100)),
+            QuietPeriodState::Quiet {
+                remaining: Duration::from_secs(30)
+            }
+        );
+        assert_eq!(
+            quiet.observe(false, start + Duration::from_secs(130)),
+            QuietPeriodState::Passed
+        );
+    }
 
-    Ok(core_is_ready && rest_is_ready && etcd_is_ready)
+    #[test]
+    fn no_quiet_period_passes_at_once() {
+        let mut quiet = RebuildQuietPeriod::new(Duration::ZERO);
+        assert_eq!(
+            quiet.observe(false, Instant::now()),
+            QuietPeriodState::Passed
+        );
+    }
+
+    #[test]
+    fn pod_delete_is_skipped_if_the_pod_is_gone() {
+        assert_eq!(
+            pod_delete_decision(None, "2.4.0"),
+            PodDeleteDecision::AlreadyReplaced
+        );
+    }
+
+    #[test]
+    fn pod_delete_is_skipped_if_the_pod_is_on_target() {
+        assert_eq!(
+            pod_delete_decision(Some(io_engine_pod("2.4.0")), "2.4.0"),
+            PodDeleteDecision::AlreadyOnTarget
+        );
+    }
+
+    #[test]
+    fn outdated_pod_is_deleted() {
+        assert_eq!(
+            pod_delete_decision(Some(io_engine_pod("2.3.0")), "2.4.0"),
+            PodDeleteDecision::Delete {
+                pod_uid: Some("pod-uid".to_string())
+            }
+        );
+    }
 }
@@ -0,0 +1,277 @@
+use crate::{
+    common::{
+        error::{AcquireUpgradeLease, Result, UpgradeAlreadyInProgress},
+        kube_client::KubeClientSet,
+    },
+    shutdown::shutdown,
+};
+use k8s_openapi::{
+    api::coordination::v1::{Lease, LeaseSpec},
+    apimachinery::pkg::apis::meta::v1::{MicroTime, ObjectMeta},
+    chrono::{Duration as ChronoDuration, Utc},
+};
+use kube::{
+    api::{Api, DeleteParams, PostParams, Preconditions},
+    ResourceExt,
+};
+use snafu::ResultExt;
+use std::time::{Duration, Instant};
+use tokio::task::JoinHandle;
+use tracing::{error, info, warn};
+
+/// This is the time for which the upgrade Lease is held without being renewed. Another upgrade Job
+/// may take over the Lease after this.
+const LEASE_DURATION: Duration = Duration::from_secs(30);
+
+/// This is the time between the renewals of the upgrade Lease.
+const LEASE_RENEW_INTERVAL: Duration = Duration::from_secs(10);
+
+/// This is a Kubernetes Lease which makes sure that only one upgrade Job at a time upgrades a helm
+/// release. The Lease is renewed in the background while this is held. The renewals stop when
+/// this is dropped, so a Lease which is not released expires on its own.
+pub(crate) struct UpgradeLock {
+    name: String,
+    holder: String,
+    leases_api: Api<Lease>,
+    renew_handle: JoinHandle<()>,
+}
+
+impl UpgradeLock {
+    /// Acquires the upgrade Lease of the helm release for this Pod. This fails if the Lease is held
+    /// by another Pod, and has not expired.
+    pub(crate) async fn acquire(
+        release_name: &str,
+        namespace: &str,
+        pod_name: &str,
+    ) -> Result<Self> {
+        let k8s_client = KubeClientSet::builder()
+            .with_namespace(namespace)
+            .build()
+            .await?;
+        let leases_api = k8s_client.leases_api().clone();
+        let name = format!("{release_name}-upgrade");
+        let error_context = AcquireUpgradeLease {
+            name: name.clone(),
+            namespace: namespace.to_string(),
+        };
+
+        let acquired_at = Instant::now();
+        let now = MicroTime(Utc::now());
+        let spec = LeaseSpec {
+            holder_identity: Some(pod_name.to_string()),
+            lease_duration_seconds: Some(LEASE_DURATION.as_secs() as i32),
+            acquire_time: Some(now.clone()),
+            renew_time: Some(now),
+            ..Default::default()
+        };
+
+        let result = match leases_api
+            .get_opt(name.as_str())
+            .await
+            .context(error_context.clone())?
+        {
+            Some(lease) => {
+                if let Some(holder) = live_lease_holder(&lease) {
+                    if holder.ne(pod_name) {
+                        return UpgradeAlreadyInProgress {
+                            holder,
+                            lease: name,
+                        }
+                        .fail();
+                    }
+                }
+                // The resourceVersion of the Lease fails the replace, if another Pod has taken the
+                // Lease over since it was read.
+                let lease = Lease {
+                    metadata: lease.metadata,
+                    spec: Some(spec),
+                };
+                leases_api
+                    .replace(name.as_str(), &PostParams::default(), &lease)
+                    .await
+            }
+            None => {
+                let lease = Lease {
+                    metadata: ObjectMeta {
+                        name: Some(name.clone()),
+                        ..Default::default()
+                    },
+                    spec: Some(spec),
+                };
+                leases_api.create(&PostParams::default(), &lease).await
+            }
+        };
+
+        match result {
+            Ok(_) => {}
+            // Another Pod has created or taken over the Lease at the same time.
+            Err(kube::Error::Api(response)) if response.code == 409 => {
+                let holder = leases_api
+                    .get_opt(name.as_str())
+                    .await
+                    .context(error_context)?
+                    .and_then(|lease| live_lease_holder(&lease))
+                    .unwrap_or_else(|| "unknown".to_string());
+                return UpgradeAlreadyInProgress {
+                    holder,
+                    lease: name,
+                }
+                .fail();
+            }
+            Err(error) => return Err(error).context(error_context),
+        }
+
+        info!(lease.name = %name, holder = %pod_name, "Acquired the upgrade Lease");
+        Ok(Self {
+            renew_handle: tokio::spawn(renew_lease(
+                leases_api.clone(),
+                name.clone(),
+                pod_name.to_string(),
+                acquired_at,
+            )),
+            name,
+            holder: pod_name.to_string(),
+            leases_api,
+        })
+    }
+
+    /// Releases the upgrade Lease, by deleting it, unless another Pod has taken it over. Failing
+    /// to release the Lease does not fail the upgrade, it is only logged. The Lease then expires
+    /// on its own.
+    pub(crate) async fn release(self) {
+        self.renew_handle.abort();
+
+        let lease = match self.leases_api.get_opt(self.name.as_str()).await {
+            Ok(Some(lease)) => lease,
+            Ok(None) => return,
+            Err(error) => {
+                error!(%error, lease.name = %self.name, "Failed to release the upgrade Lease");
+                return;
+            }
+        };
+        let holder = lease
+            .spec
+            .as_ref()
+            .and_then(|spec| spec.holder_identity.as_deref());
+        if holder != Some(self.holder.as_str()) {
+            warn!(
+                lease.name = %self.name,
+                holder = ?holder,
+                "Not releasing the upgrade Lease, it is held by another Pod"
+            );
+            return;
+        }
+
+        let delete_params = DeleteParams {
+            preconditions: Some(Preconditions {
+                resource_version: lease.resource_version(),
+                uid: lease.uid(),
+            }),
+            ..Default::default()
+        };
+        match self
+            .leases_api
+            .delete(self.name.as_str(), &delete_params)
+            .await
+        {
+            Ok(_) => info!(lease.name = %self.name, "Released the upgrade Lease"),
+            Err(error) => {
+                error!(%error, lease.name = %self.name, "Failed to release the upgrade Lease")
+            }
+        }
+    }
+}
+
+impl Drop for UpgradeLock {
+    fn drop(&mut self) {
+        self.renew_handle.abort();
+    }
+}
+
+/// Renews the upgrade Lease every LEASE_RENEW_INTERVAL, until the task is aborted. A failed renewal
+/// is logged, and tried again at the next interval. The upgrade is stopped if the Lease has been
+/// taken over by another Pod, or once the Lease has expired, i.e. LEASE_DURATION has passed since
+/// it was last renewed, so that two upgrade Jobs do not drain the storage Nodes at the same time.
+async fn renew_lease(leases_api: Api<Lease>, name: String, holder: String, acquired_at: Instant) {
+    let mut renewed_at = acquired_at;
+    let mut failures = 0_u32;
+    loop {
+        tokio::time::sleep(LEASE_RENEW_INTERVAL).await;
+        // The renew time written to the Lease is no earlier than this.
+        let renewing_at = Instant::now();
+        match renew_lease_once(&leases_api, name.as_str(), holder.as_str()).await {
+            Ok(LeaseRenewal::Renewed) => {
+                renewed_at = renewing_at;
+                failures = 0;
+            }
+            Ok(LeaseRenewal::Lost { holder }) => {
+                error!(
+                    lease.name = %name,
+                    holder = ?holder,
+                    "The upgrade Lease is no longer held by this Pod, stopping the upgrade"
+                );
+                shutdown().cancel();
+                return;
+            }
+            Err(error) => {
+                failures += 1;
+                warn!(%error, lease.name = %name, failures, "Failed to renew the upgrade Lease");
+                // Another upgrade Job may take over the Lease once it has expired.
+                if renewed_at.elapsed() >= LEASE_DURATION {
+                    error!(
+                        lease.name = %name,
+                        "The upgrade Lease has expired without being renewed, stopping the upgrade"
+                    );
+                    shutdown().cancel();
+                    return;
+                }
+            }
+        }
+    }
+}
+
+/// This is the outcome of an attempt to renew the upgrade Lease.
+enum LeaseRenewal {
+    Renewed,
+    /// The Lease is gone or has been taken over. This carries the new holder, if any.
+    Lost {
+        holder: Option<String>,
+    },
+}
+
+/// Renews the upgrade Lease, if it is still held by the holder. The Lease is read and replaced, so
+/// that the resourceVersion fails the renewal if another Pod takes the Lease over in between.
+async fn renew_lease_once(
+    leases_api: &Api<Lease>,
+    name: &str,
+    holder: &str,
+) -> Result<LeaseRenewal, kube::Error> {
+    let Some(mut lease) = leases_api.get_opt(name).await? else {
+        return Ok(LeaseRenewal::Lost { holder: None });
+    };
+    let spec = lease.spec.get_or_insert_with(Default::default);
+    if spec.holder_identity.as_deref() != Some(holder) {
+        return Ok(LeaseRenewal::Lost {
+            holder: spec.holder_identity.clone(),
+        });
+    }
+
+    spec.renew_time = Some(MicroTime(Utc::now()));
+    leases_api
+        .replace(name, &PostParams::default(), &lease)
+        .await?;
+    Ok(LeaseRenewal::Renewed)
+}
+
+/// Returns the holder of the Lease, if it has one and the Lease has not expired.
+fn live_lease_holder(lease: &Lease) -> Option<String> {
+    let spec = lease.spec.as_ref()?;
+    let holder = spec
+        .holder_identity
+        .clone()
+        .filter(|holder| !holder.is_empty())?;
+    let renewed_at = spec.renew_time.as_ref().or(spec.acquire_time.as_ref())?;
+    let duration = ChronoDuration::seconds(spec.lease_duration_seconds.unwrap_or_default().into());
+
+    (renewed_at.0 + duration > Utc::now()).then_some(holder)
+}
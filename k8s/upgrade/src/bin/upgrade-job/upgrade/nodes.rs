@@ -0,0 +1,182 @@
+use crate::{
+    common::{
+        constants::{IO_ENGINE_CONTAINER_NAME, IO_ENGINE_LABEL},
+        error::{ListPodsWithLabel, ListStorageNodes, Result, SerializeNodeStatus},
+        kube_client::KubeClientSet,
+        rest_client::RestClientSet,
+    },
+    opts::{CliArgs, OutputFormat},
+    upgrade::{
+        data_plane::selected_node_names,
+        plan::core_chart_image_tag,
+        utils::{list_volumes, pod_container_image_tag, with_rest_retries},
+    },
+};
+use kube::api::ListParams;
+use openapi::models::CordonDrainState;
+use serde::Serialize;
+use snafu::ResultExt;
+use std::collections::HashMap;
+
+/// This is the upgrade status of one storage Node.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct NodeStatus {
+    node: String,
+    current_tag: String,
+    target_tag: String,
+    cordon_state: String,
+    replicas: usize,
+}
+
+/// This prints the upgrade status of each of the storage Nodes, i.e. the current and the target
+/// io-engine image tags, the cordon or drain state and the number of replicas the Node hosts.
+/// If a Node label selector is set, only the selected storage Nodes are listed. This does not
+/// make any changes to the cluster.
+pub(crate) async fn list_nodes(
+    opts: &CliArgs,
+    image_tag: Option<String>,
+    output: OutputFormat,
+) -> Result<()> {
+    let target_tag = match image_tag {
+        Some(image_tag) => image_tag,
+        None => core_chart_image_tag(opts.core_chart_dir())?,
+    };
+
+    let namespace = opts.namespace();
+    let k8s_client = KubeClientSet::builder()
+        .with_namespace(namespace.as_str())
+        .build()
+        .await?;
+    let rest_client = RestClientSet::new_with_url(opts.rest_endpoint()?)?;
+
+    let pod_list = k8s_client
+        .list_pods(&ListParams::default().labels(IO_ENGINE_LABEL))
+        .await
+        .context(ListPodsWithLabel {
+            label: IO_ENGINE_LABEL.to_string(),
+            namespace,
+        })?;
+    let current_tags: HashMap<String, String> = pod_list
+        .iter()
+        .filter_map(|pod| {
+            let node_name = pod.spec.as_ref()?.node_name.clone()?;
+            let current_tag =
+                pod_container_image_tag(pod, IO_ENGINE_CONTAINER_NAME).unwrap_or_default();
+            Some((node_name, current_tag))
+        })
+        .collect();
+
+    let mut replica_counts: HashMap<String, usize> = HashMap::new();
+    for volume in list_volumes(&rest_client, opts.volume_list_parallelism()).await? {
+        for replica in volume.state.replica_topology.values() {
+            if let Some(node) = replica.node.as_ref() {
+                *replica_counts.entry(node.clone()).or_default() += 1;
+            }
+        }
+    }
+
+    let storage_nodes = with_rest_retries(|| rest_client.nodes_api().get_nodes(None))
+        .await
+        .context(ListStorageNodes)?
+        .into_body();
+    let selected_nodes = selected_node_names(opts.node_selector().as_deref(), &k8s_client).await?;
+
+    let statuses: Vec<NodeStatus> = storage_nodes
+        .into_iter()
+        .filter(|storage_node| {
+            selected_nodes.as_ref().map_or(true, |selected_nodes| {
+                selected_nodes.contains(&storage_node.id)
+            })
+        })
+        .map(|storage_node| {
+            let cordon_state = match storage_node
+                .spec
+                .as_ref()
+                .and_then(|spec| spec.cordondrainstate.as_ref())
+            {
+                None => "uncordoned",
+                Some(CordonDrainState::cordonedstate(_)) => "cordoned",
+                Some(CordonDrainState::drainingstate(_)) => "draining",
+                Some(CordonDrainState::drainedstate(_)) => "drained",
+            };
+            NodeStatus {
+                current_tag: current_tags
+                    .get(&storage_node.id)
+                    .cloned()
+                    .unwrap_or_default(),
+                target_tag: target_tag.clone(),
+                cordon_state: cordon_state.to_string(),
+                replicas: replica_counts
+                    .get(&storage_node.id)
+                    .copied()
+                    .unwrap_or_default(),
+                node: storage_node.id,
+            }
+        })
+        .collect();
+
+    match output {
+        OutputFormat::Table => print_node_statuses(statuses.as_slice()),
+        OutputFormat::Json => println!(
+            "{}",
+            serde_json::to_string_pretty(&statuses).context(SerializeNodeStatus)?
+        ),
+    }
+    Ok(())
+}
+
+/// This prints the storage Node statuses as a table.
+fn print_node_statuses(statuses: &[NodeStatus]) {
+    let headers = [
+        "NODE",
+        "CURRENT TAG",
+        "TARGET TAG",
+        "CORDON STATE",
+        "REPLICAS",
+    ];
+    let rows: Vec<[String; 5]> = statuses
+        .iter()
+        .map(|status| {
+            [
+                status.node.clone(),
+                status.current_tag.clone(),
+                status.target_tag.clone(),
+                status.cordon_state.clone(),
+                status.replicas.to_string(),
+            ]
+        })
+        .collect();
+
+    let mut widths = headers.map(str::len);
+    for row in rows.iter() {
+        for (width, cell) in widths.iter_mut().zip(row.iter()) {
+            *width = (*width).max(cell.len());
+        }
+    }
+
+    let print_row = |row: [&str; 5]| {
+        println!(
+            "{:<w0$}  {:<w1$}  {:<w2$}  {:<w3$}  {}",
+            row[0],
+            row[1],
+            row[2],
+            row[3],
+            row[4],
+            w0 = widths[0],
+            w1 = widths[1],
+            w2 = widths[2],
+            w3 = widths[3],
+        );
+    };
+    print_row(headers);
+    for row in rows.iter() {
+        print_row([
+            row[0].as_str(),
+            row[1].as_str(),
+            row[2].as_str(),
+            row[3].as_str(),
+            row[4].as_str(),
+        ]);
+    }
+}
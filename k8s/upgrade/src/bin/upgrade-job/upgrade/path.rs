@@ -2,19 +2,25 @@ use crate::{
     common::{
         constants::CHART_VERSION_LABEL_KEY,
         error::{
-            ListDeploymentsWithLabel, NoRestDeployment, NoVersionLabelInDeployment, ReadingFile,
-            Result, SemverParse, YamlParseBufferForUnsupportedVersion, YamlParseFromFile,
+            CrdStoredVersionsNotServed, GetCrd, ListDeploymentsWithLabel, NoRestDeployment,
+            NoVersionLabelInDeployment, ReadingFile, Result, SemverParse,
+            UpgradeJobVersionMismatch, YamlParseBufferForUnsupportedVersion, YamlParseFromFile,
         },
         kube_client::KubeClientSet,
     },
-    helm::chart::Chart,
+    helm::{chart::Chart, client::crds_from_dir},
 };
 use kube_client::{api::ListParams, ResourceExt};
 use semver::Version;
 use serde::Deserialize;
 use snafu::{ensure, ResultExt};
-use std::{fs, path::PathBuf};
-use utils::API_REST_LABEL;
+use std::{
+    cmp::Ordering,
+    fs,
+    path::{Path, PathBuf},
+};
+use tracing::{info, warn};
+use utils::{raw_version_str, API_REST_LABEL};
 
 /// Validates the upgrade path from 'from' Version to 'to' Version for the Core helm chart.
 pub(crate) fn is_valid_for_core_chart(from: &Version) -> Result<bool> {
@@ -25,16 +31,96 @@ pub(crate) fn is_valid_for_core_chart(from: &Version) -> Result<bool> {
     Ok(!unsupported_versions.contains(from))
 }
 
+/// This is a predicate which is true if the 'to' Version is at most a single minor version ahead
+/// of the 'from' Version. A major version upgrade is a single minor version upgrade, only if it
+/// is to the first minor version of the next major version.
+pub(crate) fn version_skew_is_supported(from: &Version, to: &Version) -> bool {
+    match to.major.cmp(&from.major) {
+        Ordering::Less => true,
+        Ordering::Equal => to.minor <= from.minor + 1,
+        Ordering::Greater => to.major == from.major + 1 && to.minor == 0,
+    }
+}
+
 /// Generate a semver::Version from the helm chart in local directory.
 pub(crate) fn version_from_chart_yaml_file(path: PathBuf) -> Result<Version> {
-    let values_yaml = fs::read(path.as_path()).context(ReadingFile {
+    Ok(chart_from_chart_yaml_file(path)?.version().clone())
+}
+
+/// Parse the Chart.yaml file of a helm chart.
+fn chart_from_chart_yaml_file(path: PathBuf) -> Result<Chart> {
+    let chart_yaml = fs::read(path.as_path()).context(ReadingFile {
         filepath: path.clone(),
     })?;
 
-    let to_chart: Chart = serde_yaml::from_slice(values_yaml.as_slice())
-        .context(YamlParseFromFile { filepath: path })?;
+    serde_yaml::from_slice(chart_yaml.as_slice()).context(YamlParseFromFile { filepath: path })
+}
+
+/// Validates that the CRDs shipped with the target helm chart still serve each of the versions
+/// which the cluster's existing custom resources are stored in. Custom resources stored in a
+/// version which is no longer served can no longer be read after the upgrade. Incompatibilities
+/// fail the validation if 'strict' is set, and are logged as warnings otherwise.
+pub(crate) async fn validate_crd_compatibility(
+    chart_dir: &Path,
+    k8s_client: &KubeClientSet,
+    strict: bool,
+) -> Result<()> {
+    let chart = chart_from_chart_yaml_file(chart_dir.join("Chart.yaml"))?;
+    let app_version = chart.app_version().unwrap_or_default().to_string();
+
+    for crd in crds_from_dir(chart_dir.join("crds"))? {
+        let name = crd.name_any();
+        let Some(existing_crd) = k8s_client
+            .crd_api()
+            .get_opt(name.as_str())
+            .await
+            .context(GetCrd { name: name.clone() })?
+        else {
+            // The CRD is new, so there are no custom resources to be compatible with.
+            continue;
+        };
+
+        let served_versions: Vec<&str> = crd
+            .spec
+            .versions
+            .iter()
+            .filter(|version| version.served)
+            .map(|version| version.name.as_str())
+            .collect();
+        let versions: Vec<String> = existing_crd
+            .status
+            .and_then(|status| status.stored_versions)
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|stored_version| !served_versions.contains(&stored_version.as_str()))
+            .collect();
+
+        if versions.is_empty() {
+            continue;
+        }
+
+        if strict {
+            return CrdStoredVersionsNotServed {
+                name,
+                app_version,
+                versions,
+            }
+            .fail();
+        }
+        warn!(
+            crd.name = %name,
+            app_version = %app_version,
+            ?versions,
+            "The target helm chart's CustomResourceDefinition does not serve versions which \
+            existing custom resources are stored in"
+        );
+    }
 
-    Ok(to_chart.version().clone())
+    info!(
+        app_version = %app_version,
+        "Validated the helm chart's CustomResourceDefinitions"
+    );
+    Ok(())
 }
 
 /// Generate a semver::Version from the CHART_VERSION_LABEL_KEY label on the Storage REST API
@@ -89,6 +175,50 @@ pub(crate) async fn version_from_rest_deployment_label(ns: &str) -> Result<Versi
     })
 }
 
+/// Verifies that this upgrade-job's version has the same major and minor versions as the target
+/// helm chart's version. A mismatch fails the upgrade if 'strict' is set, and is logged as a
+/// warning otherwise. Builds whose version is not a release version, e.g. development builds, are
+/// not verified.
+pub(crate) fn verify_job_version(chart_version: &str, strict: bool) -> Result<()> {
+    let job_version_str = raw_version_str().to_string();
+    let Ok(job_version) = Version::parse(job_version_str.trim_start_matches('v')) else {
+        info!(
+            job.version = %job_version_str,
+            "Skipping upgrade-job version verification, not a release version"
+        );
+        return Ok(());
+    };
+    let chart_version_parsed = Version::parse(chart_version).context(SemverParse {
+        version_string: chart_version.to_string(),
+    })?;
+
+    if job_version.major == chart_version_parsed.major
+        && job_version.minor == chart_version_parsed.minor
+    {
+        info!(
+            job.version = %job_version,
+            chart.version = %chart_version,
+            "Verified upgrade-job version"
+        );
+        return Ok(());
+    }
+
+    ensure!(
+        !strict,
+        UpgradeJobVersionMismatch {
+            job_version: job_version.to_string(),
+            chart_version
+        }
+    );
+    warn!(
+        job.version = %job_version,
+        chart.version = %chart_version,
+        "The upgrade-job version does not match the target helm chart version, use the \
+        upgrade-job image of the target version"
+    );
+    Ok(())
+}
+
 /// Struct to deserialize the unsupported version yaml.
 #[derive(Deserialize)]
 struct UnsupportedVersions {
@@ -0,0 +1,220 @@
+use crate::{
+    common::error::{InvalidPhaseTransition, Result},
+    metrics::metrics,
+    progress::progress,
+};
+use snafu::ensure;
+use std::{
+    fmt::{Display, Formatter},
+    future::Future,
+    time::{Duration, Instant},
+};
+use tracing::{error, info};
+
+/// These are the phases of an upgrade. An upgrade starts with the Preflight phase and ends in
+/// either of the Done or Failed phases.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub(crate) enum UpgradePhase {
+    /// Validations and the helm upgrade dry-run. The cluster is not modified in this phase.
+    Preflight,
+    /// The helm upgrade, which upgrades the control-plane components.
+    ControlPlane,
+    /// The controlled restart of the io-engine Pods.
+    DataPlane,
+    /// Post-upgrade verification.
+    Verify,
+    /// The upgrade completed successfully.
+    Done,
+    /// The upgrade failed.
+    Failed,
+}
+
+impl UpgradePhase {
+    /// This is a predicate for the phases which an upgrade cannot move out of.
+    pub(crate) fn is_terminal(&self) -> bool {
+        matches!(self, Self::Done | Self::Failed)
+    }
+
+    /// This is a predicate which decides if an upgrade in this phase may move to the next phase.
+    fn can_transition_to(&self, next: UpgradePhase) -> bool {
+        if self.is_terminal() {
+            return false;
+        }
+
+        matches!(
+            (self, next),
+            (Self::Preflight, Self::ControlPlane)
+                | (Self::ControlPlane, Self::DataPlane)
+                | (Self::DataPlane, Self::Verify)
+                | (Self::Verify, Self::Done)
+                | (_, Self::Failed)
+        )
+    }
+}
+
+impl Display for UpgradePhase {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let phase = match self {
+            Self::Preflight => "preflight",
+            Self::ControlPlane => "control-plane",
+            Self::DataPlane => "data-plane",
+            Self::Verify => "verify",
+            Self::Done => "done",
+            Self::Failed => "failed",
+        };
+        write!(f, "{phase}")
+    }
+}
+
+/// This records the outcome of an upgrade phase which has been run.
+#[derive(Clone, Debug)]
+pub(crate) struct PhaseRecord {
+    phase: UpgradePhase,
+    duration: Duration,
+    error: Option<String>,
+}
+
+impl PhaseRecord {
+    /// This is a getter for the phase which this record is for.
+    pub(crate) fn phase(&self) -> UpgradePhase {
+        self.phase
+    }
+
+    /// This is a getter for the time spent in the phase.
+    pub(crate) fn duration(&self) -> Duration {
+        self.duration
+    }
+
+    /// This is a getter for the error the phase failed with, if any.
+    pub(crate) fn error(&self) -> Option<&str> {
+        self.error.as_deref()
+    }
+}
+
+/// This records the time spent in each of the steps of the io-engine Pod restart on a Node.
+#[derive(Clone, Debug)]
+pub(crate) struct NodeTimingRecord {
+    node: String,
+    rebuild_wait: Duration,
+    drain: Duration,
+    restart: Duration,
+}
+
+impl NodeTimingRecord {
+    /// This creates a record of the time spent waiting on the rebuilds, draining the Node, and
+    /// from the io-engine Pod's deletion until the new Pod is Ready.
+    pub(crate) fn new(
+        node: &str,
+        rebuild_wait: Duration,
+        drain: Duration,
+        restart: Duration,
+    ) -> Self {
+        Self {
+            node: node.to_string(),
+            rebuild_wait,
+            drain,
+            restart,
+        }
+    }
+
+    /// This is a getter for the name of the Node which this record is for.
+    pub(crate) fn node(&self) -> &str {
+        self.node.as_str()
+    }
+
+    /// This returns the name of each of the steps and the time spent in it.
+    pub(crate) fn steps(&self) -> [(&'static str, Duration); 3] {
+        [
+            ("rebuild-wait", self.rebuild_wait),
+            ("drain", self.drain),
+            ("restart", self.restart),
+        ]
+    }
+}
+
+/// This drives the upgrade through its phases. It validates the transitions between phases, and
+/// records the entry, exit and errors for each phase.
+#[derive(Default)]
+pub(crate) struct UpgradePhaseDriver {
+    current: Option<UpgradePhase>,
+    records: Vec<PhaseRecord>,
+}
+
+impl UpgradePhaseDriver {
+    /// This creates a driver which is yet to enter the Preflight phase.
+    pub(crate) fn new() -> Self {
+        Self::default()
+    }
+
+    /// This is a getter for the records of the phases which have been run.
+    pub(crate) fn records(&self) -> &[PhaseRecord] {
+        self.records.as_slice()
+    }
+
+    /// This returns the phase which failed, if any.
+    pub(crate) fn failed_phase(&self) -> Option<UpgradePhase> {
+        self.records
+            .iter()
+            .find(|record| record.error.is_some())
+            .map(PhaseRecord::phase)
+    }
+
+    /// Moves the upgrade to the next phase, if the transition is a valid one.
+    fn transition(&mut self, next: UpgradePhase) -> Result<()> {
+        let allowed = match self.current {
+            None => next.eq(&UpgradePhase::Preflight),
+            Some(current) => current.can_transition_to(next),
+        };
+        ensure!(
+            allowed,
+            InvalidPhaseTransition {
+                from: self
+                    .current
+                    .map(|phase| phase.to_string())
+                    .unwrap_or_else(|| "none".to_string()),
+                to: next.to_string(),
+            }
+        );
+
+        info!(phase = %next, "Entering upgrade phase");
+        progress().set_phase(next);
+        metrics().set_upgrade_phase(next);
+        self.current = Some(next);
+        Ok(())
+    }
+
+    /// Enters the phase, runs the phase's work to completion and records the outcome. The upgrade
+    /// moves to the Failed phase if the phase's work returns an error.
+    pub(crate) async fn run<T, F>(&mut self, phase: UpgradePhase, work: F) -> Result<T>
+    where
+        F: Future<Output = Result<T>>,
+    {
+        self.transition(phase)?;
+
+        let started_at = Instant::now();
+        let result = work.await.map_err(|error| error.in_phase(phase, None));
+        let duration = started_at.elapsed();
+
+        self.records.push(PhaseRecord {
+            phase,
+            duration,
+            error: result.as_ref().err().map(ToString::to_string),
+        });
+
+        match result.as_ref() {
+            Ok(_) => info!(phase = %phase, ?duration, "Completed upgrade phase"),
+            Err(error) => {
+                error!(phase = %phase, %error, "Upgrade phase failed");
+                progress().set_phase(UpgradePhase::Failed);
+                self.current = Some(UpgradePhase::Failed);
+            }
+        }
+
+        result
+    }
+
+    /// Moves the upgrade to the Done phase.
+    pub(crate) fn finish(&mut self) -> Result<()> {
+        self.transition(UpgradePhase::Done)
+    }
+}
@@ -0,0 +1,145 @@
+use crate::{
+    common::{
+        constants::{CORE_CHART_NAME, IO_ENGINE_CONTAINER_NAME, IO_ENGINE_LABEL},
+        error::{ListPodsWithLabel, NoInputHelmChartDir, ReadingFile, Result, YamlParseFromFile},
+        kube_client::KubeClientSet,
+    },
+    helm::chart::CoreValues,
+    opts::CliArgs,
+    upgrade::{data_plane::selected_node_names, utils::pod_container_image_tag},
+};
+use kube::{api::ListParams, ResourceExt};
+use snafu::ResultExt;
+use std::{fs, path::PathBuf};
+
+/// This is the plan for one io-engine Pod.
+struct PodPlan {
+    pod_name: String,
+    node_name: String,
+    current_tag: String,
+    restart: bool,
+}
+
+/// This prints a table of the io-engine Pods, their Nodes and current image tags, and whether
+/// each of them would be restarted to upgrade to the target image tag. If a Node label selector
+/// is set, only the io-engine Pods on the selected Nodes are listed. This does not make any
+/// changes to the cluster.
+pub(crate) async fn plan_data_plane(opts: &CliArgs, image_tag: Option<String>) -> Result<()> {
+    let target_tag = match image_tag {
+        Some(image_tag) => image_tag,
+        None => core_chart_image_tag(opts.core_chart_dir())?,
+    };
+
+    let namespace = opts.namespace();
+    let k8s_client = KubeClientSet::builder()
+        .with_namespace(namespace.as_str())
+        .build()
+        .await?;
+
+    let pod_list = k8s_client
+        .list_pods(&ListParams::default().labels(IO_ENGINE_LABEL))
+        .await
+        .context(ListPodsWithLabel {
+            label: IO_ENGINE_LABEL.to_string(),
+            namespace,
+        })?;
+    // Only the io-engine Pods on the Nodes which match the Node label selector would be upgraded.
+    let selected_nodes = selected_node_names(opts.node_selector().as_deref(), &k8s_client).await?;
+
+    let plans: Vec<PodPlan> = pod_list
+        .iter()
+        .filter(|pod| match selected_nodes.as_ref() {
+            Some(selected_nodes) => pod
+                .spec
+                .as_ref()
+                .and_then(|spec| spec.node_name.as_ref())
+                .map_or(false, |node_name| selected_nodes.contains(node_name)),
+            None => true,
+        })
+        .map(|pod| {
+            let current_tag =
+                pod_container_image_tag(pod, IO_ENGINE_CONTAINER_NAME).unwrap_or_default();
+            PodPlan {
+                pod_name: pod.name_any(),
+                node_name: pod
+                    .spec
+                    .as_ref()
+                    .and_then(|spec| spec.node_name.clone())
+                    .unwrap_or_default(),
+                restart: current_tag.ne(&target_tag),
+                current_tag,
+            }
+        })
+        .collect();
+
+    print_plan(plans.as_slice(), target_tag.as_str());
+    Ok(())
+}
+
+/// This reads the container image tag from the values of the core Helm chart.
+pub(crate) fn core_chart_image_tag(chart_dir: Option<PathBuf>) -> Result<String> {
+    let chart_dir = chart_dir.ok_or(
+        NoInputHelmChartDir {
+            chart_name: CORE_CHART_NAME.to_string(),
+        }
+        .build(),
+    )?;
+
+    let values_filepath = chart_dir.join("values.yaml");
+    let values_yaml = fs::read(values_filepath.as_path()).context(ReadingFile {
+        filepath: values_filepath.clone(),
+    })?;
+    let values: CoreValues =
+        serde_yaml::from_slice(values_yaml.as_slice()).context(YamlParseFromFile {
+            filepath: values_filepath,
+        })?;
+
+    Ok(values.image_tag().to_string())
+}
+
+/// This prints the plan as a table.
+fn print_plan(plans: &[PodPlan], target_tag: &str) {
+    let headers = ["POD", "NODE", "CURRENT TAG", "RESTART"];
+    let rows: Vec<[String; 4]> = plans
+        .iter()
+        .map(|plan| {
+            [
+                plan.pod_name.clone(),
+                plan.node_name.clone(),
+                plan.current_tag.clone(),
+                if plan.restart { "yes" } else { "no" }.to_string(),
+            ]
+        })
+        .collect();
+
+    let mut widths = headers.map(str::len);
+    for row in rows.iter() {
+        for (width, cell) in widths.iter_mut().zip(row.iter()) {
+            *width = (*width).max(cell.len());
+        }
+    }
+
+    println!("Target io-engine image tag: {target_tag}");
+    println!(
+        "{:<w0$}  {:<w1$}  {:<w2$}  {}",
+        headers[0],
+        headers[1],
+        headers[2],
+        headers[3],
+        w0 = widths[0],
+        w1 = widths[1],
+        w2 = widths[2],
+    );
+    for row in rows {
+        println!(
+            "{:<w0$}  {:<w1$}  {:<w2$}  {}",
+            row[0],
+            row[1],
+            row[2],
+            row[3],
+            w0 = widths[0],
+            w1 = widths[1],
+            w2 = widths[2],
+        );
+    }
+}
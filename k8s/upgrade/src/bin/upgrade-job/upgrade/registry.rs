@@ -0,0 +1,188 @@
+use crate::common::error::{
+    ImageNotFound, ImageRegistryAuth, ImageRegistryClient, ImageRegistryRequest,
+    ImageRegistryResponse, InvalidImageReference, Result,
+};
+use reqwest::{
+    header::{ACCEPT, WWW_AUTHENTICATE},
+    Client, RequestBuilder, Response, StatusCode,
+};
+use serde::Deserialize;
+use snafu::{ensure, ResultExt};
+use std::time::Duration;
+use tracing::info;
+
+/// This is the registry which image references without a registry are pulled from.
+const DEFAULT_REGISTRY: &str = "docker.io";
+
+/// This is the host which serves the registry API for the default registry.
+const DEFAULT_REGISTRY_API_HOST: &str = "registry-1.docker.io";
+
+/// These are the manifest media types which the registry may serve an image as.
+const MANIFEST_MEDIA_TYPES: &str = "application/vnd.oci.image.index.v1+json, \
+    application/vnd.docker.distribution.manifest.list.v2+json, \
+    application/vnd.oci.image.manifest.v1+json, \
+    application/vnd.docker.distribution.manifest.v2+json";
+
+/// This is a container image reference, split into the parts needed to look it up in its registry.
+struct ImageReference {
+    registry: String,
+    repository: String,
+    reference: String,
+}
+
+impl ImageReference {
+    /// Parses an image reference of the form '[registry/]repository[:tag][@digest]'. The digest
+    /// takes precedence over the tag, and the tag defaults to 'latest'.
+    fn parse(image: &str) -> Result<Self> {
+        let (name, digest) = match image.split_once('@') {
+            Some((name, digest)) => (name, Some(digest)),
+            None => (image, None),
+        };
+
+        // A ':' after the last '/' separates the tag, any other is a registry port.
+        let (name, tag) = match name.rsplit_once(':') {
+            Some((repo, tag)) if !tag.contains('/') => (repo, Some(tag)),
+            _ => (name, None),
+        };
+
+        let (registry, repository) = match name.split_once('/') {
+            Some((host, path))
+                if host.contains('.') || host.contains(':') || host.eq("localhost") =>
+            {
+                (host.to_string(), path.to_string())
+            }
+            Some(_) => (DEFAULT_REGISTRY.to_string(), name.to_string()),
+            None => (DEFAULT_REGISTRY.to_string(), format!("library/{name}")),
+        };
+        ensure!(
+            !repository.is_empty(),
+            InvalidImageReference {
+                image: image.to_string()
+            }
+        );
+
+        Ok(Self {
+            registry,
+            repository,
+            reference: digest.or(tag).unwrap_or("latest").to_string(),
+        })
+    }
+
+    /// This is the URL of the image's manifest, in the registry API.
+    fn manifest_url(&self) -> String {
+        let host = if self.registry.eq(DEFAULT_REGISTRY) {
+            DEFAULT_REGISTRY_API_HOST
+        } else {
+            self.registry.as_str()
+        };
+        format!(
+            "https://{host}/v2/{}/manifests/{}",
+            self.repository, self.reference
+        )
+    }
+}
+
+/// This is the token response of a registry's token authentication service.
+#[derive(Deserialize)]
+struct TokenResponse {
+    token: Option<String>,
+    access_token: Option<String>,
+}
+
+/// Verifies that the container image exists in its registry, by requesting the image's manifest.
+/// Registries which require a token are sent an anonymous token request first. Failing to
+/// authenticate with the registry is reported apart from the image not being found.
+pub(crate) async fn verify_image_exists(image: &str) -> Result<()> {
+    let image_ref = ImageReference::parse(image)?;
+    let client = Client::builder()
+        .timeout(Duration::from_secs(30_u64))
+        .build()
+        .context(ImageRegistryClient)?;
+    let manifest_url = image_ref.manifest_url();
+    let manifest_request = || {
+        client
+            .head(manifest_url.as_str())
+            .header(ACCEPT, MANIFEST_MEDIA_TYPES)
+    };
+
+    let mut response = send(manifest_request(), image).await?;
+
+    if response.status().eq(&StatusCode::UNAUTHORIZED) {
+        if let Some(token) = anonymous_token(&client, &response, image).await? {
+            response = send(manifest_request().bearer_auth(token), image).await?;
+        }
+    }
+
+    let status = response.status();
+    match status {
+        status if status.is_success() => {
+            info!(%image, "Found container image in registry");
+            Ok(())
+        }
+        StatusCode::NOT_FOUND => ImageNotFound { image }.fail(),
+        StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => ImageRegistryAuth {
+            image,
+            status: status.as_u16(),
+        }
+        .fail(),
+        _ => ImageRegistryResponse {
+            image,
+            status: status.as_u16(),
+        }
+        .fail(),
+    }
+}
+
+/// Sends a request to the registry.
+async fn send(request: RequestBuilder, image: &str) -> Result<Response> {
+    request.send().await.context(ImageRegistryRequest { image })
+}
+
+/// Requests an anonymous pull token from the token service named in the registry's 'Bearer'
+/// challenge, if any.
+async fn anonymous_token(
+    client: &Client,
+    challenge_response: &Response,
+    image: &str,
+) -> Result<Option<String>> {
+    let Some(challenge) = challenge_response
+        .headers()
+        .get(WWW_AUTHENTICATE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+    else {
+        return Ok(None);
+    };
+
+    let params: Vec<(&str, &str)> = challenge
+        .split(',')
+        .filter_map(|param| param.trim().split_once('='))
+        .map(|(key, value)| (key, value.trim_matches('"')))
+        .collect();
+    let Some(realm) = params
+        .iter()
+        .find(|(key, _)| key.eq(&"realm"))
+        .map(|(_, value)| *value)
+    else {
+        return Ok(None);
+    };
+    let query: Vec<&(&str, &str)> = params
+        .iter()
+        .filter(|(key, _)| key.eq(&"service") || key.eq(&"scope"))
+        .collect();
+
+    let response = send(client.get(realm).query(&query), image).await?;
+    if !response.status().is_success() {
+        return ImageRegistryAuth {
+            image,
+            status: response.status().as_u16(),
+        }
+        .fail();
+    }
+
+    let token: TokenResponse = response
+        .json()
+        .await
+        .context(ImageRegistryRequest { image })?;
+    Ok(token.token.or(token.access_token))
+}
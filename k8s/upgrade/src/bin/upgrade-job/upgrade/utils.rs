@@ -1,17 +1,101 @@
 use crate::common::{
     constants::CHART_VERSION_LABEL_KEY,
     error::{
-        HelmChartVersionLabelHasNoValue, ListStorageVolumes, NoNamespaceInPod, Result, SemverParse,
+        HelmChartVersionLabelHasNoValue, ListStorageVolumes, NoNamespaceInPod, ReadingFile, Result,
+        SemverParse, YamlParseFromFile,
     },
-    rest_client::RestClientSet,
+    rest_client::{rest_max_retries, RestClientSet},
+};
+use futures::future::try_join_all;
+use k8s_openapi::{
+    api::core::v1::{Node, NodeSelectorRequirement, Pod, PodSpec, Taint, Toleration},
+    apimachinery::pkg::{api::resource::Quantity, apis::meta::v1::LabelSelector},
 };
-use k8s_openapi::api::core::v1::Pod;
 use kube::{api::ObjectList, ResourceExt};
-use openapi::models::{Volume, VolumeStatus};
+use openapi::models::{ReplicaState, RestJsonError, Volume, VolumeStatus};
 use semver::{Version, VersionReq};
+use serde::Deserialize;
 use snafu::ResultExt;
-use std::time::Duration;
-use tracing::{info, warn};
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    fs,
+    future::Future,
+    path::Path,
+    time::Duration,
+};
+use tracing::{debug, info, warn};
+
+/// This is the error of a storage REST API request.
+pub(crate) type RestError = openapi::tower::client::Error<RestJsonError>;
+
+/// This is a predicate which is true if the storage REST API request failed in a way which may
+/// pass if the request is made again, i.e. if the request could not be made, or if the API
+/// responded that it is unavailable for now.
+fn rest_error_is_retriable(error: &RestError) -> bool {
+    match error {
+        openapi::tower::client::Error::Request(_) => true,
+        openapi::tower::client::Error::Response(response) => {
+            matches!(response.status().as_u16(), 502 | 503 | 504)
+        }
+    }
+}
+
+/// Makes the storage REST API request, and retries it with exponential backoff if it fails with a
+/// retriable error, up to '--max-retries' times. The backoff starts at 1 second, and is at most 30
+/// seconds. Other errors are returned right away. This is meant for requests which make no
+/// changes, so that they are safe to make again.
+pub(crate) async fn with_rest_retries<T, F, Fut>(mut request: F) -> Result<T, RestError>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, RestError>>,
+{
+    let max_retries = rest_max_retries();
+    let mut backoff = Duration::from_secs(1_u64);
+    let mut attempt = 0_u32;
+    loop {
+        match request().await {
+            Err(error) if attempt < max_retries && rest_error_is_retriable(&error) => {
+                attempt += 1;
+                warn!(
+                    %error,
+                    attempt,
+                    max_retries,
+                    ?backoff,
+                    "Storage REST API request failed, retrying"
+                );
+                tokio::time::sleep(backoff).await;
+                backoff = (backoff * 2).min(Duration::from_secs(30_u64));
+            }
+            result => return result,
+        }
+    }
+}
+
+/// This decides if the rebuild of a volume is waited on before an io-engine Pod restart.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum RebuildWaitPolicy {
+    Wait,
+    Ignore,
+}
+
+/// Reads the per-volume rebuild wait policy file, and returns the UUIDs of the volumes whose
+/// rebuilds are not waited on.
+pub(crate) fn rebuild_ignored_volumes(policy_file: &Path) -> Result<HashSet<String>> {
+    let policy_yaml = fs::read(policy_file).context(ReadingFile {
+        filepath: policy_file.to_path_buf(),
+    })?;
+    let policies: HashMap<String, RebuildWaitPolicy> =
+        serde_yaml::from_slice(policy_yaml.as_slice()).context(YamlParseFromFile {
+            filepath: policy_file.to_path_buf(),
+        })?;
+
+    Ok(policies
+        .into_iter()
+        .filter(|(_, policy)| policy.eq(&RebuildWaitPolicy::Ignore))
+        .map(|(volume_uuid, _)| volume_uuid)
+        .collect())
+}
 
 /// Contains the Rebuild Results.
 #[derive(Default)]
@@ -20,13 +104,35 @@ pub(crate) struct RebuildResult {
     pub(crate) discarded_volumes: Vec<Volume>,
 }
 
-/// Function to check for any volume rebuild in progress across the cluster.
+/// Function to check for any volume rebuild in progress across the cluster. Up to
+/// 'list_parallelism' pages of volumes are fetched concurrently, when listing the volumes. The
+/// volumes in 'ignored_volumes' are discarded without checking for rebuilds. If 'only_volumes'
+/// is given, then the rebuilds of the other volumes are not checked for.
 pub(crate) async fn rebuild_result(
     rest_client: &RestClientSet,
     stale_volumes: &mut Vec<Volume>,
+    list_parallelism: usize,
+    ignored_volumes: &HashSet<String>,
+    only_volumes: Option<&HashSet<String>>,
 ) -> Result<RebuildResult> {
     loop {
-        let unhealthy_volumes = list_unhealthy_volumes(rest_client, stale_volumes).await?;
+        let (ignored, unhealthy_volumes): (Vec<Volume>, Vec<Volume>) =
+            list_unhealthy_volumes(rest_client, stale_volumes, list_parallelism)
+                .await?
+                .into_iter()
+                .filter(|volume| {
+                    only_volumes.map_or(true, |only_volumes| {
+                        only_volumes.contains(&volume.spec.uuid.to_string())
+                    })
+                })
+                .partition(|volume| ignored_volumes.contains(&volume.spec.uuid.to_string()));
+        for volume in ignored {
+            info!(
+                volume.uuid = %volume.spec.uuid,
+                "Not waiting on volume rebuild, as per the rebuild wait policy"
+            );
+            stale_volumes.push(volume);
+        }
         if unhealthy_volumes.is_empty() {
             break;
         }
@@ -62,37 +168,137 @@ pub(crate) async fn rebuild_result(
     })
 }
 
-/// Return the list of unhealthy volumes.
+/// Return the list of unhealthy volumes. The volumes are listed in pages, and up to
+/// 'list_parallelism' pages are fetched concurrently. The pages of each batch are aggregated
+/// before the next batch is fetched.
 pub(crate) async fn list_unhealthy_volumes(
     rest_client: &RestClientSet,
     discarded_volumes: &[Volume],
+    list_parallelism: usize,
+) -> Result<Vec<Volume>> {
+    let mut unhealthy_volumes: Vec<Volume> = list_volumes(rest_client, list_parallelism)
+        .await?
+        .into_iter()
+        .filter(|volume| {
+            matches!(
+                volume.state.status,
+                VolumeStatus::Faulted | VolumeStatus::Degraded
+            )
+        })
+        .collect();
+    unhealthy_volumes.retain(|v| !discarded_volumes.contains(v));
+    debug!(
+        unhealthy_volumes = unhealthy_volumes.len(),
+        "Listed unhealthy volumes"
+    );
+    Ok(unhealthy_volumes)
+}
+
+/// This is the maximum number of times the volumes are listed again, if they change while they
+/// are listed.
+const MAX_VOLUME_LIST_RESCANS: usize = 3;
+
+/// Return the list of all of the volumes. The volumes are listed in pages, and up to
+/// 'list_parallelism' pages are fetched concurrently. The pages of each batch are aggregated
+/// before the next batch is fetched.
+///
+/// The pages are fetched by offset, so volumes which are created or deleted while the volumes are
+/// listed may shift across the page boundaries, and be listed twice or be missed. The volumes are
+/// listed again, up to MAX_VOLUME_LIST_RESCANS times, if a page's next token does not follow from
+/// its offset or if a volume is listed twice. If the volumes keep changing, the last listing is
+/// returned with the volumes which are listed twice dropped.
+pub(crate) async fn list_volumes(
+    rest_client: &RestClientSet,
+    list_parallelism: usize,
 ) -> Result<Vec<Volume>> {
-    let mut unhealthy_volumes: Vec<Volume> = Vec::new();
+    let mut rescans = 0_usize;
+    loop {
+        let (volumes, consistent) = list_volume_pages(rest_client, list_parallelism).await?;
+        if consistent {
+            return Ok(volumes);
+        }
+        if rescans == MAX_VOLUME_LIST_RESCANS {
+            warn!(
+                rescans,
+                "The volumes kept changing while they were listed, the listing may be incomplete"
+            );
+            let mut uuids: HashSet<String> = HashSet::new();
+            return Ok(volumes
+                .into_iter()
+                .filter(|volume| uuids.insert(volume.spec.uuid.to_string()))
+                .collect());
+        }
+        rescans += 1;
+        debug!(
+            rescans,
+            "The volumes changed while they were listed, listing them again"
+        );
+    }
+}
+
+/// Lists the volumes, page by page. This also returns 'false' if the volumes may have changed
+/// between the pages, i.e. a page's next token is not the offset after its last volume, or a
+/// volume is listed more than once.
+async fn list_volume_pages(
+    rest_client: &RestClientSet,
+    list_parallelism: usize,
+) -> Result<(Vec<Volume>, bool)> {
+    let mut all_volumes: Vec<Volume> = Vec::new();
+    let mut consistent = true;
     // The number of volumes to get per request.
     let max_entries = 200;
+    let list_parallelism = list_parallelism.max(1);
     let mut starting_token = Some(0_isize);
+    let mut pages = 0_usize;
 
     // The last paginated request will set the `starting_token` to `None`.
-    while starting_token.is_some() {
-        let vols = rest_client
-            .volumes_api()
-            .get_volumes(max_entries, None, starting_token)
+    while let Some(token) = starting_token {
+        // The starting token is the offset of the first volume of the page, so the tokens of the
+        // pages which follow are known ahead of the requests.
+        let page_requests = (0 .. list_parallelism as isize).map(|page| {
+            with_rest_retries(move || {
+                rest_client.volumes_api().get_volumes(
+                    max_entries,
+                    None,
+                    Some(token + page * max_entries),
+                )
+            })
+        });
+        let vols_pages = try_join_all(page_requests)
             .await
             .context(ListStorageVolumes)?;
 
-        let volumes = vols.into_body();
-        starting_token = volumes.next_token;
-        for volume in volumes.entries {
-            match volume.state.status {
-                VolumeStatus::Faulted | VolumeStatus::Degraded => {
-                    unhealthy_volumes.push(volume);
-                }
-                _ => continue,
+        starting_token = None;
+        for (page, vols) in vols_pages.into_iter().enumerate() {
+            let volumes = vols.into_body();
+            pages += 1;
+            let offset = token + page as isize * max_entries;
+            if let Some(next_token) = volumes.next_token {
+                consistent &= next_token == offset + volumes.entries.len() as isize;
+            }
+            all_volumes.extend(volumes.entries);
+
+            // Pages after the last one are empty, and have no next token.
+            starting_token = volumes.next_token;
+            if starting_token.is_none() {
+                break;
             }
         }
     }
-    unhealthy_volumes.retain(|v| !discarded_volumes.contains(v));
-    Ok(unhealthy_volumes)
+
+    let uuids: HashSet<String> = all_volumes
+        .iter()
+        .map(|volume| volume.spec.uuid.to_string())
+        .collect();
+    consistent &= uuids.len() == all_volumes.len();
+
+    debug!(
+        pages,
+        volumes = all_volumes.len(),
+        consistent,
+        "Listed volumes"
+    );
+    Ok((all_volumes, consistent))
 }
 
 /// Count of number of replica rebuilding.
@@ -116,45 +322,363 @@ pub(crate) async fn replica_rebuild_count(volume: Volume) -> i32 {
     rebuild_count
 }
 
-/// This function returns 'true' only if all of the containers in the Pods contained in the
-/// ObjectList<Pod> have their Ready status.condition value set to true.
-pub(crate) fn all_pods_are_ready(pod_list: ObjectList<Pod>) -> bool {
+/// Returns the rebuild progress of each of the volumes with rebuilding replicas, keyed by the
+/// volume UUID. The progress of a volume is the sum of the progress percentages of its rebuilding
+/// replicas, so that it grows as long as any of its rebuilds progress.
+pub(crate) async fn volume_rebuild_progress(
+    rest_client: &RestClientSet,
+    list_parallelism: usize,
+) -> Result<HashMap<String, i64>> {
+    Ok(list_unhealthy_volumes(rest_client, &[], list_parallelism)
+        .await?
+        .into_iter()
+        .filter_map(|volume| {
+            let progress: Vec<i64> = volume
+                .state
+                .target
+                .as_ref()?
+                .children
+                .iter()
+                .filter_map(|child| child.rebuild_progress.map(|progress| progress as i64))
+                .collect();
+            (!progress.is_empty()).then(|| (volume.spec.uuid.to_string(), progress.iter().sum()))
+        })
+        .collect())
+}
+
+/// This function returns 'true' only if the Pod is Ready. If a container is named, then only that
+/// container of the Pod has to be ready.
+pub(crate) fn pod_ready(pod: &Pod, container: Option<&str>) -> bool {
+    let Some(container) = container else {
+        return pod_is_ready(pod);
+    };
+
+    pod.status
+        .as_ref()
+        .and_then(|status| status.container_statuses.as_ref())
+        .and_then(|container_statuses| {
+            container_statuses
+                .iter()
+                .find(|container_status| container_status.name.eq(container))
+        })
+        .map_or(false, |container_status| container_status.ready)
+}
+
+/// This function returns 'true' if the fraction of Pods in the ObjectList<Pod> which have their
+/// Ready status.condition value set to true is at least 'min_ready_fraction'.
+pub(crate) fn enough_pods_are_ready(pod_list: ObjectList<Pod>, min_ready_fraction: f64) -> bool {
+    let total = pod_list.items.len();
+    if total == 0 {
+        return true;
+    }
+
+    let ready = pod_list.iter().filter(|pod| pod_is_ready(pod)).count();
+    (ready as f64 / total as f64) >= min_ready_fraction
+}
+
+/// This function returns 'true' if any of the Pod's containers is waiting to be restarted after
+/// repeatedly crashing, i.e. it is in CrashLoopBackOff.
+pub(crate) fn pod_is_crashlooping(pod: &Pod) -> bool {
+    pod.status
+        .as_ref()
+        .and_then(|status| status.container_statuses.as_ref())
+        .map_or(false, |container_statuses| {
+            container_statuses.iter().any(|container_status| {
+                container_status
+                    .state
+                    .as_ref()
+                    .and_then(|state| state.waiting.as_ref())
+                    .and_then(|waiting| waiting.reason.as_deref())
+                    .map_or(false, |reason| reason.eq("CrashLoopBackOff"))
+            })
+        })
+}
+
+/// This function returns 'true' only if the Pod has its Ready status.condition value set to true.
+pub(crate) fn pod_is_ready(pod: &Pod) -> bool {
     let not_ready_warning = |pod_name: &String, namespace: &String| {
         warn!(
             "Couldn't verify the ready condition of Pod '{}' in namespace '{}' to be true",
             pod_name, namespace
         );
     };
-    for pod in pod_list.into_iter() {
-        match &pod
-            .status
-            .as_ref()
-            .and_then(|status| status.conditions.as_ref())
-        {
-            Some(conditions) => {
-                for condition in *conditions {
-                    if condition.type_.eq("Ready") {
-                        if condition.status.eq("True") {
-                            let pod_name = pod.name_any();
-                            info!(pod.name = %pod_name, "Pod is Ready");
-                            break;
-                        }
-                        not_ready_warning(&pod.name_any(), &pod.namespace().unwrap_or_default());
-                        return false;
-                    } else {
-                        continue;
+    match &pod
+        .status
+        .as_ref()
+        .and_then(|status| status.conditions.as_ref())
+    {
+        Some(conditions) => {
+            for condition in *conditions {
+                if condition.type_.eq("Ready") {
+                    if condition.status.eq("True") {
+                        let pod_name = pod.name_any();
+                        info!(pod.name = %pod_name, "Pod is Ready");
+                        break;
                     }
+                    not_ready_warning(&pod.name_any(), &pod.namespace().unwrap_or_default());
+                    return false;
+                } else {
+                    continue;
                 }
             }
-            None => {
-                not_ready_warning(&pod.name_any(), &pod.namespace().unwrap_or_default());
-                return false;
-            }
+        }
+        None => {
+            not_ready_warning(&pod.name_any(), &pod.namespace().unwrap_or_default());
+            return false;
         }
     }
     true
 }
 
+/// This function returns 'true' if the Pod is bound to the Node, or if the Pod's required
+/// NodeAffinity selects the Node by name (as it does for DaemonSet Pods which are yet to be
+/// scheduled).
+pub(crate) fn pod_targets_node(pod: &Pod, node_name: &str) -> bool {
+    let Some(spec) = pod.spec.as_ref() else {
+        return false;
+    };
+
+    if let Some(pod_node_name) = spec.node_name.as_ref() {
+        return pod_node_name.eq(node_name);
+    }
+
+    pod_affinity_selects_node(pod, node_name)
+}
+
+/// This function returns 'true' if the Pod's required NodeAffinity selects the Node by name, as it
+/// does for DaemonSet Pods. This is regardless of the Node the Pod is bound to, if any.
+pub(crate) fn pod_affinity_selects_node(pod: &Pod, node_name: &str) -> bool {
+    let Some(spec) = pod.spec.as_ref() else {
+        return false;
+    };
+
+    spec.affinity
+        .as_ref()
+        .and_then(|affinity| affinity.node_affinity.as_ref())
+        .and_then(|node_affinity| {
+            node_affinity
+                .required_during_scheduling_ignored_during_execution
+                .as_ref()
+        })
+        .map_or(false, |node_selector| {
+            node_selector.node_selector_terms.iter().any(|term| {
+                term.match_fields.iter().flatten().any(|requirement| {
+                    requirement.key.eq("metadata.name")
+                        && requirement
+                            .values
+                            .iter()
+                            .flatten()
+                            .any(|value| value.eq(node_name))
+                })
+            })
+        })
+}
+
+/// This function returns 'true' only if the Pod has its PodScheduled status.condition value set
+/// to true.
+pub(crate) fn pod_is_scheduled(pod: &Pod) -> bool {
+    pod.status
+        .as_ref()
+        .and_then(|status| status.conditions.as_ref())
+        .map_or(false, |conditions| {
+            conditions
+                .iter()
+                .any(|condition| condition.type_.eq("PodScheduled") && condition.status.eq("True"))
+        })
+}
+
+/// This returns the scheduler's message for a Pod which could not be scheduled because none of
+/// the Nodes have enough of the resources it requests, e.g. '0/3 nodes are available: 1
+/// Insufficient hugepages-2Mi.'.
+pub(crate) fn pod_insufficient_resources_reason(pod: &Pod) -> Option<String> {
+    pod.status
+        .as_ref()
+        .and_then(|status| status.conditions.as_ref())?
+        .iter()
+        .find(|condition| {
+            condition.type_.eq("PodScheduled")
+                && condition.status.eq("False")
+                && condition
+                    .reason
+                    .as_ref()
+                    .map_or(false, |reason| reason.eq("Unschedulable"))
+        })
+        .and_then(|condition| condition.message.clone())
+        .filter(|message| message.contains("Insufficient"))
+}
+
+/// These are the keys of the taints which the DaemonSet controller adds tolerations for, to all
+/// of the DaemonSet Pods.
+const DAEMONSET_TOLERATED_TAINT_KEYS: [&str; 7] = [
+    "node.kubernetes.io/not-ready",
+    "node.kubernetes.io/unreachable",
+    "node.kubernetes.io/disk-pressure",
+    "node.kubernetes.io/memory-pressure",
+    "node.kubernetes.io/pid-pressure",
+    "node.kubernetes.io/unschedulable",
+    "node.kubernetes.io/network-unavailable",
+];
+
+/// This simulates the scheduler's node filters for a DaemonSet Pod with the PodSpec, on the Node.
+/// It returns the reason the Pod would not be scheduled onto the Node, if any. The taints and
+/// tolerations, the nodeSelector and the required node affinity are considered. Resource requests
+/// are not.
+pub(crate) fn pod_spec_unschedulable_reason(spec: &PodSpec, node: &Node) -> Option<String> {
+    let node_labels = node.labels();
+
+    // Taints with the NoSchedule and NoExecute effects repel the Pods which do not tolerate them.
+    let tolerations = spec.tolerations.as_deref().unwrap_or_default();
+    let untolerated_taint = node
+        .spec
+        .as_ref()
+        .and_then(|spec| spec.taints.as_ref())
+        .into_iter()
+        .flatten()
+        .filter(|taint| taint.effect.eq("NoSchedule") || taint.effect.eq("NoExecute"))
+        .filter(|taint| !DAEMONSET_TOLERATED_TAINT_KEYS.contains(&taint.key.as_str()))
+        .find(|taint| {
+            !tolerations
+                .iter()
+                .any(|toleration| toleration_tolerates(toleration, taint))
+        });
+    if let Some(taint) = untolerated_taint {
+        return Some(format!(
+            "untolerated taint {}={}:{}",
+            taint.key,
+            taint.value.as_deref().unwrap_or_default(),
+            taint.effect
+        ));
+    }
+
+    // Each of the nodeSelector's labels must be on the Node.
+    if let Some((key, value)) = spec
+        .node_selector
+        .iter()
+        .flatten()
+        .find(|(key, value)| node_labels.get(*key).ne(&Some(*value)))
+    {
+        return Some(format!(
+            "node label {key}={value} from the nodeSelector is absent"
+        ));
+    }
+
+    // Any one of the required node affinity's terms must match the Node.
+    let required_terms = spec
+        .affinity
+        .as_ref()
+        .and_then(|affinity| affinity.node_affinity.as_ref())
+        .and_then(|node_affinity| {
+            node_affinity
+                .required_during_scheduling_ignored_during_execution
+                .as_ref()
+        })
+        .map(|node_selector| node_selector.node_selector_terms.as_slice())
+        .unwrap_or_default();
+    let node_name = node.name_any();
+    let term_matches = |requirements: Option<&Vec<NodeSelectorRequirement>>,
+                        value_of: &dyn Fn(&str) -> Option<String>| {
+        requirements
+            .into_iter()
+            .flatten()
+            .all(|requirement| node_selector_requirement_matches(requirement, value_of))
+    };
+    if !required_terms.is_empty()
+        && !required_terms.iter().any(|term| {
+            term_matches(term.match_expressions.as_ref(), &|key: &str| {
+                node_labels.get(key).cloned()
+            }) && term_matches(term.match_fields.as_ref(), &|key: &str| {
+                key.eq("metadata.name").then(|| node_name.clone())
+            })
+        })
+    {
+        return Some("none of the required node affinity terms match the node".to_string());
+    }
+
+    None
+}
+
+/// This is a predicate which is true if the toleration tolerates the taint.
+fn toleration_tolerates(toleration: &Toleration, taint: &Taint) -> bool {
+    let effect_matches = toleration
+        .effect
+        .as_ref()
+        .map_or(true, |effect| effect.is_empty() || effect.eq(&taint.effect));
+    let exists = toleration
+        .operator
+        .as_ref()
+        .map_or(false, |operator| operator.eq("Exists"));
+    let key_matches = match toleration.key.as_deref() {
+        None | Some("") => exists,
+        Some(key) => key.eq(&taint.key),
+    };
+    let value_matches = exists
+        || toleration.value.as_deref().unwrap_or_default()
+            == taint.value.as_deref().unwrap_or_default();
+
+    effect_matches && key_matches && value_matches
+}
+
+/// This is a predicate which is true if the label selector matches the labels, i.e. if all of its
+/// matchLabels and matchExpressions are satisfied. An empty label selector matches all labels.
+pub(crate) fn label_selector_matches(
+    selector: &LabelSelector,
+    labels: &BTreeMap<String, String>,
+) -> bool {
+    let labels_match = selector
+        .match_labels
+        .iter()
+        .flatten()
+        .all(|(key, value)| labels.get(key) == Some(value));
+
+    let expressions_match = selector
+        .match_expressions
+        .iter()
+        .flatten()
+        .all(|requirement| {
+            let value = labels.get(requirement.key.as_str());
+            let values = requirement.values.as_deref().unwrap_or_default();
+            match requirement.operator.as_str() {
+                "In" => value.map_or(false, |value| values.contains(value)),
+                "NotIn" => value.map_or(true, |value| !values.contains(value)),
+                "Exists" => value.is_some(),
+                "DoesNotExist" => value.is_none(),
+                _ => false,
+            }
+        });
+
+    labels_match && expressions_match
+}
+
+/// This is a predicate which is true if the Node's value for the requirement's key satisfies the
+/// requirement.
+fn node_selector_requirement_matches(
+    requirement: &NodeSelectorRequirement,
+    value_of: &dyn Fn(&str) -> Option<String>,
+) -> bool {
+    let value = value_of(requirement.key.as_str());
+    let values = requirement.values.as_deref().unwrap_or_default();
+    let compare_with = |ordering: std::cmp::Ordering| {
+        let value = value.as_ref().and_then(|value| value.parse::<i64>().ok());
+        let operand = values
+            .first()
+            .and_then(|operand| operand.parse::<i64>().ok());
+        match (value, operand) {
+            (Some(value), Some(operand)) => value.cmp(&operand).eq(&ordering),
+            _ => false,
+        }
+    };
+
+    match requirement.operator.as_str() {
+        "In" => value.as_ref().map_or(false, |value| values.contains(value)),
+        "NotIn" => value.as_ref().map_or(true, |value| !values.contains(value)),
+        "Exists" => value.is_some(),
+        "DoesNotExist" => value.is_none(),
+        "Gt" => compare_with(std::cmp::Ordering::Greater),
+        "Lt" => compare_with(std::cmp::Ordering::Less),
+        _ => false,
+    }
+}
+
 /// Checks to see if all of io-engine Pods are already upgraded to the version of the local helm
 /// chart.
 pub(crate) async fn data_plane_is_upgraded(
@@ -191,3 +715,179 @@ pub(crate) async fn data_plane_is_upgraded(
 
     Ok(true)
 }
+
+/// Compares the io-engine version reported by a storage Node against the upgrade target version.
+/// The reported version may carry a 'v' prefix (as do the container image tags).
+pub(crate) fn io_engine_version_matches(reported_version: &str, to_version: &str) -> bool {
+    let reported_version = reported_version.trim().trim_start_matches('v');
+    match (Version::parse(reported_version), Version::parse(to_version)) {
+        (Ok(reported), Ok(target)) => reported.eq(&target),
+        _ => reported_version.eq(to_version.trim_start_matches('v')),
+    }
+}
+
+/// Returns the number of volumes which are Degraded.
+pub(crate) async fn degraded_volume_count(
+    rest_client: &RestClientSet,
+    list_parallelism: usize,
+) -> Result<usize> {
+    Ok(list_unhealthy_volumes(rest_client, &[], list_parallelism)
+        .await?
+        .iter()
+        .filter(|volume| matches!(volume.state.status, VolumeStatus::Degraded))
+        .count())
+}
+
+/// Returns the UUIDs of the volumes with replicas on the storage Node, which would be left with
+/// fewer than 'min' healthy replicas while the Node is drained. A replica is healthy if it is
+/// Online, and the Online replicas on the Node are counted as going away.
+pub(crate) async fn volumes_below_min_healthy_replicas(
+    node_id: &str,
+    min: usize,
+    rest_client: &RestClientSet,
+    list_parallelism: usize,
+) -> Result<Vec<String>> {
+    Ok(list_volumes(rest_client, list_parallelism)
+        .await?
+        .into_iter()
+        .filter(|volume| {
+            let replicas = volume.state.replica_topology.values();
+            let hosts_replica = replicas
+                .clone()
+                .any(|replica| replica.node.as_deref().eq(&Some(node_id)));
+            let healthy_elsewhere = replicas
+                .filter(|replica| {
+                    matches!(replica.state, ReplicaState::Online)
+                        && replica.node.as_deref().ne(&Some(node_id))
+                })
+                .count();
+            hosts_replica && healthy_elsewhere < min
+        })
+        .map(|volume| volume.spec.uuid.to_string())
+        .collect())
+}
+
+/// Returns the UUIDs of the volumes whose targets are on the storage Node, i.e. the volumes which
+/// are yet to move off of the Node while it is drained.
+pub(crate) async fn volumes_targeted_on_node(
+    node_id: &str,
+    rest_client: &RestClientSet,
+    list_parallelism: usize,
+) -> Result<Vec<String>> {
+    Ok(list_volumes(rest_client, list_parallelism)
+        .await?
+        .into_iter()
+        .filter(|volume| {
+            volume
+                .state
+                .target
+                .as_ref()
+                .map_or(false, |target| target.node.eq(node_id))
+        })
+        .map(|volume| volume.spec.uuid.to_string())
+        .collect())
+}
+
+/// Returns the UUIDs of the volumes with replicas or targets on each of the storage Nodes, keyed
+/// by the Node ID.
+pub(crate) async fn storage_node_volumes(
+    rest_client: &RestClientSet,
+    list_parallelism: usize,
+) -> Result<HashMap<String, HashSet<String>>> {
+    let mut node_volumes: HashMap<String, HashSet<String>> = HashMap::new();
+    for volume in list_volumes(rest_client, list_parallelism).await? {
+        let volume_uuid = volume.spec.uuid.to_string();
+        let replica_nodes = volume
+            .state
+            .replica_topology
+            .values()
+            .filter_map(|replica| replica.node.clone());
+        let target_node = volume.state.target.map(|target| target.node);
+        for node_id in replica_nodes.chain(target_node) {
+            node_volumes
+                .entry(node_id)
+                .or_default()
+                .insert(volume_uuid.clone());
+        }
+    }
+    Ok(node_volumes)
+}
+
+/// Returns the tag of the image of the named container of the Pod, e.g. 'v2.4.0' of
+/// 'docker.io/openebs/mayastor-io-engine:v2.4.0'. This is None if the image has no tag.
+pub(crate) fn pod_container_image_tag(pod: &Pod, container: &str) -> Option<String> {
+    let image = pod
+        .spec
+        .as_ref()?
+        .containers
+        .iter()
+        .find(|pod_container| pod_container.name.eq(container))?
+        .image
+        .as_deref()?;
+    // The digest, if any, is not a part of the tag.
+    let image = image.split('@').next()?;
+    let (_, tag) = image.rsplit_once(':')?;
+    (!tag.contains('/')).then(|| tag.to_string())
+}
+
+/// Converts a Kubernetes resource Quantity (e.g. '2Gi', '512Mi', '1G', '1048576') to a whole number
+/// of units. Fractional quantities and quantities with unknown suffixes are not supported.
+pub(crate) fn quantity_to_units(quantity: &Quantity) -> Option<u64> {
+    let quantity = quantity.0.trim();
+    let suffix_start = quantity
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(quantity.len());
+    let (number, suffix) = quantity.split_at(suffix_start);
+    let multiplier: u64 = match suffix {
+        "" => 1,
+        "Ki" => 1 << 10,
+        "Mi" => 1 << 20,
+        "Gi" => 1 << 30,
+        "Ti" => 1 << 40,
+        "k" => 1_000,
+        "M" => 1_000_000,
+        "G" => 1_000_000_000,
+        "T" => 1_000_000_000_000,
+        _ => return None,
+    };
+    number.parse::<u64>().ok()?.checked_mul(multiplier)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use k8s_openapi::apimachinery::pkg::apis::meta::v1::LabelSelectorRequirement;
+
+    #[test]
+    fn label_selector_matches_labels_and_expressions() {
+        let labels = BTreeMap::from([
+            ("app".to_string(), "io-engine".to_string()),
+            ("openebs.io/release".to_string(), "mayastor".to_string()),
+        ]);
+        let requirement = |key: &str, operator: &str, values: &[&str]| LabelSelectorRequirement {
+            key: key.to_string(),
+            operator: operator.to_string(),
+            values: Some(values.iter().map(ToString::to_string).collect()),
+        };
+
+        let selector = LabelSelector {
+            match_labels: Some(BTreeMap::from([(
+                "app".to_string(),
+                "io-engine".to_string(),
+            )])),
+            match_expressions: Some(vec![
+                requirement("openebs.io/release", "In", &["mayastor"]),
+                requirement("openebs.io/canary", "DoesNotExist", &[]),
+            ]),
+        };
+        assert!(label_selector_matches(&selector, &labels));
+
+        let selector = LabelSelector {
+            match_labels: None,
+            match_expressions: Some(vec![requirement("app", "NotIn", &["io-engine"])]),
+        };
+        assert!(!label_selector_matches(&selector, &labels));
+
+        assert!(label_selector_matches(&LabelSelector::default(), &labels));
+    }
+}
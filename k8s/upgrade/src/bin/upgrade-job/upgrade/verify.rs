@@ -0,0 +1,174 @@
+use crate::{
+    common::{
+        constants::{CSI_DRIVER_NAME, IO_ENGINE_LABEL},
+        error::{
+            ListPersistentVolumeClaims, ListPodsWithLabel, PostUpgradeUnhealthy,
+            PvcBindingUnhealthy, Result,
+        },
+        kube_client::KubeClientSet,
+        rest_client::RestClientSet,
+    },
+    opts::PvcScope,
+    upgrade::utils::{list_volumes, pod_is_ready, replica_rebuild_count},
+};
+use k8s_openapi::api::core::v1::PersistentVolumeClaim;
+use kube::{api::ListParams, ResourceExt};
+use openapi::models::VolumeStatus;
+use snafu::ResultExt;
+use std::collections::HashMap;
+use tracing::{info, warn};
+
+/// These are the annotations which carry the name of the provisioner of a PersistentVolumeClaim.
+const STORAGE_PROVISIONER_ANNOTATIONS: [&str; 2] = [
+    "volume.kubernetes.io/storage-provisioner",
+    "volume.beta.kubernetes.io/storage-provisioner",
+];
+
+/// This maps '<namespace>/<name>' of the PersistentVolumeClaims provisioned by the storage CSI
+/// driver to their .status.phase.
+pub(crate) type PvcBindingSnapshot = HashMap<String, String>;
+
+/// Lists the PersistentVolumeClaims provisioned by the storage CSI driver, within the scope, and
+/// records their .status.phase.
+pub(crate) async fn pvc_binding_snapshot(
+    k8s_client: &KubeClientSet,
+    namespace: &str,
+    scope: PvcScope,
+) -> Result<PvcBindingSnapshot> {
+    let (pvcs_api, scope_name) = match scope {
+        PvcScope::Namespace => (k8s_client.pvcs_api(), format!("namespace {namespace}")),
+        PvcScope::Cluster => (
+            k8s_client.all_namespaces_pvcs_api(),
+            "all namespaces".to_string(),
+        ),
+    };
+
+    let pvcs = pvcs_api
+        .list(&ListParams::default())
+        .await
+        .context(ListPersistentVolumeClaims { scope: scope_name })?;
+
+    Ok(pvcs
+        .into_iter()
+        .filter(is_provisioned_by_csi_driver)
+        .map(|pvc| {
+            let phase = pvc
+                .status
+                .as_ref()
+                .and_then(|status| status.phase.clone())
+                .unwrap_or_default();
+            (
+                format!("{}/{}", pvc.namespace().unwrap_or_default(), pvc.name_any()),
+                phase,
+            )
+        })
+        .collect())
+}
+
+/// Verifies that the PersistentVolumeClaims which were Bound before the upgrade have not moved to
+/// the Pending or Lost phases. Failures are only logged, unless 'strict' is set.
+pub(crate) async fn verify_pvc_binding(
+    k8s_client: &KubeClientSet,
+    namespace: &str,
+    scope: PvcScope,
+    before: &PvcBindingSnapshot,
+    strict: bool,
+) -> Result<()> {
+    let after = pvc_binding_snapshot(k8s_client, namespace, scope).await?;
+
+    let mut unhealthy_pvcs: Vec<String> = after
+        .iter()
+        .filter(|(pvc, phase)| {
+            matches!(phase.as_str(), "Pending" | "Lost")
+                && before
+                    .get(pvc.as_str())
+                    .map_or(false, |previous_phase| previous_phase.eq("Bound"))
+        })
+        .map(|(pvc, phase)| format!("{pvc} ({phase})"))
+        .collect();
+    unhealthy_pvcs.sort();
+
+    if unhealthy_pvcs.is_empty() {
+        info!(
+            count = after.len(),
+            "Verified binding health of PersistentVolumeClaims"
+        );
+        return Ok(());
+    }
+
+    if strict {
+        return PvcBindingUnhealthy {
+            pvcs: unhealthy_pvcs,
+        }
+        .fail();
+    }
+
+    warn!(
+        pvcs = ?unhealthy_pvcs,
+        "PersistentVolumeClaims are no longer Bound after the upgrade"
+    );
+    Ok(())
+}
+
+/// Verifies that all of the volumes are Online with no rebuilds in progress, and that all of the
+/// io-engine Pods are Ready. The io-engine Pods on the Nodes which the upgrade skipped are not
+/// verified.
+pub(crate) async fn verify_cluster_health(
+    k8s_client: &KubeClientSet,
+    rest_client: &RestClientSet,
+    namespace: &str,
+    list_parallelism: usize,
+    skipped_nodes: &[String],
+) -> Result<()> {
+    let mut unhealthy_volumes: Vec<String> = Vec::new();
+    for volume in list_volumes(rest_client, list_parallelism).await? {
+        let uuid = volume.spec.uuid.to_string();
+        if !matches!(volume.state.status, VolumeStatus::Online) {
+            unhealthy_volumes.push(format!("{uuid} ({:?})", volume.state.status));
+        } else if replica_rebuild_count(volume).await > 0 {
+            unhealthy_volumes.push(format!("{uuid} (Rebuilding)"));
+        }
+    }
+
+    let io_engine_listparams = ListParams::default().labels(IO_ENGINE_LABEL);
+    let mut unready_pods: Vec<String> = k8s_client
+        .list_pods(&io_engine_listparams)
+        .await
+        .context(ListPodsWithLabel {
+            label: IO_ENGINE_LABEL.to_string(),
+            namespace: namespace.to_string(),
+        })?
+        .into_iter()
+        .filter(|pod| {
+            !pod.spec
+                .as_ref()
+                .and_then(|spec| spec.node_name.as_ref())
+                .map_or(false, |node_name| skipped_nodes.contains(node_name))
+        })
+        .filter(|pod| !pod_is_ready(pod))
+        .map(|pod| pod.name_any())
+        .collect();
+    unready_pods.sort();
+
+    if unhealthy_volumes.is_empty() && unready_pods.is_empty() {
+        info!("Verified the health of the volumes and the io-engine Pods");
+        return Ok(());
+    }
+
+    PostUpgradeUnhealthy {
+        volumes: unhealthy_volumes,
+        pods: unready_pods,
+    }
+    .fail()
+}
+
+/// This is a predicate to pick out the PersistentVolumeClaims provisioned by the storage CSI
+/// driver.
+fn is_provisioned_by_csi_driver(pvc: &PersistentVolumeClaim) -> bool {
+    let annotations = pvc.annotations();
+    STORAGE_PROVISIONER_ANNOTATIONS.iter().any(|key| {
+        annotations
+            .get(*key)
+            .map_or(false, |provisioner| provisioner.eq(CSI_DRIVER_NAME))
+    })
+}
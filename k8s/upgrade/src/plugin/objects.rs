@@ -165,7 +165,7 @@ pub(crate) fn upgrade_job_cluster_role(
             },
             PolicyRule {
                 api_groups: Some(vec!["batch"].into_vec()),
-                resources: Some(vec!["cronjobs"].into_vec()),
+                resources: Some(vec!["cronjobs", "jobs"].into_vec()),
                 verbs: vec!["create", "list", "delete", "get", "patch"].into_vec(),
                 ..Default::default()
             },
@@ -214,6 +214,12 @@ pub(crate) fn upgrade_job_cluster_role(
                 verbs: vec!["create", "list", "delete", "get", "patch"].into_vec(),
                 ..Default::default()
             },
+            PolicyRule {
+                api_groups: Some(vec!["coordination.k8s.io"].into_vec()),
+                resources: Some(vec!["leases"].into_vec()),
+                verbs: vec!["create", "delete", "get", "patch", "update"].into_vec(),
+                ..Default::default()
+            },
         ]),
         ..Default::default()
     }